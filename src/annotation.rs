@@ -0,0 +1,232 @@
+/// annotation.rs contains the annotation component and its non system functions.
+/// Annotations are review marks living in world space alongside the TwImages: text labels,
+/// arrows, rectangles and freehand strokes.
+/// An annotation attached to an image store its points in pixel coord of this image, so it follows
+/// the image moves, rotation, scale, flip and crop. A free annotation store its points in world coord.
+use amethyst::core::{Transform, math::Point3};
+use amethyst::ecs::prelude::{Component, DenseVecStorage, Entity};
+use serde::{Serialize, Deserialize};
+
+use crate::image::TwImage;
+
+
+pub const ANNOTATION_COLOR: [f32; 4] = [1.0, 0.8, 0.0, 1.0];
+
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub enum TwAnnotationKind {
+    Text(String),
+    Arrow,
+    Rectangle,
+    Stroke,
+}
+
+
+/// annotation component, points are in pixel coord of the attached image or in world coord
+/// if the annotation is free.
+/// Text use the first point as anchor, arrow and rectangle use the two first points.
+#[derive(PartialEq, Debug, Clone)]
+pub struct TwAnnotation {
+    pub kind: TwAnnotationKind,
+    pub points: Vec<(f32, f32)>,
+    pub color: [f32; 4],
+    pub attached_to: Option<Entity>,
+}
+
+impl TwAnnotation {
+    pub fn new(kind: TwAnnotationKind, attached_to: Option<Entity>) -> Self {
+        Self {
+            kind,
+            points: Vec::new(),
+            color: ANNOTATION_COLOR,
+            attached_to,
+        }
+    }
+
+    /// world positions of the annotation points.
+    pub fn world_points(&self, attached: Option<(&TwImage, &Transform)>) -> Vec<(f32, f32)> {
+        self.points.iter().map(|p| to_world(*p, attached)).collect()
+    }
+
+    /// world segments to draw, the rectangle corners are computed in annotation space
+    /// to follow the attached image rotation.
+    pub fn world_segments(&self, attached: Option<(&TwImage, &Transform)>) -> Vec<((f32, f32), (f32, f32))> {
+        let mut segments = Vec::new();
+        match self.kind {
+            TwAnnotationKind::Text(_) => {}
+            TwAnnotationKind::Arrow => {
+                let points = self.world_points(attached);
+                if points.len() >= 2 {
+                    let (start, end) = (points[0], points[1]);
+                    let angle = (start.1 - end.1).atan2(start.0 - end.0);
+                    let length = ((end.0 - start.0).powi(2) + (end.1 - start.1).powi(2)).sqrt();
+                    // TODO: arrow head size as settings
+                    let head = (length * 0.2).min(40.0);
+                    let spread = 25.0_f32.to_radians();
+                    segments.push((start, end));
+                    segments.push((end, (end.0 + head * (angle + spread).cos(), end.1 + head * (angle + spread).sin())));
+                    segments.push((end, (end.0 + head * (angle - spread).cos(), end.1 + head * (angle - spread).sin())));
+                }
+            }
+            TwAnnotationKind::Rectangle => {
+                if self.points.len() >= 2 {
+                    let (a, b) = (self.points[0], self.points[1]);
+                    let corners = [a, (b.0, a.1), b, (a.0, b.1)].iter()
+                        .map(|p| to_world(*p, attached)).collect::<Vec<_>>();
+                    for i in 0..corners.len() {
+                        segments.push((corners[i], corners[(i + 1) % corners.len()]));
+                    }
+                }
+            }
+            TwAnnotationKind::Stroke => {
+                let points = self.world_points(attached);
+                for pair in points.windows(2) {
+                    segments.push((pair[0], pair[1]));
+                }
+            }
+        }
+        segments
+    }
+
+    /// smallest world distance between a world position and the annotation, used to pick the
+    /// annotation under the mouse.
+    pub fn distance(&self, world_position: (f32, f32), attached: Option<(&TwImage, &Transform)>) -> f32 {
+        match self.kind {
+            TwAnnotationKind::Text(_) => {
+                self.world_points(attached).first().map_or(std::f32::MAX, |anchor| {
+                    ((world_position.0 - anchor.0).powi(2) + (world_position.1 - anchor.1).powi(2)).sqrt()
+                })
+            }
+            _ => {
+                self.world_segments(attached).iter()
+                    .map(|(a, b)| segment_distance(world_position, *a, *b))
+                    .fold(std::f32::MAX, f32::min)
+            }
+        }
+    }
+}
+
+impl Component for TwAnnotation {
+    type Storage = DenseVecStorage<Self>;
+}
+
+
+/// convert a world position to the annotation space, pixel coord of the attached image
+/// or world coord if free. None if the attached image transform is not invertible, like a zero scale.
+pub fn to_annotation_space(world_position: (f32, f32), attached: Option<(&TwImage, &Transform)>) -> Option<(f32, f32)> {
+    match attached {
+        Some((tw_image, transform)) => {
+            let inverse = transform.matrix().try_inverse()?;
+            let local = inverse.transform_point(&Point3::new(world_position.0, world_position.1, 0.0));
+            Some(tw_image.local_to_pixel((local.x, local.y)))
+        }
+        None => Some(world_position)
+    }
+}
+
+
+/// convert a position of the annotation space to world coord.
+pub fn to_world(position: (f32, f32), attached: Option<(&TwImage, &Transform)>) -> (f32, f32) {
+    match attached {
+        Some((tw_image, transform)) => {
+            let local = tw_image.pixel_to_local(position);
+            let world = transform.matrix().transform_point(&Point3::new(local.0, local.1, 0.0));
+            (world.x, world.y)
+        }
+        None => position
+    }
+}
+
+
+/// distance between a point and a segment.
+fn segment_distance(p: (f32, f32), a: (f32, f32), b: (f32, f32)) -> f32 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let length_sq = dx * dx + dy * dy;
+    let t = if length_sq == 0.0 { 0.0 } else { (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / length_sq).max(0.0).min(1.0) };
+    let (x, y) = (a.0 + t * dx, a.1 + t * dy);
+    ((p.0 - x).powi(2) + (p.1 - y).powi(2)).sqrt()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use amethyst::core::math::Vector3;
+    use crate::image::TwCrop;
+
+    // 400x200 image centered on 100, 50
+    fn placed(rotation: f32, scale: f32) -> Transform {
+        let mut transform = Transform::default();
+        transform.set_translation_xyz(100.0, 50.0, 0.0);
+        transform.set_rotation_z_axis(rotation.to_radians());
+        transform.set_scale(Vector3::new(scale, scale, 1.0));
+        transform
+    }
+
+    fn assert_near(value: (f32, f32), expected: (f32, f32)) {
+        assert!((value.0 - expected.0).abs() < 0.01 && (value.1 - expected.1).abs() < 0.01,
+                "{:?} expected {:?}", value, expected);
+    }
+
+    #[test]
+    fn pixel_follows_the_rotation_and_the_flip() {
+        let mut tw_image = TwImage::new(400, 200, "fixture.png");
+        let transform = placed(90.0, 1.0);
+        // top right pixel, local (200, 100) turned a quarter counterclockwise
+        assert_near(to_world((400.0, 0.0), Some((&tw_image, &transform))), (0.0, 250.0));
+        tw_image.flip_horizontal = true;
+        assert_near(to_world((400.0, 0.0), Some((&tw_image, &transform))), (0.0, -150.0));
+        tw_image.flip_vertical = true;
+        assert_near(to_world((400.0, 0.0), Some((&tw_image, &transform))), (200.0, -150.0));
+    }
+
+    #[test]
+    fn annotation_space_round_trip_on_a_rotated_flipped_cropped_image() {
+        let mut tw_image = TwImage::new(400, 200, "fixture.png");
+        tw_image.flip_horizontal = true;
+        tw_image.flip_vertical = true;
+        tw_image.crop = Some(TwCrop { x: 100, y: 50, width: 200, height: 100 });
+        let transform = placed(30.0, 2.0);
+        for pixel in &[(100.0, 50.0), (300.0, 150.0), (180.5, 77.25), (0.0, 0.0)] {
+            let world = to_world(*pixel, Some((&tw_image, &transform)));
+            assert_near(to_annotation_space(world, Some((&tw_image, &transform))).unwrap(), *pixel);
+        }
+        // the crop center is the sprite center, on the image position
+        assert_near(to_world((200.0, 100.0), Some((&tw_image, &transform))), (100.0, 50.0));
+        assert_near(tw_image.local_to_pixel(tw_image.pixel_to_local((120.0, 60.0))), (120.0, 60.0));
+    }
+
+    #[test]
+    fn free_annotation_stays_in_world_space() {
+        assert_eq!(to_world((12.0, -3.0), None), (12.0, -3.0));
+        assert_eq!(to_annotation_space((12.0, -3.0), None), Some((12.0, -3.0)));
+    }
+
+    #[test]
+    fn no_annotation_space_for_a_zero_scale() {
+        let tw_image = TwImage::new(400, 200, "fixture.png");
+        assert_eq!(to_annotation_space((0.0, 0.0), Some((&tw_image, &placed(0.0, 0.0)))), None);
+    }
+
+    #[test]
+    fn hit_testing_on_a_rotated_flipped_image() {
+        let mut tw_image = TwImage::new(400, 200, "fixture.png");
+        tw_image.flip_horizontal = true;
+        let transform = placed(90.0, 1.0);
+        let attached = Some((&tw_image, &transform));
+        // the rotated image covers x 0 to 200 and y -150 to 250
+        let mut frame = TwAnnotation::new(TwAnnotationKind::Rectangle, None);
+        frame.points = vec![(0.0, 0.0), (400.0, 200.0)];
+        assert!(frame.distance((0.0, 250.0), attached) < 0.01);
+        assert!((frame.distance((100.0, 50.0), attached) - 100.0).abs() < 0.01);
+        assert!((frame.distance((250.0, 50.0), attached) - 50.0).abs() < 0.01);
+        // from the left edge of the image to its center, the flipped and rotated left edge is on top
+        let mut arrow = TwAnnotation::new(TwAnnotationKind::Arrow, None);
+        arrow.points = vec![(0.0, 100.0), (200.0, 100.0)];
+        assert!(arrow.distance((100.0, 250.0), attached) < 0.01);
+        assert!((arrow.distance((100.0, -150.0), attached) - 200.0).abs() < 0.01);
+        let mut note = TwAnnotation::new(TwAnnotationKind::Text("note".to_owned()), None);
+        note.points = vec![(400.0, 0.0)];
+        assert!((note.distance((0.0, -140.0), attached) - 10.0).abs() < 0.01);
+    }
+}
@@ -0,0 +1,282 @@
+/// annotation_system.rs contains all the annotation related systems, creation with the tools,
+/// drawing and edition.
+use amethyst::{
+    core::{SystemDesc, Transform, math::{Point3, Vector2}},
+    derive::SystemDesc,
+    ecs::{Join, System, SystemData, World, WriteStorage},
+    ecs::prelude::*,
+    input::VirtualKeyCode,
+    renderer::{Camera, debug_drawing::DebugLines, palette::Srgba,
+               rendy::wsi::winit::Window},
+};
+use amethyst_imgui::{
+    imgui,
+    imgui::{im_str, Condition, ImString},
+};
+
+use std::time::Duration;
+
+use crate::annotation::{TwAnnotation, TwAnnotationKind, to_annotation_space};
+use crate::camera::world_to_screen;
+use crate::image::TwImage;
+use crate::inputshandler::TwInputsHandler;
+
+
+/// the attached image and its transform if both are present.
+fn attached_pair<'a>(tw_image: Option<&'a TwImage>, transform: Option<&'a Transform>) -> Option<(&'a TwImage, &'a Transform)> {
+    match (tw_image, transform) {
+        (Some(tw_image), Some(transform)) => Some((tw_image, transform)),
+        _ => None
+    }
+}
+
+
+fn draw_segments(debug_lines: &mut DebugLines, annotation: &TwAnnotation, attached: Option<(&TwImage, &Transform)>) {
+    let color = Srgba::new(annotation.color[0], annotation.color[1], annotation.color[2], annotation.color[3]);
+    for (a, b) in annotation.world_segments(attached) {
+        // TODO: annotation z as settings, lines are drawn a bit above the images
+        debug_lines.draw_line([a.0, a.1, 0.5].into(), [b.0, b.1, 0.5].into(), color);
+    }
+}
+
+
+#[derive(SystemDesc, Default)]
+pub struct TwAnnotationCreateSystem {
+    current: Option<TwAnnotation>,
+}
+/// create the annotations by drawing on the board, the annotation is attached to the active image
+/// if any, so it moves with it.
+/// A + click and drag draw an arrow
+/// B + click and drag draw a rectangle
+/// P + click and drag draw a freehand stroke
+impl<'s> System<'s> for TwAnnotationCreateSystem {
+    type SystemData = (WriteExpect<'s, TwInputsHandler>,
+                       ReadStorage<'s, TwImage>,
+                       ReadStorage<'s, Transform>,
+                       Write<'s, DebugLines>,
+                       Write<'s, LazyUpdate>,
+                       Entities<'s>);
+    fn run(&mut self, (
+        mut tw_in,
+        tw_images,
+        transforms,
+        mut debug_lines,
+        world,
+        entities,
+    ): Self::SystemData) {
+        let tool = if tw_in.keys_pressed.len() == 1 {
+            match tw_in.keys_pressed[0] {
+                VirtualKeyCode::A => Some(TwAnnotationKind::Arrow),
+                VirtualKeyCode::B => Some(TwAnnotationKind::Rectangle),
+                VirtualKeyCode::P => Some(TwAnnotationKind::Stroke),
+                _ => None
+            }
+        } else {
+            None
+        };
+        if let (Some(kind), Some(_button), Some(world_position)) = (tool, tw_in.mouse_button_pressed, tw_in.mouse_world_position) {
+            tw_in.active_busy = true;
+            if self.current.is_none() {
+                self.current = Some(TwAnnotation::new(kind, tw_in.active_entities.last().cloned()));
+            }
+            let annotation = self.current.as_mut().unwrap();
+            let attached = attached_pair(annotation.attached_to.and_then(|e| tw_images.get(e)),
+                                         annotation.attached_to.and_then(|e| transforms.get(e)));
+            let position = match to_annotation_space(world_position, attached) {
+                Some(position) => position,
+                None => return
+            };
+            match annotation.kind {
+                TwAnnotationKind::Stroke => {
+                    if annotation.points.last() != Some(&position) {
+                        annotation.points.push(position);
+                    }
+                }
+                _ => {
+                    if annotation.points.is_empty() {
+                        annotation.points.push(position);
+                        annotation.points.push(position);
+                    }
+                    annotation.points[1] = position;
+                }
+            }
+            draw_segments(&mut debug_lines, annotation, attached);
+        } else if let Some(annotation) = self.current.take() {
+            tw_in.active_busy = false;
+            if annotation.points.len() >= 2 && annotation.points.first() != annotation.points.last() {
+                debug!("Annotation {:?} is created", annotation.kind);
+                world.create_entity(&*entities)
+                    .with(annotation)
+                    .build();
+            }
+        }
+    }
+}
+
+
+#[derive(SystemDesc, Default)]
+pub struct TwAnnotationDrawSystem;
+/// draw all the annotations, lines are sent to the DebugLines resource and text labels are drawn
+/// as imgui windows placed at the screen position of their anchor.
+impl<'s> System<'s> for TwAnnotationDrawSystem {
+    type SystemData = (ReadStorage<'s, TwAnnotation>,
+                       ReadStorage<'s, TwImage>,
+                       ReadStorage<'s, Transform>,
+                       ReadStorage<'s, Camera>,
+                       ReadExpect<'s, Window>,
+                       Write<'s, DebugLines>,
+                       Entities<'s>);
+    fn run(&mut self, (
+        annotations,
+        tw_images,
+        transforms,
+        cameras,
+        window,
+        mut debug_lines,
+        entities,
+    ): Self::SystemData) {
+        let (camera, cam_transform) = (&cameras, &transforms).join().next().unwrap();
+        let win_size = window.get_inner_size().unwrap();
+        let diag = Vector2::new(win_size.width as f32, win_size.height as f32);
+        for (annotation, entity) in (&annotations, &*entities).join() {
+            let attached = attached_pair(annotation.attached_to.and_then(|e| tw_images.get(e)),
+                                         annotation.attached_to.and_then(|e| transforms.get(e)));
+            // the attached image is not loaded yet
+            if annotation.attached_to.is_some() && attached.is_none() { continue }
+            draw_segments(&mut debug_lines, annotation, attached);
+            if let TwAnnotationKind::Text(text) = &annotation.kind {
+                if let Some(anchor) = annotation.world_points(attached).first() {
+                    let screen = world_to_screen(camera, Point3::new(anchor.0, anchor.1, 0.0), diag, cam_transform);
+                    let label = ImString::new(format!("##annotation{}", entity.id()));
+                    amethyst_imgui::with(|ui| {
+                        imgui::Window::new(&label)
+                            .title_bar(false)
+                            .resizable(false)
+                            .movable(false)
+                            .always_auto_resize(true)
+                            .position([screen.x, screen.y], Condition::Always)
+                            .build(ui, || {
+                                ui.text_colored(annotation.color, text);
+                            });
+                    });
+                }
+            }
+        }
+    }
+}
+
+
+#[derive(SystemDesc, Default)]
+pub struct TwAnnotationEditSystem {
+    editing: Option<Entity>,
+    text: ImString,
+}
+/// create, edit and delete the annotations.
+/// N key create a text note under the mouse, attached to the active image if any
+/// E key open the editor of the annotation under the mouse
+/// X key delete the annotation under the mouse
+/// The editor is closed with escape.
+impl<'s> System<'s> for TwAnnotationEditSystem {
+    type SystemData = (WriteExpect<'s, TwInputsHandler>,
+                       WriteStorage<'s, TwAnnotation>,
+                       ReadStorage<'s, TwImage>,
+                       ReadStorage<'s, Transform>,
+                       ReadStorage<'s, Camera>,
+                       Write<'s, LazyUpdate>,
+                       Entities<'s>);
+    fn run(&mut self, (
+        mut tw_in,
+        mut annotations,
+        tw_images,
+        transforms,
+        cameras,
+        world,
+        entities,
+    ): Self::SystemData) {
+        // get the annotation under the mouse, the tolerance follow the camera distance
+        // TODO: picking tolerance as settings
+        let (_camera, cam_transform) = (&cameras, &transforms).join().next().unwrap();
+        let mut hovered = None;
+        let mut nearest = cam_transform.translation().z * 0.01;
+        if let Some(mouse_world_position) = tw_in.mouse_world_position {
+            for (annotation, entity) in (&annotations, &*entities).join() {
+                let attached = attached_pair(annotation.attached_to.and_then(|e| tw_images.get(e)),
+                                             annotation.attached_to.and_then(|e| transforms.get(e)));
+                let distance = annotation.distance(mouse_world_position, attached);
+                if distance < nearest {
+                    nearest = distance;
+                    hovered = Some(entity);
+                }
+            }
+        }
+        if tw_in.keys_pressed.len() == 1 && Duration::from_millis(500) <= tw_in.stopwatch.elapsed() {
+            match tw_in.keys_pressed[0] {
+                VirtualKeyCode::N => {
+                    if let Some(world_position) = tw_in.mouse_world_position {
+                        let attached_to = tw_in.active_entities.last().cloned();
+                        let attached = attached_pair(attached_to.and_then(|e| tw_images.get(e)),
+                                                     attached_to.and_then(|e| transforms.get(e)));
+                        if let Some(position) = to_annotation_space(world_position, attached) {
+                            let mut annotation = TwAnnotation::new(TwAnnotationKind::Text("Note".to_owned()), attached_to);
+                            annotation.points.push(position);
+                            self.editing = Some(world.create_entity(&*entities).with(annotation).build());
+                            self.text = ImString::new("Note");
+                            debug!("Text annotation is created");
+                        }
+                    }
+                    tw_in.stopwatch.restart();
+                }
+                VirtualKeyCode::E => {
+                    if let Some(entity) = hovered {
+                        if let TwAnnotationKind::Text(text) = &annotations.get(entity).unwrap().kind {
+                            self.text = ImString::new(text.clone());
+                        }
+                        self.editing = Some(entity);
+                    }
+                    tw_in.stopwatch.restart();
+                }
+                VirtualKeyCode::X => {
+                    if let Some(entity) = hovered {
+                        entities.delete(entity).expect("Failed to delete annotation entity.");
+                        if self.editing == Some(entity) { self.editing = None }
+                        debug!("Annotation is deleted");
+                    }
+                    tw_in.stopwatch.restart();
+                }
+                _ => {}
+            }
+        }
+        if tw_in.keys_pressed.contains(&VirtualKeyCode::Escape) { self.editing = None }
+        if let Some(entity) = self.editing {
+            if !entities.is_alive(entity) {
+                self.editing = None;
+            } else if let Some(annotation) = annotations.get_mut(entity) {
+                let text = &mut self.text;
+                let mut delete = false;
+                let mut close = false;
+                amethyst_imgui::with(|ui| {
+                    imgui::Window::new(im_str!("Annotation"))
+                        .always_auto_resize(true)
+                        .position([ui.io().mouse_pos[0], ui.io().mouse_pos[1]], Condition::Appearing)
+                        .build(ui, || {
+                            if let TwAnnotationKind::Text(_) = annotation.kind {
+                                if text.capacity_with_nul() < 256 { text.reserve(256) }
+                                if ui.input_text(im_str!("Text"), text).build() {
+                                    annotation.kind = TwAnnotationKind::Text(text.to_str().to_owned());
+                                }
+                            }
+                            imgui::ColorEdit::new(im_str!("Color"), &mut annotation.color).build(ui);
+                            delete = ui.button(im_str!("Delete"), [0.0, 0.0]);
+                            ui.same_line(0.0);
+                            close = ui.button(im_str!("Close"), [0.0, 0.0]);
+                        });
+                });
+                if delete {
+                    entities.delete(entity).expect("Failed to delete annotation entity.");
+                    debug!("Annotation is deleted");
+                }
+                if delete || close { self.editing = None }
+            }
+        }
+    }
+}
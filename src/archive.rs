@@ -0,0 +1,198 @@
+/// archive.rs contains the inputs that are not plain files, zip and tar archives and stdin.
+/// They are written in the Tower cache directory, so the rest of Tower see them as regular
+/// directories and images:
+/// - an archive is extracted once in a new thread, like the downloads, in a directory named from its
+///   path and modification time. The images of the sub directories are flattened, a/b/c.png is
+///   extracted as a_b_c.png, and a name used twice gets a number, a_b_c-2.png
+/// - the image read from stdin is written in a directory of the current process
+use flate2::read::GzDecoder;
+
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::UNIX_EPOCH;
+
+use crate::utils::is_valid_file;
+
+
+pub const STDIN_INPUT: &str = "-";
+
+
+/// state of the extractions shared with the threads, the directories of the extracted archives
+/// wait in done until TwImageDroppedSystem opens them.
+#[derive(Debug, Default)]
+pub struct TwExtractions {
+    pub running: HashSet<PathBuf>,
+    pub done: Vec<String>,
+}
+
+
+fn tower_cache_dir(name: &str) -> io::Result<PathBuf> {
+    let cache = dirs::cache_dir().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No cache directory"))?;
+    Ok(cache.join("tower").join(name))
+}
+
+
+pub fn is_archive_file(file: &Path) -> bool {
+    let name = file.file_name().map_or(String::new(), |n| n.to_string_lossy().to_lowercase());
+    file.is_file() && [".zip", ".tar", ".tar.gz", ".tgz"].iter().any(|ext| name.ends_with(ext))
+}
+
+
+/// file name of an archive entry in the flattened directory, None if the entry is not an image.
+/// a/b_c.png and a_b/c.png are both flattened as a_b_c.png, a name already used gets a number.
+/// The names are compared without case for the case insensitive file systems.
+fn flattened_name(entry: &Path, used: &mut HashSet<String>) -> Option<String> {
+    if !is_valid_file(entry) { return None }
+    let parts = entry.components()
+        .filter_map(|c| match c {
+            std::path::Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+            _ => None
+        })
+        .collect::<Vec<_>>();
+    if parts.is_empty() { return None }
+    let name = parts.join("_");
+    let mut unique = name.clone();
+    let mut count = 1;
+    while !used.insert(unique.to_lowercase()) {
+        count += 1;
+        unique = match name.rfind('.') {
+            Some(dot) => format!("{}-{}{}", &name[..dot], count, &name[dot..]),
+            None => format!("{}-{}", name, count),
+        };
+    }
+    Some(unique)
+}
+
+
+fn extract_zip(archive: &Path, dir: &Path) -> io::Result<()> {
+    let mut zip = zip::ZipArchive::new(fs::File::open(archive)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    let mut used = HashSet::new();
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        if let Some(name) = flattened_name(&entry.sanitized_name(), &mut used) {
+            io::copy(&mut entry, &mut fs::File::create(dir.join(name))?)?;
+        }
+    }
+    Ok(())
+}
+
+
+fn extract_tar<R: Read>(reader: R, dir: &Path) -> io::Result<()> {
+    let mut tar = tar::Archive::new(reader);
+    let mut used = HashSet::new();
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        if let Some(name) = flattened_name(&path, &mut used) {
+            io::copy(&mut entry, &mut fs::File::create(dir.join(name))?)?;
+        }
+    }
+    Ok(())
+}
+
+
+/// extract the images of an archive in the cache, an archive already extracted is reused.
+/// Returns the directory of the images.
+pub fn extract_archive(archive: &Path) -> io::Result<PathBuf> {
+    let mtime = fs::metadata(archive)?.modified()?.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let absolute = fs::canonicalize(archive)?;
+    let key = format!("{:x}", md5::compute(format!("{}:{}", absolute.to_string_lossy(), mtime)));
+    let stem = archive.file_name().unwrap().to_string_lossy().into_owned();
+    let dir = tower_cache_dir("archives")?.join(format!("{}-{}", stem, &key[..8]));
+    if dir.is_dir() {
+        debug!("Archive {:?} already extracted in {:?}", archive, &dir);
+        return Ok(dir)
+    }
+    // extracted aside then renamed, an interrupted extraction is not reused
+    let partial = dir.with_extension("partial");
+    if partial.exists() { fs::remove_dir_all(&partial)? }
+    fs::create_dir_all(&partial)?;
+    let name = stem.to_lowercase();
+    if name.ends_with(".zip") {
+        extract_zip(archive, &partial)?;
+    } else if name.ends_with(".tar") {
+        extract_tar(fs::File::open(archive)?, &partial)?;
+    } else {
+        extract_tar(GzDecoder::new(fs::File::open(archive)?), &partial)?;
+    }
+    fs::rename(&partial, &dir)?;
+    info!("Archive {:?} extracted in {:?}", archive, &dir);
+    Ok(dir)
+}
+
+
+/// read an image from stdin and write it in the cache, the extension is guessed from the data.
+pub fn read_stdin_image() -> io::Result<PathBuf> {
+    let mut data = Vec::new();
+    io::stdin().read_to_end(&mut data)?;
+    let format = image::guess_format(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    let extension = match format {
+        image::ImageFormat::PNG => "png",
+        image::ImageFormat::JPEG => "jpg",
+        image::ImageFormat::GIF => "gif",
+        image::ImageFormat::BMP => "bmp",
+        image::ImageFormat::TGA => "tga",
+        image::ImageFormat::TIFF => "tiff",
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Unsupported format {:?}", format)))
+    };
+    let dir = tower_cache_dir("stdin")?.join(std::process::id().to_string());
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("stdin.{}", extension));
+    fs::write(&path, data)?;
+    info!("Image read from stdin written in {:?}", &path);
+    Ok(path)
+}
+
+
+/// start the extraction of an archive in a new thread, the directory is pushed in extractions
+/// once done. An archive being extracted is not started again.
+pub fn start_extraction(archive: &Path, extractions: &Arc<Mutex<TwExtractions>>) {
+    if !extractions.lock().unwrap().running.insert(archive.to_path_buf()) { return }
+    let archive = archive.to_path_buf();
+    let extractions = Arc::clone(extractions);
+    debug!("A new thread spawned to extract {:?}", &archive);
+    thread::spawn(move || {
+        let result = extract_archive(&archive);
+        let mut extractions = extractions.lock().unwrap();
+        extractions.running.remove(&archive);
+        match result {
+            Ok(dir) => extractions.done.push(dir.to_string_lossy().into_owned()),
+            Err(e) => error!("Failed to extract {:?}: {:?}", &archive, e)
+        }
+    });
+}
+
+
+/// turn an input into a path Tower can load, - read stdin. The archives are extracted later by
+/// start_extraction, other inputs are returned as they are, the input is also kept on failure.
+pub fn resolve_input(input: &str) -> String {
+    if input != STDIN_INPUT { return input.to_owned() }
+    match read_stdin_image() {
+        Ok(path) => path.to_string_lossy().into_owned(),
+        Err(e) => {
+            error!("Failed to open {:?}: {:?}", input, e);
+            input.to_owned()
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flattened_names_are_unique() {
+        let mut used = HashSet::new();
+        let names = ["a/b_c.png", "a_b/c.png", "A_B_C.png", "a/notes.txt", "d/e.jpg"].iter()
+            .map(|entry| flattened_name(Path::new(entry), &mut used))
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec![Some("a_b_c.png".to_owned()), Some("a_b_c-2.png".to_owned()),
+                               Some("A_B_C-3.png".to_owned()), None, Some("d_e.jpg".to_owned())]);
+    }
+}
@@ -1,5 +1,9 @@
 use structopt::StructOpt;
 use std::iter::Iterator;
+use std::path::PathBuf;
+
+use crate::utils::{parse_hex_color, TwSortBy, TwFileFilter};
+use crate::image::TwFilter;
 
 #[derive(Debug, StructOpt)]
 /// tower is a new kind of image viewer software, adjust your images like a mood-board,
@@ -7,9 +11,70 @@ use std::iter::Iterator;
 #[structopt(name = "Tower", about = "Tower is an open source project made with rust and powered by Amethyst\n\
 It's a multi image viewer that allow to manipulate and adjust, like a mood board, several images at same time.")]
 pub struct Opt {
-    /// Input images
+    /// Input images, directories, .tower scenes, zip / tar archives, http(s) URLs or - to read an image from stdin
     #[structopt(required=false, multiple=true, number_of_values=1)]
     pub inputs: Vec<String>,
+    /// Texture filtering of the images when zoomed out: nearest or linear
+    #[structopt(long="filter", default_value="linear")]
+    pub filter: TwFilter,
+    /// Order of the directory navigation: name, mtime, size or dimensions
+    #[structopt(long="sort", default_value="name")]
+    pub sort: TwSortBy,
+    /// Reverse the order of the directory navigation
+    #[structopt(long="reverse")]
+    pub reverse: bool,
+    /// Only navigate the files whose name match the glob pattern, like "*_diffuse.*"
+    #[structopt(long="glob", parse(try_from_str = TwFileFilter::glob))]
+    pub glob: Option<TwFileFilter>,
+    /// Only navigate the files whose name match the regular expression
+    #[structopt(long="regex", parse(try_from_str = TwFileFilter::regex), conflicts_with="glob")]
+    pub regex: Option<TwFileFilter>,
+    /// List the sub directories of the working directories
+    #[structopt(short="r", long="recursive")]
+    pub recursive: bool,
+    /// Count of sub directory levels listed, implies --recursive
+    #[structopt(long="depth")]
+    pub depth: Option<usize>,
+    /// Send the inputs to the running Tower instead of opening a new window
+    #[structopt(long="single-instance")]
+    pub single_instance: bool,
+    /// Listen for JSON-RPC requests on a local socket, to drive Tower from other tools
+    #[structopt(long="remote")]
+    pub remote: bool,
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    /// Generate a contact sheet image of a directory or of images, without window
+    #[structopt(name = "contact-sheet")]
+    ContactSheet(ContactSheetOpt),
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ContactSheetOpt {
+    /// Input directories or images
+    #[structopt(required=true, multiple=true)]
+    pub inputs: Vec<String>,
+    /// Output image path, the format is given by the extension
+    #[structopt(short="o", long="output", parse(from_os_str))]
+    pub output: PathBuf,
+    /// Columns count, a square grid by default
+    #[structopt(short="c", long="columns")]
+    pub columns: Option<usize>,
+    /// Largest side of each thumbnail in pixels
+    #[structopt(short="s", long="thumbnail-size", default_value="256")]
+    pub thumbnail_size: u32,
+    /// Space between thumbnails in pixels
+    #[structopt(long="offset", default_value="10")]
+    pub offset: u32,
+    /// Background color as RRGGBB or RRGGBBAA
+    #[structopt(short="b", long="background", default_value="1a1a1a", parse(try_from_str = parse_hex_color))]
+    pub background: [f32; 4],
+    /// Don't write the file names under the thumbnails
+    #[structopt(long="no-captions")]
+    pub no_captions: bool,
 }
 
 
@@ -15,6 +15,7 @@ use std::time::Duration;
 use crate::camera::{TwCamera};
 use crate::inputshandler::{TwInputsHandler};
 use crate::tower::{TowerData};
+use crate::remote::{TwRemoteActions, TwFitTarget};
 
 use std::cmp::max;
 
@@ -198,20 +199,24 @@ pub struct CameraFitNavigationSystem;
 /// F key fit the active image
 /// Shift + F fit the whole images scene, a bounding box is calculated with all image sizes and position
 /// to get the center and maxi size of height or width.
+/// The remote control fit_camera request does the same.
 impl<'s> System<'s> for CameraFitNavigationSystem {
     type SystemData = (Write<'s, TwInputsHandler>,
                        ReadStorage<'s, TwCamera>,
                        WriteStorage<'s, Transform>,
-                       Read<'s, TowerData>);
+                       Read<'s, TowerData>,
+                       Write<'s, TwRemoteActions>);
 
     fn run(&mut self, (
         tw_in,
         tw_cameras,
         mut transforms,
         tw_data,
+        mut remote_actions,
     ): Self::SystemData) {
-        if tw_in.keys_pressed.contains(&VirtualKeyCode::F) && tw_in.keys_pressed.len() == 1 {
-            if Duration::from_millis(500) <= tw_in.stopwatch.elapsed() {
+        let remote_fit = remote_actions.fit.take();
+        if (tw_in.keys_pressed.contains(&VirtualKeyCode::F) && tw_in.keys_pressed.len() == 1) || remote_fit == Some(TwFitTarget::Active) {
+            if Duration::from_millis(500) <= tw_in.stopwatch.elapsed() || remote_fit.is_some() {
                 let (_, transform) = (&tw_cameras, &mut transforms).join().next().unwrap();
                 transform.set_translation_x((tw_data.active_rect.min.x + tw_data.active_rect.max.x) / 2.0);
                 transform.set_translation_y((tw_data.active_rect.min.y + tw_data.active_rect.max.y) / 2.0);
@@ -219,8 +224,8 @@ impl<'s> System<'s> for CameraFitNavigationSystem {
                 debug!("Camera is moved with {:?} to fit the active image", transform);
             }
         }
-        if tw_in.keys_pressed.contains(&VirtualKeyCode::F) && tw_in.keys_pressed.contains(&VirtualKeyCode::LShift) && tw_in.keys_pressed.len() == 2 {
-            if Duration::from_millis(500) <= tw_in.stopwatch.elapsed() {
+        if (tw_in.keys_pressed.contains(&VirtualKeyCode::F) && tw_in.keys_pressed.contains(&VirtualKeyCode::LShift) && tw_in.keys_pressed.len() == 2) || remote_fit == Some(TwFitTarget::All) {
+            if Duration::from_millis(500) <= tw_in.stopwatch.elapsed() || remote_fit.is_some() {
                 let (_, transform) = (&tw_cameras, &mut transforms).join().next().unwrap();
                 transform.set_translation_x(tw_data.scene_middle_point.x);
                 transform.set_translation_y(tw_data.scene_middle_point.y);
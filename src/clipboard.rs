@@ -0,0 +1,44 @@
+/// clipboard.rs contains the non system functions of the clipboard copy and paste.
+/// The pasted images are written as png in a session folder of the Tower data directory, so they
+/// are regular files that can be saved in a scene and opened again later.
+use image::RgbaImage;
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::image::{TwImage, TwPixels};
+
+
+/// folder of the current session, named from the session start time.
+pub fn session_dir(session_start: SystemTime) -> io::Result<PathBuf> {
+    let data = dirs::data_dir().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No data directory"))?;
+    let start = session_start.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    Ok(data.join("tower").join("sessions").join(start.to_string()))
+}
+
+
+/// write an image pasted from the clipboard in the session folder, returns its path.
+pub fn save_pasted_image(session_start: SystemTime, image: &RgbaImage) -> io::Result<PathBuf> {
+    let dir = session_dir(session_start)?;
+    fs::create_dir_all(&dir)?;
+    let count = fs::read_dir(&dir)?.count();
+    let path = dir.join(format!("paste-{:03}.png", count + 1));
+    image.save(&path)?;
+    info!("Pasted image written in {:?}", &path);
+    Ok(path)
+}
+
+
+/// straight alpha rgba pixels of the visible part of an image, as displayed, crop and flips.
+pub fn visible_pixels(tw_image: &TwImage, tw_pixels: &TwPixels) -> RgbaImage {
+    let rect = tw_image.visible_rect();
+    RgbaImage::from_fn(rect.width, rect.height, |x, y| {
+        let x = if tw_image.flip_horizontal { rect.width - 1 - x } else { x };
+        let y = if tw_image.flip_vertical { rect.height - 1 - y } else { y };
+        let rgba = tw_pixels.rgba(rect.x + x, rect.y + y);
+        image::Rgba([(rgba[0] * 255.0).round() as u8, (rgba[1] * 255.0).round() as u8,
+                     (rgba[2] * 255.0).round() as u8, (rgba[3] * 255.0).round() as u8])
+    })
+}
@@ -0,0 +1,120 @@
+/// clipboard_system.rs contains the copy and paste with the system clipboard.
+use amethyst::{
+    core::SystemDesc,
+    derive::SystemDesc,
+    ecs::{Read, System, SystemData, World},
+    ecs::prelude::*,
+    input::VirtualKeyCode,
+};
+use arboard::{Clipboard, ImageData};
+use image::RgbaImage;
+
+use std::borrow::Cow;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use crate::clipboard::{save_pasted_image, visible_pixels};
+use crate::download::is_url;
+use crate::image::TwImage;
+use crate::inputshandler::TwInputsHandler;
+use crate::tower::TowerData;
+
+
+#[derive(SystemDesc)]
+pub struct TwClipboardSystem {
+    session_start: SystemTime,
+}
+
+impl Default for TwClipboardSystem {
+    fn default() -> Self {
+        Self {
+            session_start: SystemTime::now(),
+        }
+    }
+}
+/// copy and paste with the system clipboard.
+/// ctrl + V paste an image under the mouse, the clipboard image is written in the session folder,
+/// a clipboard text is opened if it's an URL or an existing path.
+/// ctrl + C copy the visible pixels of the active image, ctrl + shift + C copy its path.
+/// Pasted images go through TwInputsHandler.last_dropped_file_path like a drag and drop.
+impl<'s> System<'s> for TwClipboardSystem {
+    type SystemData = (WriteExpect<'s, TwInputsHandler>,
+                       ReadStorage<'s, TwImage>,
+                       Read<'s, TowerData>);
+    fn run(&mut self, (
+        mut tw_in,
+        tw_images,
+        tw_data,
+    ): Self::SystemData) {
+        if !tw_in.keys_pressed.contains(&VirtualKeyCode::LControl) { return }
+        if Duration::from_millis(500) > tw_in.stopwatch.elapsed() { return }
+        let shift = tw_in.keys_pressed.contains(&VirtualKeyCode::LShift);
+        if tw_in.keys_pressed.contains(&VirtualKeyCode::V) && tw_in.keys_pressed.len() == 2 {
+            tw_in.stopwatch.restart();
+            let mut clipboard = match Clipboard::new() {
+                Ok(clipboard) => clipboard,
+                Err(e) => {
+                    error!("Failed to open the clipboard: {:?}", e);
+                    return
+                }
+            };
+            if let Ok(data) = clipboard.get_image() {
+                let image = RgbaImage::from_raw(data.width as u32, data.height as u32, data.bytes.into_owned());
+                match image.map(|image| save_pasted_image(self.session_start, &image)) {
+                    Some(Ok(path)) => tw_in.last_dropped_file_path.push(path.to_str().unwrap().to_owned()),
+                    Some(Err(e)) => error!("Failed to write the pasted image: {:?}", e),
+                    None => error!("Invalid clipboard image data"),
+                }
+            } else if let Ok(text) = clipboard.get_text() {
+                let text = text.trim().to_owned();
+                if is_url(&text) || Path::new(&text).exists() {
+                    debug!("Clipboard text {:?} is opened", &text);
+                    tw_in.last_dropped_file_path.push(text);
+                } else {
+                    warn!("Nothing to paste from the clipboard");
+                }
+            }
+        }
+        let copy = tw_in.keys_pressed.contains(&VirtualKeyCode::C)
+            && ((shift && tw_in.keys_pressed.len() == 3) || tw_in.keys_pressed.len() == 2);
+        if copy {
+            tw_in.stopwatch.restart();
+            let tw_image = match tw_in.active_entities.last().and_then(|entity| tw_images.get(*entity)) {
+                Some(tw_image) => tw_image,
+                None => return
+            };
+            let mut clipboard = match Clipboard::new() {
+                Ok(clipboard) => clipboard,
+                Err(e) => {
+                    error!("Failed to open the clipboard: {:?}", e);
+                    return
+                }
+            };
+            if shift {
+                match clipboard.set_text(tw_image.file_name.clone()) {
+                    Ok(_) => info!("Path {:?} copied to the clipboard", &tw_image.file_name),
+                    Err(e) => error!("Failed to copy the path: {:?}", e),
+                }
+            } else {
+                let pixels = match tw_data.cache.try_lock() {
+                    Ok(cache) => cache.get(&tw_image.file_name).map(|(_, tw_pixels)| visible_pixels(tw_image, tw_pixels)),
+                    Err(_e) => None
+                };
+                match pixels {
+                    Some(pixels) => {
+                        let data = ImageData {
+                            width: pixels.width() as usize,
+                            height: pixels.height() as usize,
+                            bytes: Cow::Owned(pixels.into_raw()),
+                        };
+                        match clipboard.set_image(data) {
+                            Ok(_) => info!("Pixels of {:?} copied to the clipboard", &tw_image.file_name),
+                            Err(e) => error!("Failed to copy the pixels: {:?}", e),
+                        }
+                    }
+                    None => warn!("Pixels of {:?} are not in cache yet", &tw_image.file_name)
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,92 @@
+/// contact_sheet.rs contains the headless contact sheet generator, tower contact-sheet subcommand.
+/// The thumbnails are placed with the same grid as TwImageLayoutSystem, composed on the CPU by the
+/// board export and the file names are written under each thumbnail with the small bitmap font of export.rs.
+/// Nothing here needs a window.
+use amethyst::core::Transform;
+use geo::Rect;
+
+use std::path::Path;
+use std::ffi::OsStr;
+
+use crate::args_cli::ContactSheetOpt;
+use crate::export::{TwExportLayer, TwExportSettings, GLYPH_HEIGHT, compose, draw_text, save_export};
+use crate::image::load_thumbnail;
+use crate::layout::grid_layout;
+use crate::utils::{is_valid_file, list_valid_files, TwListOptions};
+
+
+/// get the images to put in the contact sheet, directories are listed in natural order.
+fn collect_files(inputs: &[String]) -> Vec<String> {
+    let mut files = Vec::new();
+    for input in inputs {
+        let path = Path::new(input);
+        if path.is_dir() {
+            let dir_files = list_valid_files(OsStr::new(input), &TwListOptions::default()).into_iter()
+                .map(|f| f.into_string().unwrap());
+            files.extend(dir_files);
+        } else if is_valid_file(path) {
+            files.push(input.clone());
+        } else {
+            warn!("Invalid format for {:?}", input);
+        }
+    }
+    files
+}
+
+
+/// generate the contact sheet image from the subcommand options and write it.
+pub fn contact_sheet(opt: &ContactSheetOpt) -> Result<(), String> {
+    let files = collect_files(&opt.inputs);
+    if files.is_empty() {
+        return Err("No valid image found for the contact sheet".to_owned())
+    }
+    let mut thumbnails = Vec::new();
+    for file in &files {
+        match load_thumbnail(file, opt.thumbnail_size) {
+            Ok(thumbnail) => thumbnails.push(thumbnail),
+            Err(e) => warn!("Failed to load {:?}: {:?}", file, e)
+        }
+    }
+    let caption_scale = if opt.thumbnail_size >= 256 { 2 } else { 1 };
+    let caption_height = if opt.no_captions { 0 } else { (GLYPH_HEIGHT + 4) * caption_scale };
+    let sizes = thumbnails.iter()
+        .map(|(_, pixels)| (pixels.width as f32, pixels.height as f32 + caption_height as f32))
+        .collect::<Vec<_>>();
+    let (grid, cells) = grid_layout(&sizes, opt.columns, opt.offset as f32);
+    // one world unit is one pixel of the sheet, the cells go from the top left corner
+    let offset = opt.offset as f32;
+    let layers = thumbnails.iter().zip(&cells).map(|((tw_image, pixels), (column, row))| {
+        let mut transform = Transform::default();
+        let cell_x = offset + grid.cell_width * *column as f32;
+        let cell_y = offset + grid.cell_height * *row as f32;
+        transform.set_translation_xyz(cell_x + (grid.cell_width - offset) * 0.5,
+                                      -(cell_y + pixels.height as f32 * 0.5), 0.0);
+        // the TwImage keeps the original size, it's moved to the thumbnail size
+        let mut tw_image = tw_image.clone();
+        tw_image.width = pixels.width;
+        tw_image.height = pixels.height;
+        TwExportLayer { tw_image, pixels: pixels.clone(), matrix: transform.matrix() }
+    }).collect::<Vec<_>>();
+    let width = offset + grid.cell_width * grid.columns as f32;
+    let height = offset + grid.cell_height * grid.rows as f32;
+    let rect = Rect::new((0.0, -height), (width, 0.0));
+    let settings = TwExportSettings { rect, width: width as u32, height: height as u32, background: opt.background };
+    let mut sheet = compose(&layers, &[], &settings);
+    if !opt.no_captions {
+        let color = if opt.background[0] + opt.background[1] + opt.background[2] > 1.5 {
+            [0.08, 0.08, 0.08, 1.0]
+        } else {
+            [0.9, 0.9, 0.9, 1.0]
+        };
+        for ((tw_image, pixels), (column, row)) in thumbnails.iter().zip(&cells) {
+            let name = Path::new(&tw_image.file_name).file_name().and_then(|n| n.to_str()).unwrap_or("");
+            let cell_x = (offset + grid.cell_width * *column as f32) as u32;
+            let cell_y = (offset + grid.cell_height * *row as f32) as u32;
+            draw_text(&mut sheet, name, cell_x, cell_y + pixels.height + 2 * caption_scale,
+                      caption_scale, (grid.cell_width - offset) as u32, color);
+        }
+    }
+    save_export(&sheet, &opt.output).map_err(|e| format!("Failed to write {:?}: {:?}", &opt.output, e))?;
+    info!("Contact sheet of {:?} images written in {:?}", thumbnails.len(), &opt.output);
+    Ok(())
+}
@@ -0,0 +1,482 @@
+/// decoder.rs contains the image decoders of Tower, every format is read through the TwDecoder
+/// trait. A new format is added by implementing TwDecoder and listing it in DECODERS, the loading
+/// of the cache, the thumbnails and the directory listing dispatch through this list.
+use exr::prelude::{MetaData, read_first_rgba_layer_from_file};
+use image::{DynamicImage, GenericImageView, RgbaImage};
+use psd::Psd;
+
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use crate::image::TwPixels;
+use crate::texture::{is_texture_header, read_texture_file, read_texture_header};
+use crate::svg::{rasterize_svg, svg_size};
+use crate::web_formats::{avif_size, decode_avif, decode_webp, webp_size};
+
+
+/// samples of a decoded image, interleaved by pixel.
+#[derive(Debug, Clone)]
+pub enum TwSamples {
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+    F32(Vec<f32>),
+}
+
+
+/// pixels of a decoded image, 1 gray, 2 gray alpha, 3 rgb or 4 rgba channels with straight alpha.
+#[derive(Debug, Clone)]
+pub struct TwPixelBuffer {
+    pub width: u32,
+    pub height: u32,
+    pub channels: u8,
+    pub samples: TwSamples,
+}
+
+impl TwPixelBuffer {
+    /// sample of a pixel channel between 0 and 1, float samples are clamped.
+    pub fn sample(&self, index: usize) -> f32 {
+        match &self.samples {
+            TwSamples::U8(samples) => samples[index] as f32 / 255.0,
+            TwSamples::U16(samples) => samples[index] as f32 / 65535.0,
+            TwSamples::F32(samples) => samples[index].max(0.0).min(1.0),
+        }
+    }
+
+    /// straight alpha rgba values between 0 and 1 of the pixel at x, y.
+    pub fn rgba(&self, x: u32, y: u32) -> [f32; 4] {
+        let i = (y as usize * self.width as usize + x as usize) * self.channels as usize;
+        match self.channels {
+            1 => { let g = self.sample(i); [g, g, g, 1.0] }
+            2 => { let g = self.sample(i); [g, g, g, self.sample(i + 1)] }
+            3 => [self.sample(i), self.sample(i + 1), self.sample(i + 2), 1.0],
+            _ => [self.sample(i), self.sample(i + 1), self.sample(i + 2), self.sample(i + 3)],
+        }
+    }
+
+    /// straight alpha rgba 8bit image, used by the thumbnails whatever the decoded depth.
+    pub fn to_rgba(&self) -> RgbaImage {
+        RgbaImage::from_fn(self.width, self.height, |x, y| {
+            let rgba = self.rgba(x, y);
+            image::Rgba([(rgba[0] * 255.0).round() as u8, (rgba[1] * 255.0).round() as u8,
+                         (rgba[2] * 255.0).round() as u8, (rgba[3] * 255.0).round() as u8])
+        })
+    }
+}
+
+
+/// a part of a file decoded on its own, like a layer.
+#[derive(Debug, Clone)]
+pub struct TwSubImage {
+    pub name: String,
+    pub visible: bool,
+}
+
+
+/// a decoder of one or several image formats.
+pub trait TwDecoder: Sync {
+    /// name shown in the logs.
+    fn name(&self) -> &'static str;
+    /// lowercase file extensions of the formats.
+    fn extensions(&self) -> &'static [&'static str];
+    /// true if the first bytes of a file are the signature of the format, used for the files
+    /// without known extension.
+    fn matches_magic(&self, _header: &[u8]) -> bool {
+        false
+    }
+    /// extension of a file matching the signature, for the files saved without a name like the
+    /// downloads. The first extension by default.
+    fn magic_extension(&self, _header: &[u8]) -> &'static str {
+        self.extensions()[0]
+    }
+    /// dimensions of the image, only the header should be read.
+    fn probe(&self, path: &Path) -> io::Result<(u32, u32)>;
+    /// decode the full resolution pixels.
+    fn decode(&self, path: &Path) -> io::Result<TwPixelBuffer>;
+    /// parts of the file that can be composed or opened alone, none by default.
+    fn sub_images(&self, _path: &Path) -> io::Result<Vec<TwSubImage>> {
+        Ok(Vec::new())
+    }
+    /// compose the given sub images, in the order of the file.
+    fn decode_sub_images(&self, path: &Path, _indices: &[usize]) -> io::Result<TwPixelBuffer> {
+        Err(io::Error::new(io::ErrorKind::InvalidInput, format!("No sub image in {:?}", path)))
+    }
+    /// pixels ready to be cached and uploaded, the decoded buffer with its mip chain by default.
+    fn load_pixels(&self, path: &Path) -> io::Result<TwPixels> {
+        Ok(TwPixels::from_buffer(self.decode(path)?).with_mipmaps())
+    }
+}
+
+
+pub fn invalid_data<E: ToString>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+
+/// formats read by the image crate.
+pub struct TwImageCrateDecoder;
+
+impl TwDecoder for TwImageCrateDecoder {
+    fn name(&self) -> &'static str {
+        "image"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["bmp", "gif", "jpeg", "jpg", "png", "tga", "tiff", "tif"]
+    }
+
+    fn matches_magic(&self, header: &[u8]) -> bool {
+        header.starts_with(b"\x89PNG") || header.starts_with(&[0xff, 0xd8, 0xff]) || header.starts_with(b"GIF8")
+            || header.starts_with(b"BM") || header.starts_with(b"II*\0") || header.starts_with(b"MM\0*")
+    }
+
+    // the image crate opens the files from their extension
+    fn magic_extension(&self, header: &[u8]) -> &'static str {
+        if header.starts_with(b"\x89PNG") { "png" }
+        else if header.starts_with(&[0xff, 0xd8, 0xff]) { "jpg" }
+        else if header.starts_with(b"GIF8") { "gif" }
+        else if header.starts_with(b"BM") { "bmp" }
+        else { "tiff" }
+    }
+
+    fn probe(&self, path: &Path) -> io::Result<(u32, u32)> {
+        image::image_dimensions(path).map_err(invalid_data)
+    }
+
+    fn decode(&self, path: &Path) -> io::Result<TwPixelBuffer> {
+        let img = image::open(path).map_err(invalid_data)?;
+        let (width, height) = img.dimensions();
+        let (channels, samples) = match img {
+            DynamicImage::ImageLuma8(buffer) => (1, TwSamples::U8(buffer.into_raw())),
+            DynamicImage::ImageLumaA8(buffer) => (2, TwSamples::U8(buffer.into_raw())),
+            DynamicImage::ImageRgb8(buffer) => (3, TwSamples::U8(buffer.into_raw())),
+            DynamicImage::ImageRgba8(buffer) => (4, TwSamples::U8(buffer.into_raw())),
+            DynamicImage::ImageLuma16(buffer) => (1, TwSamples::U16(buffer.into_raw())),
+            DynamicImage::ImageLumaA16(buffer) => (2, TwSamples::U16(buffer.into_raw())),
+            DynamicImage::ImageRgb16(buffer) => (3, TwSamples::U16(buffer.into_raw())),
+            DynamicImage::ImageRgba16(buffer) => (4, TwSamples::U16(buffer.into_raw())),
+            // bgr orders are swapped to rgb
+            other => (4, TwSamples::U8(other.to_rgba().into_raw())),
+        };
+        Ok(TwPixelBuffer { width, height, channels, samples })
+    }
+}
+
+
+/// GPU texture files, the first face at full resolution is shown. The mip levels of the file are
+/// kept, and the blocks without alpha are uploaded directly.
+pub struct TwTextureDecoder;
+
+impl TwDecoder for TwTextureDecoder {
+    fn name(&self) -> &'static str {
+        "texture"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["dds", "ktx2"]
+    }
+
+    fn matches_magic(&self, header: &[u8]) -> bool {
+        is_texture_header(header)
+    }
+
+    fn magic_extension(&self, header: &[u8]) -> &'static str {
+        if header.starts_with(b"DDS ") { "dds" } else { "ktx2" }
+    }
+
+    fn probe(&self, path: &Path) -> io::Result<(u32, u32)> {
+        read_texture_header(path).map(|texture| (texture.width, texture.height))
+    }
+
+    fn decode(&self, path: &Path) -> io::Result<TwPixelBuffer> {
+        read_texture_file(path)?.decode_surface(0, 0)
+    }
+
+    fn load_pixels(&self, path: &Path) -> io::Result<TwPixels> {
+        read_texture_file(path)?.surface_pixels(0, 0)
+    }
+}
+
+
+/// Photoshop files, the composite is shown and every layer is a sub image. The layers are
+/// decoded at the size of the document, so a pulled out layer keeps its place.
+pub struct TwPsdDecoder;
+
+impl TwPsdDecoder {
+    fn open(path: &Path) -> io::Result<Psd> {
+        Psd::from_bytes(&fs::read(path)?).map_err(invalid_data)
+    }
+}
+
+impl TwDecoder for TwPsdDecoder {
+    fn name(&self) -> &'static str {
+        "psd"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["psd"]
+    }
+
+    fn matches_magic(&self, header: &[u8]) -> bool {
+        header.starts_with(b"8BPS")
+    }
+
+    fn probe(&self, path: &Path) -> io::Result<(u32, u32)> {
+        // the document size follows the signature, the version and the reserved bytes
+        let mut header = [0; 26];
+        File::open(path)?.read_exact(&mut header)?;
+        let height = u32::from_be_bytes([header[14], header[15], header[16], header[17]]);
+        let width = u32::from_be_bytes([header[18], header[19], header[20], header[21]]);
+        Ok((width, height))
+    }
+
+    fn decode(&self, path: &Path) -> io::Result<TwPixelBuffer> {
+        let psd = TwPsdDecoder::open(path)?;
+        Ok(TwPixelBuffer { width: psd.width(), height: psd.height(), channels: 4, samples: TwSamples::U8(psd.rgba()) })
+    }
+
+    fn sub_images(&self, path: &Path) -> io::Result<Vec<TwSubImage>> {
+        let psd = TwPsdDecoder::open(path)?;
+        Ok(psd.layers().iter().map(|layer| TwSubImage { name: layer.name().to_owned(), visible: layer.visible() }).collect())
+    }
+
+    fn decode_sub_images(&self, path: &Path, indices: &[usize]) -> io::Result<TwPixelBuffer> {
+        let psd = TwPsdDecoder::open(path)?;
+        let rgba = psd.flatten_layers_rgba(&|(index, _layer)| indices.contains(&index)).map_err(invalid_data)?;
+        Ok(TwPixelBuffer { width: psd.width(), height: psd.height(), channels: 4, samples: TwSamples::U8(rgba) })
+    }
+}
+
+
+/// vector images, decoded at the document size. The texture is rasterized again at the camera
+/// zoom by the TwSvgRasterSystem.
+pub struct TwSvgDecoder;
+
+impl TwDecoder for TwSvgDecoder {
+    fn name(&self) -> &'static str {
+        "svg"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["svg", "svgz"]
+    }
+
+    fn matches_magic(&self, header: &[u8]) -> bool {
+        header.starts_with(b"<svg") || (header.starts_with(b"<?xml") && header.windows(4).any(|w| w == b"<svg"))
+    }
+
+    fn probe(&self, path: &Path) -> io::Result<(u32, u32)> {
+        svg_size(path)
+    }
+
+    fn decode(&self, path: &Path) -> io::Result<TwPixelBuffer> {
+        rasterize_svg(path, 1.0)
+    }
+}
+
+
+/// WebP lossy, lossless and animated, the first frame of an animation is decoded, the others are
+/// browsed from the frames window, see FramesSystem.
+pub struct TwWebpDecoder;
+
+impl TwDecoder for TwWebpDecoder {
+    fn name(&self) -> &'static str {
+        "webp"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["webp"]
+    }
+
+    fn matches_magic(&self, header: &[u8]) -> bool {
+        header.len() >= 12 && &header[0..4] == b"RIFF" && &header[8..12] == b"WEBP"
+    }
+
+    fn probe(&self, path: &Path) -> io::Result<(u32, u32)> {
+        webp_size(path)
+    }
+
+    fn decode(&self, path: &Path) -> io::Result<TwPixelBuffer> {
+        decode_webp(path, 0)
+    }
+}
+
+
+/// AVIF, the 10 and 12bit images go through the 16bit texture path.
+pub struct TwAvifDecoder;
+
+impl TwDecoder for TwAvifDecoder {
+    fn name(&self) -> &'static str {
+        "avif"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["avif"]
+    }
+
+    fn matches_magic(&self, header: &[u8]) -> bool {
+        header.len() >= 12 && &header[4..8] == b"ftyp" && (&header[8..12] == b"avif" || &header[8..12] == b"avis")
+    }
+
+    fn probe(&self, path: &Path) -> io::Result<(u32, u32)> {
+        avif_size(path)
+    }
+
+    fn decode(&self, path: &Path) -> io::Result<TwPixelBuffer> {
+        decode_avif(path)
+    }
+}
+
+
+/// OpenEXR, the first rgba layer at its largest resolution goes through the float texture path.
+/// The colors of the file are premultiplied, they are divided by the alpha like a straight alpha
+/// image.
+pub struct TwExrDecoder;
+
+impl TwDecoder for TwExrDecoder {
+    fn name(&self) -> &'static str {
+        "exr"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["exr"]
+    }
+
+    fn matches_magic(&self, header: &[u8]) -> bool {
+        header.starts_with(&[0x76, 0x2f, 0x31, 0x01])
+    }
+
+    fn probe(&self, path: &Path) -> io::Result<(u32, u32)> {
+        let meta = MetaData::read_from_file(path, false).map_err(invalid_data)?;
+        let header = meta.headers.first().ok_or_else(|| invalid_data("Exr file without header"))?;
+        Ok((header.layer_size.width() as u32, header.layer_size.height() as u32))
+    }
+
+    fn decode(&self, path: &Path) -> io::Result<TwPixelBuffer> {
+        let image = read_first_rgba_layer_from_file(
+            path,
+            |size, _| (size.width(), vec![0.0; size.width() * size.height() * 4]),
+            |(width, samples): &mut (usize, Vec<f32>), position, (r, g, b, a): (f32, f32, f32, f32)| {
+                let straight = |c: f32| if a > 0.0 { c / a } else { c };
+                let i = (position.y() * *width + position.x()) * 4;
+                samples[i..i + 4].copy_from_slice(&[straight(r), straight(g), straight(b), a]);
+            },
+        ).map_err(invalid_data)?;
+        let size = image.layer_data.size;
+        let (_, samples) = image.layer_data.channel_data.pixels;
+        Ok(TwPixelBuffer { width: size.width() as u32, height: size.height() as u32, channels: 4, samples: TwSamples::F32(samples) })
+    }
+}
+
+
+/// every decoder of Tower, the first one matching a file is used.
+static DECODERS: &[&dyn TwDecoder] = &[&TwImageCrateDecoder, &TwTextureDecoder, &TwPsdDecoder, &TwSvgDecoder,
+                                       &TwWebpDecoder, &TwAvifDecoder, &TwExrDecoder];
+
+
+/// a sub image is opened with the path of its file followed by #index, like image.psd#3.
+pub fn sub_image_path(file: &Path, index: usize) -> String {
+    format!("{}#{}", file.to_string_lossy(), index)
+}
+
+
+/// split a path between the file and the index of the sub image if any.
+pub fn split_sub_image(file: &Path) -> (PathBuf, Option<usize>) {
+    let name = file.to_string_lossy();
+    if let Some(position) = name.rfind('#') {
+        if let Ok(index) = name[position + 1..].parse::<usize>() {
+            return (PathBuf::from(&name[..position]), Some(index))
+        }
+    }
+    (file.to_path_buf(), None)
+}
+
+
+/// decoder of a file from its extension.
+pub fn decoder_for_extension(file: &Path) -> Option<&'static dyn TwDecoder> {
+    let (file, _) = split_sub_image(file);
+    let ext = file.extension()?.to_str()?.to_lowercase();
+    DECODERS.iter().find(|decoder| decoder.extensions().contains(&ext.as_str())).copied()
+}
+
+
+/// decoder of a file from its extension, or from its first bytes if the extension is unknown.
+pub fn find_decoder(file: &Path) -> Option<&'static dyn TwDecoder> {
+    if let Some(decoder) = decoder_for_extension(file) {
+        return Some(decoder)
+    }
+    let (file, _) = split_sub_image(file);
+    let mut header = [0; 32];
+    let read = File::open(&file).and_then(|mut f| f.read(&mut header)).ok()?;
+    DECODERS.iter().find(|decoder| decoder.matches_magic(&header[..read])).copied()
+}
+
+
+fn unsupported(file: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, format!("No decoder for {:?}", file))
+}
+
+
+/// dimensions of an image from its header, a sub image has the size of its file.
+pub fn probe_dimensions(file: &Path) -> io::Result<(u32, u32)> {
+    let decoder = find_decoder(file).ok_or_else(|| unsupported(file))?;
+    decoder.probe(&split_sub_image(file).0)
+}
+
+
+/// decode the full resolution pixels of an image or of a sub image.
+pub fn decode_file(file: &Path) -> io::Result<TwPixelBuffer> {
+    let decoder = find_decoder(file).ok_or_else(|| unsupported(file))?;
+    debug!("{:?} is decoded by the {} decoder", file, decoder.name());
+    match split_sub_image(file) {
+        (file, Some(index)) => decoder.decode_sub_images(&file, &[index]),
+        (file, None) => decoder.decode(&file),
+    }
+}
+
+
+/// sub images of a file, empty if its format has none.
+pub fn list_sub_images(file: &Path) -> io::Result<Vec<TwSubImage>> {
+    find_decoder(file).ok_or_else(|| unsupported(file))?.sub_images(file)
+}
+
+
+/// compose the given sub images of a file.
+pub fn compose_sub_images(file: &Path, indices: &[usize]) -> io::Result<TwPixelBuffer> {
+    find_decoder(file).ok_or_else(|| unsupported(file))?.decode_sub_images(file, indices)
+}
+
+
+/// decode an image as TwPixels ready for the cache.
+pub fn load_pixels(file: &Path) -> io::Result<TwPixels> {
+    let decoder = find_decoder(file).ok_or_else(|| unsupported(file))?;
+    debug!("{:?} is loaded by the {} decoder", file, decoder.name());
+    match split_sub_image(file) {
+        (file, Some(index)) => Ok(TwPixels::from_buffer(decoder.decode_sub_images(&file, &[index])?).with_mipmaps()),
+        (file, None) => decoder.load_pixels(&file),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exr::prelude::write_rgba_file;
+
+    #[test]
+    fn exr_is_decoded_as_straight_float() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pixels.exr");
+        // premultiplied like in the exr files, half transparent red, transparent and over bright
+        let pixels = [(0.25, 0.0, 0.0, 0.5), (0.0, 0.0, 0.0, 0.0), (2.0, 1.0, 0.5, 1.0)];
+        write_rgba_file(&path, 3, 1, |x, _| pixels[x]).unwrap();
+        assert!(TwExrDecoder.matches_magic(&fs::read(&path).unwrap()));
+        assert_eq!(TwExrDecoder.probe(&path).unwrap(), (3, 1));
+        let buffer = TwExrDecoder.decode(&path).unwrap();
+        assert_eq!((buffer.width, buffer.height, buffer.channels), (3, 1, 4));
+        match buffer.samples {
+            TwSamples::F32(samples) => assert_eq!(samples, vec![0.5, 0.0, 0.0, 0.5, 0.0, 0.0, 0.0, 0.0, 2.0, 1.0, 0.5, 1.0]),
+            _ => panic!("Exr samples are not float"),
+        }
+    }
+}
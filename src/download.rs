@@ -0,0 +1,180 @@
+/// download.rs contains the loading of the images from http(s) URLs.
+/// An URL is downloaded in background in the Tower cache directory, then its TwPlaceHolder
+/// continue with the local file like any other image. The file name is the md5 of the URL, so an
+/// URL already downloaded is not downloaded again.
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::utils::is_valid_file;
+use crate::decoder::find_decoder;
+
+
+/// state of a download shared with the loader thread.
+#[derive(Debug, Clone, Default)]
+pub struct TwDownload {
+    pub received: u64,
+    pub total: Option<u64>,
+    pub path: Option<PathBuf>,
+    pub error: Option<String>,
+}
+
+impl TwDownload {
+    /// progress between 0 and 1, None while the size is unknown.
+    pub fn progress(&self) -> Option<f32> {
+        self.total.filter(|total| *total > 0).map(|total| self.received as f32 / total as f32)
+    }
+}
+
+
+pub fn is_url(input: &str) -> bool {
+    let input = input.to_lowercase();
+    input.starts_with("http://") || input.starts_with("https://")
+}
+
+
+fn downloads_dir() -> io::Result<PathBuf> {
+    let cache = dirs::cache_dir().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No cache directory"))?;
+    Ok(cache.join("tower").join("downloads"))
+}
+
+
+/// the already downloaded file of an URL, whatever its extension.
+fn cached_download(dir: &Path, key: &str) -> Option<PathBuf> {
+    fs::read_dir(dir).ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .find(|path| path.file_stem().map_or(false, |stem| stem == key) && is_valid_file(path))
+}
+
+
+/// download an URL in the cache directory, the extension is guessed from the data as the URL may
+/// not have one, with the signatures of the decoders.
+fn download_file(url: &str, dir: &Path, downloads: &Arc<Mutex<HashMap<String, TwDownload>>>) -> io::Result<PathBuf> {
+    let key = format!("{:x}", md5::compute(url));
+    if let Some(path) = cached_download(dir, &key) {
+        debug!("URL {:?} already downloaded in {:?}", url, &path);
+        return Ok(path)
+    }
+    fs::create_dir_all(dir)?;
+    // TODO: download timeouts as settings
+    let response = ureq::get(url).timeout_connect(10_000).timeout_read(30_000).call();
+    if let Some(error) = response.synthetic_error() {
+        return Err(io::Error::new(io::ErrorKind::Other, error.to_string()))
+    }
+    if !response.ok() {
+        return Err(io::Error::new(io::ErrorKind::Other, format!("HTTP status {}", response.status())))
+    }
+    let total = response.header("Content-Length").and_then(|length| length.parse::<u64>().ok());
+    downloads.lock().unwrap().entry(url.to_owned()).or_default().total = total;
+    let partial = dir.join(format!("{}.partial", key));
+    let mut file = fs::File::create(&partial)?;
+    let mut reader = response.into_reader();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let count = reader.read(&mut buffer)?;
+        if count == 0 { break }
+        file.write_all(&buffer[..count])?;
+        downloads.lock().unwrap().entry(url.to_owned()).or_default().received += count as u64;
+    }
+    drop(file);
+    let mut header = [0; 32];
+    let read = fs::File::open(&partial)?.read(&mut header)?;
+    let extension = match find_decoder(&partial) {
+        Some(decoder) => decoder.magic_extension(&header[..read]),
+        None => {
+            fs::remove_file(&partial)?;
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Downloaded data is not a supported image"))
+        }
+    };
+    let path = dir.join(format!("{}.{}", key, extension));
+    fs::rename(&partial, &path)?;
+    info!("URL {:?} downloaded in {:?}", url, &path);
+    Ok(path)
+}
+
+
+/// start the download of an URL in a new thread, the state is updated in downloads.
+/// An URL already in downloads is not started again, except after a failure.
+pub fn start_download(url: &str, downloads: &Arc<Mutex<HashMap<String, TwDownload>>>) {
+    {
+        let mut downloads = downloads.lock().unwrap();
+        if downloads.get(url).map_or(false, |download| download.error.is_none()) { return }
+        downloads.insert(url.to_owned(), TwDownload::default());
+    }
+    let url = url.to_owned();
+    let downloads = Arc::clone(downloads);
+    debug!("A new thread spawned to download {:?}", &url);
+    thread::spawn(move || {
+        let result = downloads_dir().and_then(|dir| download_file(&url, &dir, &downloads));
+        let mut downloads = downloads.lock().unwrap();
+        let download = downloads.entry(url.clone()).or_default();
+        match result {
+            Ok(path) => download.path = Some(path),
+            Err(e) => download.error = Some(e.to_string()),
+        }
+    });
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    // answer a single request with the body
+    fn serve_once(body: Vec<u8>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/image", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut byte = [0; 1];
+            while !request.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap() == 1 {
+                request.push(byte[0]);
+            }
+            write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", body.len()).unwrap();
+            stream.write_all(&body).unwrap();
+        });
+        url
+    }
+
+    fn png() -> Vec<u8> {
+        let mut data = Vec::new();
+        image::DynamicImage::ImageRgba8(image::RgbaImage::new(3, 2))
+            .write_to(&mut data, image::ImageOutputFormat::PNG).unwrap();
+        data
+    }
+
+    #[test]
+    fn download_then_cache_hit() {
+        let dir = tempfile::tempdir().unwrap();
+        let body = png();
+        let url = serve_once(body.clone());
+        let downloads = Arc::new(Mutex::new(HashMap::new()));
+        let path = download_file(&url, dir.path(), &downloads).unwrap();
+        // no extension in the URL, it comes from the png signature
+        assert_eq!(path.extension().unwrap(), "png");
+        assert_eq!(fs::read(&path).unwrap(), body);
+        {
+            let downloads = downloads.lock().unwrap();
+            let download = &downloads[&url];
+            assert_eq!((download.received, download.total), (body.len() as u64, Some(body.len() as u64)));
+            assert_eq!(download.progress(), Some(1.0));
+        }
+        // the server is gone, the second download is read from the cache directory
+        assert_eq!(download_file(&url, dir.path(), &downloads).unwrap(), path);
+    }
+
+    #[test]
+    fn download_of_unknown_data() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = serve_once(b"not an image".to_vec());
+        let downloads = Arc::new(Mutex::new(HashMap::new()));
+        assert_eq!(download_file(&url, dir.path(), &downloads).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+}
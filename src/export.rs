@@ -0,0 +1,429 @@
+/// export.rs contains the board export, the scene or a part of it is composed on the CPU from the
+/// cached pixels and written as a flattened image file.
+/// Nothing here needs a window, so the composition can run headless.
+use amethyst::core::math::{Matrix4, Point3};
+use geo::Rect;
+use image::{RgbaImage, Rgba, DynamicImage};
+use exr::prelude::write_rgba_file;
+
+use std::cmp::Ordering::Equal;
+use std::io;
+use std::path::Path;
+
+use crate::image::{TwImage, TwPixels};
+
+
+/// 5x8 bitmap font of the printable ascii characters, from space to tilde.
+/// each glyph is five columns, the lowest bit is the top row.
+static FONT: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x5F, 0x00, 0x00],
+    [0x00, 0x07, 0x00, 0x07, 0x00],
+    [0x14, 0x7F, 0x14, 0x7F, 0x14],
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12],
+    [0x23, 0x13, 0x08, 0x64, 0x62],
+    [0x36, 0x49, 0x56, 0x20, 0x50],
+    [0x00, 0x08, 0x07, 0x03, 0x00],
+    [0x00, 0x1C, 0x22, 0x41, 0x00],
+    [0x00, 0x41, 0x22, 0x1C, 0x00],
+    [0x2A, 0x1C, 0x7F, 0x1C, 0x2A],
+    [0x08, 0x08, 0x3E, 0x08, 0x08],
+    [0x00, 0x80, 0x70, 0x30, 0x00],
+    [0x08, 0x08, 0x08, 0x08, 0x08],
+    [0x00, 0x00, 0x60, 0x60, 0x00],
+    [0x20, 0x10, 0x08, 0x04, 0x02],
+    [0x3E, 0x51, 0x49, 0x45, 0x3E],
+    [0x00, 0x42, 0x7F, 0x40, 0x00],
+    [0x72, 0x49, 0x49, 0x49, 0x46],
+    [0x21, 0x41, 0x49, 0x4D, 0x33],
+    [0x18, 0x14, 0x12, 0x7F, 0x10],
+    [0x27, 0x45, 0x45, 0x45, 0x39],
+    [0x3C, 0x4A, 0x49, 0x49, 0x31],
+    [0x41, 0x21, 0x11, 0x09, 0x07],
+    [0x36, 0x49, 0x49, 0x49, 0x36],
+    [0x46, 0x49, 0x49, 0x29, 0x1E],
+    [0x00, 0x00, 0x14, 0x00, 0x00],
+    [0x00, 0x40, 0x34, 0x00, 0x00],
+    [0x00, 0x08, 0x14, 0x22, 0x41],
+    [0x14, 0x14, 0x14, 0x14, 0x14],
+    [0x00, 0x41, 0x22, 0x14, 0x08],
+    [0x02, 0x01, 0x59, 0x09, 0x06],
+    [0x3E, 0x41, 0x5D, 0x59, 0x4E],
+    [0x7C, 0x12, 0x11, 0x12, 0x7C],
+    [0x7F, 0x49, 0x49, 0x49, 0x36],
+    [0x3E, 0x41, 0x41, 0x41, 0x22],
+    [0x7F, 0x41, 0x41, 0x41, 0x3E],
+    [0x7F, 0x49, 0x49, 0x49, 0x41],
+    [0x7F, 0x09, 0x09, 0x09, 0x01],
+    [0x3E, 0x41, 0x41, 0x51, 0x73],
+    [0x7F, 0x08, 0x08, 0x08, 0x7F],
+    [0x00, 0x41, 0x7F, 0x41, 0x00],
+    [0x20, 0x40, 0x41, 0x3F, 0x01],
+    [0x7F, 0x08, 0x14, 0x22, 0x41],
+    [0x7F, 0x40, 0x40, 0x40, 0x40],
+    [0x7F, 0x02, 0x1C, 0x02, 0x7F],
+    [0x7F, 0x04, 0x08, 0x10, 0x7F],
+    [0x3E, 0x41, 0x41, 0x41, 0x3E],
+    [0x7F, 0x09, 0x09, 0x09, 0x06],
+    [0x3E, 0x41, 0x51, 0x21, 0x5E],
+    [0x7F, 0x09, 0x19, 0x29, 0x46],
+    [0x26, 0x49, 0x49, 0x49, 0x32],
+    [0x03, 0x01, 0x7F, 0x01, 0x03],
+    [0x3F, 0x40, 0x40, 0x40, 0x3F],
+    [0x1F, 0x20, 0x40, 0x20, 0x1F],
+    [0x3F, 0x40, 0x38, 0x40, 0x3F],
+    [0x63, 0x14, 0x08, 0x14, 0x63],
+    [0x03, 0x04, 0x78, 0x04, 0x03],
+    [0x61, 0x59, 0x49, 0x4D, 0x43],
+    [0x00, 0x7F, 0x41, 0x41, 0x41],
+    [0x02, 0x04, 0x08, 0x10, 0x20],
+    [0x00, 0x41, 0x41, 0x41, 0x7F],
+    [0x04, 0x02, 0x01, 0x02, 0x04],
+    [0x40, 0x40, 0x40, 0x40, 0x40],
+    [0x00, 0x03, 0x07, 0x08, 0x00],
+    [0x20, 0x54, 0x54, 0x78, 0x40],
+    [0x7F, 0x28, 0x44, 0x44, 0x38],
+    [0x38, 0x44, 0x44, 0x44, 0x28],
+    [0x38, 0x44, 0x44, 0x28, 0x7F],
+    [0x38, 0x54, 0x54, 0x54, 0x18],
+    [0x00, 0x08, 0x7E, 0x09, 0x02],
+    [0x18, 0xA4, 0xA4, 0x9C, 0x78],
+    [0x7F, 0x08, 0x04, 0x04, 0x78],
+    [0x00, 0x44, 0x7D, 0x40, 0x00],
+    [0x20, 0x40, 0x40, 0x3D, 0x00],
+    [0x7F, 0x10, 0x28, 0x44, 0x00],
+    [0x00, 0x41, 0x7F, 0x40, 0x00],
+    [0x7C, 0x04, 0x78, 0x04, 0x78],
+    [0x7C, 0x08, 0x04, 0x04, 0x78],
+    [0x38, 0x44, 0x44, 0x44, 0x38],
+    [0xFC, 0x18, 0x24, 0x24, 0x18],
+    [0x18, 0x24, 0x24, 0x18, 0xFC],
+    [0x7C, 0x08, 0x04, 0x04, 0x08],
+    [0x48, 0x54, 0x54, 0x54, 0x24],
+    [0x04, 0x04, 0x3F, 0x44, 0x24],
+    [0x3C, 0x40, 0x40, 0x20, 0x7C],
+    [0x1C, 0x20, 0x40, 0x20, 0x1C],
+    [0x3C, 0x40, 0x30, 0x40, 0x3C],
+    [0x44, 0x28, 0x10, 0x28, 0x44],
+    [0x4C, 0x90, 0x90, 0x90, 0x7C],
+    [0x44, 0x64, 0x54, 0x4C, 0x44],
+    [0x00, 0x08, 0x36, 0x41, 0x00],
+    [0x00, 0x00, 0x77, 0x00, 0x00],
+    [0x00, 0x41, 0x36, 0x08, 0x00],
+    [0x02, 0x01, 0x02, 0x04, 0x02]
+];
+pub const GLYPH_WIDTH: u32 = 6;
+pub const GLYPH_HEIGHT: u32 = 8;
+
+
+/// one image to compose, its TwImage for crop, flip and channels, its pixels and its world matrix.
+#[derive(Debug, Clone)]
+pub struct TwExportLayer {
+    pub tw_image: TwImage,
+    pub pixels: TwPixels,
+    pub matrix: Matrix4<f32>,
+}
+
+
+/// one annotation to draw over the images, its segments and the anchor of its text in world coord.
+#[derive(Debug, Clone)]
+pub struct TwExportAnnotation {
+    pub segments: Vec<((f32, f32), (f32, f32))>,
+    pub text: Option<(String, (f32, f32))>,
+    pub color: [f32; 4],
+}
+
+
+/// the world rectangle to export, the output size in pixels and the background color.
+#[derive(Debug, Clone)]
+pub struct TwExportSettings {
+    pub rect: Rect<f32>,
+    pub width: u32,
+    pub height: u32,
+    pub background: [f32; 4],
+}
+
+impl TwExportSettings {
+    /// the output height follow the ratio of the exported rectangle.
+    pub fn with_width(rect: Rect<f32>, width: u32, background: [f32; 4]) -> Self {
+        let height = (width as f32 * rect.height() / rect.width().max(1.0)).round().max(1.0) as u32;
+        Self { rect, width: width.max(1), height, background }
+    }
+}
+
+
+/// composed board, premultiplied linear rgba pixels row by row from the top left corner.
+#[derive(Debug, Clone)]
+pub struct TwComposition {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[f32; 4]>,
+}
+
+impl TwComposition {
+    /// blend a straight alpha sRGB color over the pixel at x, y, outside pixels are ignored.
+    pub fn blend(&mut self, x: u32, y: u32, color: [f32; 4]) {
+        if x >= self.width || y >= self.height { return }
+        let pixel = &mut self.pixels[(y * self.width + x) as usize];
+        for c in 0..3 {
+            pixel[c] = srgb_to_linear(color[c]) * color[3] + pixel[c] * (1.0 - color[3]);
+        }
+        pixel[3] = color[3] + pixel[3] * (1.0 - color[3]);
+    }
+
+    /// convert to a straight alpha sRGB 8bit image.
+    pub fn to_rgba(&self) -> RgbaImage {
+        let to_u8 = |c: f32| (c * 255.0).round().max(0.0).min(255.0) as u8;
+        RgbaImage::from_fn(self.width, self.height, |x, y| {
+            let color = self.pixels[(y * self.width + x) as usize];
+            let straight = |c: f32| if color[3] > 0.0 { linear_to_srgb(c / color[3]) } else { 0.0 };
+            Rgba([to_u8(straight(color[0])), to_u8(straight(color[1])), to_u8(straight(color[2])), to_u8(color[3])])
+        })
+    }
+}
+
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.003_130_8 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 }
+}
+
+
+/// world corners of the displayed rectangle of an image, the crop if any.
+pub fn image_world_corners(tw_image: &TwImage, matrix: &Matrix4<f32>) -> Vec<(f32, f32)> {
+    let rect = tw_image.visible_rect();
+    let (left, top, right, bottom) = (rect.x, rect.y, rect.x + rect.width, rect.y + rect.height);
+    [(left, top), (right, top), (right, bottom), (left, bottom)].iter().map(|(x, y)| {
+        let local = tw_image.pixel_to_local((*x as f32, *y as f32));
+        let world = matrix.transform_point(&Point3::new(local.0, local.1, 0.0));
+        (world.x, world.y)
+    }).collect()
+}
+
+
+/// compose the layers, sorted by their z, inside the exported rectangle, then draw the annotations
+/// over them.
+/// Each output pixel is mapped back in the space of each layer image, the nearest pixel is taken,
+/// tinted by the TwImage channels the same way as TwImageApplyBlendingSystem does, then blended
+/// over the previous layers in linear space.
+pub fn compose(layers: &[TwExportLayer], annotations: &[TwExportAnnotation], settings: &TwExportSettings) -> TwComposition {
+    let mut sorted = layers.iter()
+        .filter_map(|layer| layer.matrix.try_inverse().map(|inverse| (layer, inverse)))
+        .collect::<Vec<_>>();
+    sorted.sort_by(|a, b| a.0.matrix[(2, 3)].partial_cmp(&b.0.matrix[(2, 3)]).unwrap_or(Equal));
+    let background = [srgb_to_linear(settings.background[0]) * settings.background[3],
+                      srgb_to_linear(settings.background[1]) * settings.background[3],
+                      srgb_to_linear(settings.background[2]) * settings.background[3],
+                      settings.background[3]];
+    let mut composition = TwComposition {
+        width: settings.width,
+        height: settings.height,
+        pixels: Vec::with_capacity(settings.width as usize * settings.height as usize),
+    };
+    for y in 0..settings.height {
+        for x in 0..settings.width {
+            let world_x = settings.rect.min.x + (x as f32 + 0.5) / settings.width as f32 * settings.rect.width();
+            let world_y = settings.rect.max.y - (y as f32 + 0.5) / settings.height as f32 * settings.rect.height();
+            // premultiplied linear color
+            let mut color = background;
+            for (layer, inverse) in &sorted {
+                let local = inverse.transform_point(&Point3::new(world_x, world_y, 0.0));
+                let (px, py) = layer.tw_image.local_to_pixel((local.x, local.y));
+                let rect = layer.tw_image.visible_rect();
+                if px < rect.x as f32 || py < rect.y as f32
+                    || px >= (rect.x + rect.width).min(layer.pixels.width) as f32
+                    || py >= (rect.y + rect.height).min(layer.pixels.height) as f32 {
+                    continue
+                }
+                let source = layer.pixels.rgba(px as u32, py as u32);
+                let tw_image = &layer.tw_image;
+                let alpha = source[3] * tw_image.alpha.powf(1.0 / 2.2);
+                let tinted = [srgb_to_linear(source[0]) * tw_image.red,
+                              srgb_to_linear(source[1]) * tw_image.green,
+                              srgb_to_linear(source[2]) * tw_image.blue];
+                for c in 0..3 {
+                    color[c] = tinted[c] * alpha + color[c] * (1.0 - alpha);
+                }
+                color[3] = alpha + color[3] * (1.0 - alpha);
+            }
+            composition.pixels.push(color);
+        }
+    }
+    // the annotations keep the same size relative to the output as on a 1280 pixels wide window
+    // TODO: annotation line width and text size as settings
+    let size = (settings.width as f32 / 1280.0).max(1.0) * 2.0;
+    let to_output = |p: (f32, f32)| {
+        ((p.0 - settings.rect.min.x) / settings.rect.width().max(std::f32::EPSILON) * settings.width as f32,
+         (settings.rect.max.y - p.1) / settings.rect.height().max(std::f32::EPSILON) * settings.height as f32)
+    };
+    for annotation in annotations {
+        for (a, b) in &annotation.segments {
+            draw_segment(&mut composition, to_output(*a), to_output(*b), size, annotation.color);
+        }
+        if let Some((text, anchor)) = &annotation.text {
+            let (x, y) = to_output(*anchor);
+            if x >= 0.0 && y >= 0.0 {
+                // the text is clipped by the output, not cut
+                draw_text(&mut composition, text, x as u32, y as u32, size.round() as u32, std::u32::MAX, annotation.color);
+            }
+        }
+    }
+    composition
+}
+
+
+/// draw a segment of the given width, the segment is in output pixel coord.
+fn draw_segment(composition: &mut TwComposition, a: (f32, f32), b: (f32, f32), width: f32, color: [f32; 4]) {
+    let half = width * 0.5;
+    let min_x = (a.0.min(b.0) - half).floor().max(0.0) as u32;
+    let max_x = (a.0.max(b.0) + half).ceil().min(composition.width as f32).max(0.0) as u32;
+    let min_y = (a.1.min(b.1) - half).floor().max(0.0) as u32;
+    let max_y = (a.1.max(b.1) + half).ceil().min(composition.height as f32).max(0.0) as u32;
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let length_sq = dx * dx + dy * dy;
+    for y in min_y..max_y {
+        for x in min_x..max_x {
+            let p = (x as f32 + 0.5, y as f32 + 0.5);
+            let t = if length_sq == 0.0 { 0.0 } else { (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / length_sq).max(0.0).min(1.0) };
+            let (nearest_x, nearest_y) = (a.0 + t * dx, a.1 + t * dy);
+            if (p.0 - nearest_x).powi(2) + (p.1 - nearest_y).powi(2) <= half * half {
+                composition.blend(x, y, color);
+            }
+        }
+    }
+}
+
+
+/// draw a text with the bitmap font from its top left corner, the text is cut at max_width pixels.
+pub fn draw_text(composition: &mut TwComposition, text: &str, x: u32, y: u32, scale: u32, max_width: u32, color: [f32; 4]) {
+    let scale = scale.max(1);
+    let max_chars = (max_width / (GLYPH_WIDTH * scale)) as usize;
+    for (i, c) in text.chars().take(max_chars).enumerate() {
+        let glyph = FONT[if (c as u32) >= 32 && (c as u32) < 127 { c as usize - 32 } else { '?' as usize - 32 }];
+        for (column, bits) in glyph.iter().enumerate() {
+            for row in 0..GLYPH_HEIGHT {
+                if (*bits >> row) & 1 == 0 { continue }
+                for dx in 0..scale {
+                    for dy in 0..scale {
+                        let px = x + (i as u32 * GLYPH_WIDTH + column as u32) * scale + dx;
+                        composition.blend(px, y + row * scale + dy, color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+
+/// write the composed image, the format is given by the file extension.
+/// EXR keeps the linear premultiplied float pixels, formats without alpha, like jpeg, are flattened.
+pub fn save_export(composition: &TwComposition, path: &Path) -> io::Result<()> {
+    let extension = path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase()).unwrap_or_default();
+    match extension.as_str() {
+        "exr" => {
+            let width = composition.width as usize;
+            write_rgba_file(path, width, composition.height as usize, |x, y| {
+                let pixel = composition.pixels[y * width + x];
+                (pixel[0], pixel[1], pixel[2], pixel[3])
+            }).map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))
+        }
+        "jpg" | "jpeg" | "bmp" => DynamicImage::ImageRgba8(composition.to_rgba()).to_rgb().save(path),
+        _ => composition.to_rgba().save(path),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use amethyst::core::Transform;
+
+    // 2x2 image centered on x, 0
+    fn layer(color: [u8; 4], x: f32, z: f32) -> TwExportLayer {
+        let mut transform = Transform::default();
+        transform.set_translation_xyz(x, 0.0, z);
+        TwExportLayer {
+            tw_image: TwImage::new(2, 2, "layer.png"),
+            pixels: TwPixels::from_rgba(RgbaImage::from_pixel(2, 2, Rgba(color))),
+            matrix: transform.matrix(),
+        }
+    }
+
+    // a 4x4 world units board around the origin, one output pixel by unit
+    fn settings() -> TwExportSettings {
+        TwExportSettings { rect: Rect::new((-2.0, -2.0), (2.0, 2.0)), width: 4, height: 4, background: [0.0, 0.0, 0.0, 1.0] }
+    }
+
+    #[test]
+    fn compose_image_over_background() {
+        let image = compose(&[layer([255, 0, 0, 255], 0.0, 0.0)], &[], &settings()).to_rgba();
+        assert_eq!(image.get_pixel(0, 0), &Rgba([0, 0, 0, 255]));
+        assert_eq!(image.get_pixel(1, 1), &Rgba([255, 0, 0, 255]));
+        assert_eq!(image.get_pixel(2, 2), &Rgba([255, 0, 0, 255]));
+        assert_eq!(image.get_pixel(3, 3), &Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn compose_follows_z_order() {
+        // blue is above red where they overlap, whatever the order of the layers
+        let layers = [layer([0, 0, 255, 255], 0.5, 0.2), layer([255, 0, 0, 255], -0.5, 0.1)];
+        let image = compose(&layers, &[], &settings()).to_rgba();
+        assert_eq!(image.get_pixel(0, 1), &Rgba([255, 0, 0, 255]));
+        assert_eq!(image.get_pixel(1, 1), &Rgba([0, 0, 255, 255]));
+        assert_eq!(image.get_pixel(2, 1), &Rgba([0, 0, 255, 255]));
+        assert_eq!(image.get_pixel(3, 1), &Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn compose_applies_channels_and_crop() {
+        let mut white = layer([255, 255, 255, 255], 0.0, 0.0);
+        white.tw_image.red = 0.0;
+        white.tw_image.crop = Some(crate::image::TwCrop { x: 0, y: 0, width: 1, height: 2 });
+        let image = compose(&[white], &[], &settings()).to_rgba();
+        // the cropped image is one pixel wide, centered on its transform
+        assert_eq!(image.get_pixel(0, 1), &Rgba([0, 0, 0, 255]));
+        assert_eq!(image.get_pixel(1, 1), &Rgba([0, 255, 255, 255]));
+        assert_eq!(image.get_pixel(2, 1), &Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn compose_draws_annotations() {
+        let line = TwExportAnnotation { segments: vec![((-2.0, -1.5), (2.0, -1.5))], text: None, color: [0.0, 1.0, 0.0, 1.0] };
+        let note = TwExportAnnotation { segments: Vec::new(), text: Some(("H".to_owned(), (-2.0, 2.0))), color: [1.0, 1.0, 1.0, 1.0] };
+        let image = compose(&[], &[line, note], &settings()).to_rgba();
+        // the line is two pixels wide
+        assert_eq!(image.get_pixel(3, 2), &Rgba([0, 255, 0, 255]));
+        assert_eq!(image.get_pixel(3, 3), &Rgba([0, 255, 0, 255]));
+        assert_eq!(image.get_pixel(3, 1), &Rgba([0, 0, 0, 255]));
+        // the first column of H at scale 2, drawn over the line
+        assert_eq!(image.get_pixel(0, 0), &Rgba([255, 255, 255, 255]));
+        assert_eq!(image.get_pixel(1, 3), &Rgba([255, 255, 255, 255]));
+        assert_eq!(image.get_pixel(2, 0), &Rgba([0, 0, 0, 255]));
+    }
+
+
+    #[test]
+    fn save_export_writes_exr() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("board.exr");
+        let composition = compose(&[layer([255, 0, 0, 255], 0.0, 0.0)], &[], &settings());
+        save_export(&composition, &path).unwrap();
+        let image = exr::prelude::read_first_rgba_layer_from_file(
+            &path,
+            |size, _| (size.width(), vec![[0.0; 4]; size.width() * size.height()]),
+            |(width, pixels): &mut (usize, Vec<[f32; 4]>), position, (r, g, b, a): (f32, f32, f32, f32)| {
+                pixels[position.y() * *width + position.x()] = [r, g, b, a];
+            },
+        ).unwrap();
+        assert_eq!((image.layer_data.size.width(), image.layer_data.size.height()), (4, 4));
+        let (_, pixels) = &image.layer_data.channel_data.pixels;
+        // the float pixels are written as they are composed, linear and premultiplied
+        assert_eq!(pixels, &composition.pixels);
+        assert!((pixels[5][0] - 1.0).abs() < 0.001 && pixels[5][1] == 0.0 && pixels[5][3] == 1.0);
+        assert_eq!(pixels[0], [0.0, 0.0, 0.0, 1.0]);
+    }
+}
@@ -0,0 +1,218 @@
+/// export_system.rs contains the export related system, the export window use ImGui as GUI lib
+/// and the composition itself run in a new thread.
+use amethyst::{
+    core::{SystemDesc, Transform},
+    derive::SystemDesc,
+    ecs::{Join, Read, System, SystemData, World},
+    ecs::prelude::*,
+    input::VirtualKeyCode,
+    renderer::SpriteRender,
+};
+use amethyst_imgui::{
+    imgui,
+    imgui::{im_str, Condition, ImString},
+};
+use geo::LineString;
+use serde_json::json;
+use geo::algorithm::bounding_rect::BoundingRect;
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use crate::export::{TwExportLayer, TwExportAnnotation, TwExportSettings, compose, save_export, image_world_corners};
+use crate::image::{TwImage, load_texture_from_file};
+use crate::annotation::{TwAnnotation, TwAnnotationKind};
+use crate::inputshandler::TwInputsHandler;
+use crate::tower::{TowerData, BACKGROUNDCOLOR};
+use crate::ui_system::UI_WIDTH;
+use crate::remote::{TwRemoteActions, TwRemoteReply};
+
+
+#[derive(SystemDesc)]
+pub struct TwExportSystem {
+    open: bool,
+    path: ImString,
+    width: i32,
+    // images listed by the export window, the checked ones are exported
+    selection: Vec<(Entity, String, bool)>,
+    active: Option<Entity>,
+}
+
+impl Default for TwExportSystem {
+    fn default() -> Self {
+        Self {
+            open: false,
+            path: ImString::with_capacity(512),
+            // TODO: default export width as settings
+            width: 4096,
+            selection: Vec::new(),
+            active: None,
+        }
+    }
+}
+/// export the board as a flattened image file, Ctrl + E open the export window.
+/// The window lists the images of the board, all of them are checked when it opens. Only the
+/// checked images are exported, with their annotations, inside the bounds of the selection.
+/// The free annotations are exported with the whole board only.
+/// The export is done at the chosen width, the height follow the ratio of the exported area.
+/// The format is given by the file extension, png, jpeg, tiff, exr...
+/// The remote control snapshot request exports the whole board without the window.
+impl<'s> System<'s> for TwExportSystem {
+    type SystemData = (WriteExpect<'s, TwInputsHandler>,
+                       Read<'s, TowerData>,
+                       Write<'s, TwRemoteActions>,
+                       ReadStorage<'s, TwImage>,
+                       ReadStorage<'s, Transform>,
+                       ReadStorage<'s, SpriteRender>,
+                       ReadStorage<'s, TwAnnotation>,
+                       Entities<'s>);
+    fn run(&mut self, (
+        mut tw_in,
+        tw_data,
+        mut remote_actions,
+        tw_images,
+        transforms,
+        sprites,
+        annotations,
+        entities,
+    ): Self::SystemData) {
+        if tw_in.keys_pressed.contains(&VirtualKeyCode::E) && tw_in.keys_pressed.contains(&VirtualKeyCode::LControl) && tw_in.keys_pressed.len() == 2 {
+            if Duration::from_millis(500) <= tw_in.stopwatch.elapsed() {
+                self.open = true;
+                self.active = tw_in.active_entities.last().cloned();
+                self.selection = (&tw_images, &sprites, &*entities).join()
+                    .map(|(tw_image, _, entity)| {
+                        let name = Path::new(&tw_image.file_name).file_name().map_or(tw_image.file_name.clone(), |n| n.to_string_lossy().into_owned());
+                        (entity, name, true)
+                    })
+                    .collect();
+                if self.path.to_str().is_empty() {
+                    self.path.push_str(Path::new(&tw_data.working_dir).join("export.png").to_str().unwrap());
+                }
+                tw_in.stopwatch.restart();
+            }
+        }
+        if let Some(snapshot) = remote_actions.snapshot.take() {
+            let width = snapshot.width.unwrap_or(self.width.max(1) as u32);
+            export_board(&tw_data, &tw_images, &transforms, &sprites, &annotations, &entities, None, width, snapshot.path, Some(snapshot.reply));
+        }
+        if tw_in.keys_pressed.contains(&VirtualKeyCode::Escape) { self.open = false }
+        if !self.open { return }
+        let mut export = false;
+        let (path, width, selection, active) = (&mut self.path, &mut self.width, &mut self.selection, self.active);
+        amethyst_imgui::with(|ui| {
+            imgui::Window::new(im_str!("Export"))
+                .size([UI_WIDTH * 1.5, 0.0], Condition::Always)
+                .position([ui.io().mouse_pos[0] - UI_WIDTH * 0.5, ui.io().mouse_pos[1]], Condition::Appearing)
+                .build(ui, || {
+                    ui.input_text(im_str!("Path"), path).build();
+                    ui.input_int(im_str!("Width"), width).build();
+                    if ui.button(im_str!("All"), [0.0, 0.0]) {
+                        selection.iter_mut().for_each(|(_, _, checked)| *checked = true);
+                    }
+                    if let Some(active) = active {
+                        ui.same_line(0.0);
+                        if ui.button(im_str!("Active image only"), [0.0, 0.0]) {
+                            selection.iter_mut().for_each(|(entity, _, checked)| *checked = *entity == active);
+                        }
+                    }
+                    for (entity, name, checked) in selection.iter_mut() {
+                        ui.checkbox(&ImString::new(format!("{}##{}", name, entity.id())), checked);
+                    }
+                    export = ui.button(im_str!("Export"), [0.0, 0.0]);
+                });
+        });
+        if !export { return }
+        self.open = false;
+        self.selection.retain(|(entity, _, _)| entities.is_alive(*entity));
+        let selected = self.selection.iter().filter(|(_, _, checked)| *checked).map(|(entity, _, _)| *entity).collect::<HashSet<_>>();
+        if selected.is_empty() {
+            warn!("No image selected to export");
+            return
+        }
+        let only = if selected.len() == self.selection.len() { None } else { Some(selected) };
+        let path = PathBuf::from(self.path.to_str());
+        export_board(&tw_data, &tw_images, &transforms, &sprites, &annotations, &entities, only, self.width.max(1) as u32, path, None);
+    }
+}
+
+
+/// compose the images of the board, or only the given entities, with their annotations and save
+/// them in a new thread. The exported area is the bounds of the exported images.
+/// The result is sent to the remote reply when the export comes from the remote control.
+fn export_board(tw_data: &TowerData, tw_images: &ReadStorage<TwImage>, transforms: &ReadStorage<Transform>,
+                sprites: &ReadStorage<SpriteRender>, annotations: &ReadStorage<TwAnnotation>, entities: &Entities,
+                only: Option<HashSet<Entity>>, width: u32, path: PathBuf, reply: Option<TwRemoteReply>) {
+    let exported = |entity: Entity| only.as_ref().map_or(true, |only| only.contains(&entity));
+    // get the pixels from cache, missing ones are loaded again inside the export thread
+    let mut images = Vec::new();
+    let mut corners = Vec::new();
+    {
+        let cache = tw_data.cache.lock().unwrap();
+        for (tw_image, transform, _sprite, entity) in (tw_images, transforms, sprites, &**entities).join() {
+            if !exported(entity) { continue }
+            let pixels = cache.get(&tw_image.file_name).map(|(_, pixels)| pixels.clone());
+            corners.extend(image_world_corners(tw_image, &transform.matrix()));
+            images.push((tw_image.clone(), transform.matrix(), pixels));
+        }
+    }
+    let rect = match LineString::from(corners).bounding_rect() {
+        Some(rect) => rect,
+        None => {
+            warn!("No image to export");
+            if let Some(reply) = reply {
+                let _ = reply.send(Err("No image to export".to_owned()));
+            }
+            return
+        }
+    };
+    let mut export_annotations = Vec::new();
+    for annotation in annotations.join() {
+        let attached = match annotation.attached_to {
+            Some(entity) => match (tw_images.get(entity), transforms.get(entity)) {
+                (Some(tw_image), Some(transform)) if exported(entity) => Some((tw_image, transform)),
+                _ => continue
+            },
+            None if only.is_none() => None,
+            None => continue
+        };
+        let text = match &annotation.kind {
+            TwAnnotationKind::Text(text) => annotation.world_points(attached).first().map(|anchor| (text.clone(), *anchor)),
+            _ => None
+        };
+        export_annotations.push(TwExportAnnotation { segments: annotation.world_segments(attached), text, color: annotation.color });
+    }
+    let settings = TwExportSettings::with_width(rect, width, BACKGROUNDCOLOR);
+    info!("Board is exporting to {:?} at {:?}x{:?}", &path, settings.width, settings.height);
+    thread::spawn(move || {
+        let layers = images.into_iter().filter_map(|(tw_image, matrix, pixels)| {
+            let pixels = match pixels {
+                Some(pixels) => pixels,
+                None => match load_texture_from_file(&tw_image.file_name) {
+                    Ok((_, pixels)) => pixels,
+                    Err(e) => {
+                        warn!("Image {:?} is missing from the export: {:?}", &tw_image.file_name, e);
+                        return None
+                    }
+                }
+            };
+            Some(TwExportLayer { tw_image, pixels, matrix })
+        }).collect::<Vec<_>>();
+        let result = match save_export(&compose(&layers, &export_annotations, &settings), &path) {
+            Ok(()) => {
+                info!("Board exported to {:?}", &path);
+                Ok(json!({"path": path, "width": settings.width, "height": settings.height}))
+            }
+            Err(e) => {
+                error!("Failed to export board to {:?}: {:?}", &path, e);
+                Err(e.to_string())
+            }
+        };
+        // the remote client may be gone after the timeout
+        if let Some(reply) = reply {
+            let _ = reply.send(result);
+        }
+    });
+}
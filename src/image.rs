@@ -7,16 +7,27 @@ use amethyst::renderer::{
     Format,
     };
 use amethyst::ecs::prelude::{Component, DenseVecStorage, VecStorage, };
+use amethyst::renderer::sprite::Sprite;
+use amethyst::core::{Transform, math::Point3};
 use image;
-use image::{GenericImageView, ColorType};
+use image::RgbaImage;
+use geo::{Polygon, LineString};
+use serde::{Serialize, Deserialize};
 
 use uuid::Uuid;
 
 use std::borrow::Cow;
-use std::collections::HashMap;
-use std::sync::MutexGuard;
+use std::str::FromStr;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
 
 use crate::utils::{premultiply_by_alpha, add_alpha_channel};
+use crate::tile::MAX_TEXTURE_SIZE;
+use crate::texture::is_block_format_sampled;
+use crate::decoder::{TwPixelBuffer, TwSamples, decode_file, load_pixels};
+use crate::metadata::{TwMetadata, read_metadata};
 
 
 /// active ui component, special component to get active image that will used by the UI system,
@@ -38,6 +49,37 @@ impl Component for TwActiveComponent {
 }
 
 
+/// non destructive crop of a TwImage, a rectangle in pixel coord from the top left corner of the
+/// image. Only this sub rectangle of the texture is rendered by the sprite.
+#[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TwCrop {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl TwCrop {
+    /// fit the crop inside an image of the given size, None if nothing is left to display.
+    pub fn clamp(&self, width: u32, height: u32) -> Option<Self> {
+        if self.x >= width || self.y >= height {
+            return None
+        }
+        let crop = Self {
+            x: self.x,
+            y: self.y,
+            width: self.width.min(width - self.x),
+            height: self.height.min(height - self.y),
+        };
+        if crop.width == 0 || crop.height == 0 { None } else { Some(crop) }
+    }
+}
+
+
+// smallest scale of an image, a null or negative scale would make its transform singular
+pub const MIN_SCALE: f32 = 0.01;
+
+
 /// The big component Image, TwImage is the main component of the image element. It store all the image
 /// attributes like size, image path, ratio...
 #[derive(PartialEq, Debug, Clone)]
@@ -52,6 +94,13 @@ pub struct TwImage {
     pub red: f32,
     pub green: f32,
     pub blue: f32,
+    pub scale: f32,
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+    pub crop: Option<TwCrop>,
+    pub filter: Option<TwFilter>,
+    /// read by the loader thread, shared by the TwImages of the same file
+    pub metadata: Option<Arc<TwMetadata>>,
 }
 
 impl  TwImage {
@@ -69,8 +118,49 @@ impl  TwImage {
             red: 1.0,
             green: 1.0,
             blue: 1.0,
+            scale: 1.0,
+            flip_horizontal: false,
+            flip_vertical: false,
+            crop: None,
+            filter: None,
+            metadata: None,
         }
     }
+
+    /// take the user attributes of a previous TwImage, used when an image replace an other one
+    /// like with next / previous navigation or scene loading.
+    pub fn inherit(&mut self, previous: &TwImage) {
+        self.scale = previous.scale;
+        self.flip_horizontal = previous.flip_horizontal;
+        self.flip_vertical = previous.flip_vertical;
+        self.crop = previous.crop.and_then(|crop| crop.clamp(self.width, self.height));
+        self.alpha = previous.alpha;
+        self.red = previous.red;
+        self.green = previous.green;
+        self.blue = previous.blue;
+        self.filter = previous.filter;
+    }
+
+    /// get the displayed rectangle, the crop if any or the whole image.
+    pub fn visible_rect(&self) -> TwCrop {
+        self.crop.unwrap_or(TwCrop { x: 0, y: 0, width: self.width, height: self.height })
+    }
+
+    /// convert a position local to the sprite, centered and y up, to a pixel position of the image.
+    pub fn local_to_pixel(&self, local: (f32, f32)) -> (f32, f32) {
+        let rect = self.visible_rect();
+        let x = if self.flip_horizontal { -local.0 } else { local.0 };
+        let y = if self.flip_vertical { -local.1 } else { local.1 };
+        (rect.x as f32 + rect.width as f32 * 0.5 + x, rect.y as f32 + rect.height as f32 * 0.5 - y)
+    }
+
+    /// convert a pixel position of the image to a position local to the sprite, centered and y up.
+    pub fn pixel_to_local(&self, pixel: (f32, f32)) -> (f32, f32) {
+        let rect = self.visible_rect();
+        let x = pixel.0 - rect.x as f32 - rect.width as f32 * 0.5;
+        let y = rect.y as f32 + rect.height as f32 * 0.5 - pixel.1;
+        (if self.flip_horizontal { -x } else { x }, if self.flip_vertical { -y } else { y })
+    }
 }
 
 impl Component for TwImage {
@@ -78,69 +168,521 @@ impl Component for TwImage {
 }
 
 
-/// get the gfx color format from the image crate color type.
-/// Add also the Swizzle to manage the grayscale image.
-/// All this could be change with the support of more complex format image
-pub fn get_color_type(color: &ColorType) -> (Format, format::Swizzle) {
-    match color {
-        ColorType::RGB(8) => (Format::Rgba8Srgb, format::Swizzle(format::Component::R, format::Component::G, format::Component::B, format::Component::A)),
-        ColorType::RGBA(8) => (Format::Rgba8Srgb, format::Swizzle(format::Component::R, format::Component::G, format::Component::B, format::Component::A)),
-        ColorType::Gray(8) => (Format::R8Unorm, format::Swizzle(format::Component::R, format::Component::R, format::Component::R, format::Component::A)),
-        _ => (Format::Rgb8Unorm, format::Swizzle(format::Component::R, format::Component::G, format::Component::B, format::Component::A))
-    }
-}
-
-
-/// from an image path, create a full TwImage component and a TextureData component.
-/// TextureData is made directly from the pixel data.
-/// get pixel format and swizzle from get_color_type()
-/// could be change with the support of more complex format image
-pub fn load_texture_from_file (name: &str) ->  (TwImage, TextureData) {
-    let img = image::open(name).unwrap();
-    let dimensions = img.dimensions();
-    let (color_type, swizzle) = get_color_type(&img.color());
-    debug!("Image color format is {:?} and after conversion {:?}", color_type, &img.color());
-    let pixels = match &img.color() {
-        ColorType::RGBA(8) => premultiply_by_alpha(&img.raw_pixels()),
-        ColorType::RGB(8) => add_alpha_channel(&img.raw_pixels()),
-        _ => img.raw_pixels()};
-    let texture_builder = TextureBuilder::new()
-        .with_data_width(dimensions.0)
-        .with_data_height(dimensions.1)
-        .with_kind(Kind::D2(dimensions.0, dimensions.1, 1, 1))
-        .with_view_kind(ViewKind::D2)
-        // TODO: Filter type as settings
-        .with_sampler_info(SamplerInfo {
-            min_filter: Filter::Nearest,
-            mag_filter: Filter::Nearest,
-            mip_filter: Filter::Nearest,
-            wrap_mode: (WrapMode::Clamp, WrapMode::Clamp, WrapMode::Clamp),
-            lod_bias: 0.0.into(),
-            lod_range: std::ops::Range {
-                start: 0.0.into(),
-                end: 1.0.into(),
+/// build the sprite of a TwImage, only the crop rectangle is used if the TwImage has one,
+/// the flip flags of the TwImage are applied.
+pub fn build_sprite(tw_image: &TwImage) -> Sprite {
+    let rect = tw_image.visible_rect();
+    Sprite::from_pixel_values(
+        tw_image.width, tw_image.height, rect.width,
+        rect.height, rect.x, rect.y, [0.0, 0.0],
+        tw_image.flip_horizontal, tw_image.flip_vertical,
+    )
+}
+
+
+/// build the sprite of a preview, the texture is a thumbnail but the sprite keeps the original
+/// image size, scale, crop and flip, so the preview is drawn where the full image will be.
+pub fn build_preview_sprite(tw_image: &TwImage) -> Sprite {
+    let rect = tw_image.visible_rect();
+    let (width, height) = (tw_image.width.max(1) as f32, tw_image.height.max(1) as f32);
+    let (pixel_left, pixel_right) = (rect.x as f32 / width, (rect.x + rect.width) as f32 / width);
+    let (pixel_top, pixel_bottom) = (rect.y as f32 / height, (rect.y + rect.height) as f32 / height);
+    let (left, right) = if tw_image.flip_horizontal { (pixel_right, pixel_left) } else { (pixel_left, pixel_right) };
+    let (bottom, top) = if tw_image.flip_vertical { (pixel_top, pixel_bottom) } else { (pixel_bottom, pixel_top) };
+    Sprite::from(((rect.width as f32 * tw_image.scale, rect.height as f32 * tw_image.scale), [left, right, bottom, top]))
+}
+
+
+/// get the oriented rectangle of the sprite in world coord, the four sprite corners are moved by
+/// the full transform, translation, rotation and scale.
+pub fn sprite_world_polygon(sprite: &Sprite, transform: &Transform) -> Polygon<f32> {
+    let (half_width, half_height) = (sprite.width * 0.5, sprite.height * 0.5);
+    let matrix = transform.matrix();
+    let corners = [(-half_width, -half_height), (half_width, -half_height),
+                   (half_width, half_height), (-half_width, half_height)];
+    let points = corners.iter().map(|(x, y)| {
+        let p = matrix.transform_point(&Point3::new(*x, *y, 0.0));
+        (p.x, p.y)
+    }).collect::<Vec<_>>();
+    Polygon::new(LineString::from(points), vec![])
+}
+
+
+/// texture filtering of the images, the magnification is always nearest to keep the pixel
+/// peeping at high zoom, only the minification change.
+/// The texture holds a single mip level chosen on the CPU from the camera Z, see TwImageLevelSystem.
+/// - Nearest: nearest pixel of the full resolution, the mip chain is not used
+/// - Linear: linear filtering of the nearest mip level
+// TODO: trilinear filtering, it needs the two nearest levels blended on the GPU
+#[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum TwFilter {
+    Nearest,
+    Linear,
+}
+
+impl Default for TwFilter {
+    fn default() -> Self {
+        TwFilter::Linear
+    }
+}
+
+impl FromStr for TwFilter {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "nearest" => Ok(TwFilter::Nearest),
+            "linear" => Ok(TwFilter::Linear),
+            _ => Err(format!("Invalid filter {:?}, expected nearest or linear", s))
+        }
+    }
+}
+
+impl TwFilter {
+    pub fn next(self) -> Self {
+        match self {
+            TwFilter::Nearest => TwFilter::Linear,
+            TwFilter::Linear => TwFilter::Nearest,
+        }
+    }
+}
+
+
+/// decoded pixels of an image, as stored in the TowerData cache. They are uploaded as a texture
+/// when the TwImage is created and they are read on the CPU by the board export.
+/// the data are shared behind an Arc to avoid copies between the cache and the threads.
+#[derive(Debug, Clone)]
+pub struct TwPixels {
+    pub width: u32,
+    pub height: u32,
+    pub data: Arc<Vec<u8>>,
+    pub format: Format,
+    pub swizzle: format::Swizzle,
+    /// mip levels after the full resolution, each one is half of the previous one until 1x1
+    pub mips: Vec<Arc<Vec<u8>>>,
+    /// block compressed levels uploaded instead of the decoded pixels, see texture.rs
+    pub blocks: Option<TwBlocks>,
+}
+
+
+/// block compressed mip levels of a texture file, from the full resolution.
+#[derive(Debug, Clone)]
+pub struct TwBlocks {
+    pub format: Format,
+    pub swizzle: format::Swizzle,
+    pub levels: Vec<Arc<Vec<u8>>>,
+}
+
+impl TwPixels {
+    /// create premultiplied rgba 8bit pixels from a straight alpha rgba image.
+    pub fn from_rgba(image: RgbaImage) -> Self {
+        Self {
+            width: image.width(),
+            height: image.height(),
+            data: Arc::new(premultiply_by_alpha(&image.into_raw())),
+            format: Format::Rgba8Srgb,
+            swizzle: format::Swizzle::NO,
+            mips: Vec::new(),
+            blocks: None,
+        }
+    }
+
+    /// create the TwPixels of a decoded image, the alpha is premultiplied and the gray alpha and
+    /// rgb images are expanded to rgba. 8bit samples are sRGB, 16bit are unorm and float are
+    /// uploaded as they are, the gray images keep a single channel swizzled to rgb.
+    pub fn from_buffer(buffer: TwPixelBuffer) -> Self {
+        let gray = format::Swizzle(format::Component::R, format::Component::R, format::Component::R, format::Component::A);
+        let channels = buffer.channels as usize;
+        let (data, format, swizzle) = match buffer.samples {
+            TwSamples::U8(samples) => match channels {
+                1 => (samples, Format::R8Unorm, gray),
+                3 => (add_alpha_channel(&samples), Format::Rgba8Srgb, format::Swizzle::NO),
+                4 => (premultiply_by_alpha(&samples), Format::Rgba8Srgb, format::Swizzle::NO),
+                _ => {
+                    let rgba = samples.chunks(2).flat_map(|p| vec![p[0], p[0], p[0], p[1]]).collect::<Vec<_>>();
+                    (premultiply_by_alpha(&rgba), Format::Rgba8Srgb, format::Swizzle::NO)
+                }
             },
-            comparison: None,
-            border: PackedColor(0),
-            anisotropic: Anisotropic::On(8),
+            TwSamples::U16(samples) => {
+                if channels == 1 {
+                    (samples.iter().flat_map(|s| s.to_le_bytes().to_vec()).collect(), Format::R16Unorm, gray)
+                } else {
+                    let rgba = premultiplied_rgba(samples.iter().map(|s| *s as f32 / 65535.0), channels);
+                    let data = rgba.iter().flat_map(|s| ((s * 65535.0).round() as u16).to_le_bytes().to_vec()).collect();
+                    (data, Format::Rgba16Unorm, format::Swizzle::NO)
+                }
+            }
+            TwSamples::F32(samples) => {
+                if channels == 1 {
+                    (samples.iter().flat_map(|s| s.to_le_bytes().to_vec()).collect(), Format::R32Sfloat, gray)
+                } else {
+                    let rgba = premultiplied_rgba(samples.into_iter(), channels);
+                    (rgba.iter().flat_map(|s| s.to_le_bytes().to_vec()).collect(), Format::Rgba32Sfloat, format::Swizzle::NO)
+                }
+            }
+        };
+        Self {
+            width: buffer.width,
+            height: buffer.height,
+            data: Arc::new(data),
+            format,
+            swizzle,
+            mips: Vec::new(),
+            blocks: None,
+        }
+    }
+
+    /// size in bytes of one sample of the pixel format.
+    pub fn sample_size(&self) -> usize {
+        match self.format {
+            Format::R16Unorm | Format::Rgba16Unorm => 2,
+            Format::R32Sfloat | Format::Rgba32Sfloat => 4,
+            _ => 1,
+        }
+    }
+
+    /// generate the mip chain with a 2x2 box filter, run in the loader thread.
+    /// The 8bit, 16bit and float formats are downsampled, the blocks of the texture files keep
+    /// the levels of the file beside them.
+    pub fn with_mipmaps(mut self) -> Self {
+        match self.format {
+            Format::Rgba8Srgb | Format::Rgb8Unorm | Format::R8Unorm
+            | Format::R16Unorm | Format::Rgba16Unorm
+            | Format::R32Sfloat | Format::Rgba32Sfloat => {}
+            _ => return self
+        }
+        let size = self.sample_size();
+        let channels = self.data.len() / size / (self.width as usize * self.height as usize).max(1);
+        let sample = |data: &[u8], i: usize| match size {
+            2 => u16::from_le_bytes([data[i * 2], data[i * 2 + 1]]) as f32,
+            4 => f32::from_le_bytes([data[i * 4], data[i * 4 + 1], data[i * 4 + 2], data[i * 4 + 3]]),
+            _ => data[i] as f32,
+        };
+        let (mut width, mut height) = (self.width as usize, self.height as usize);
+        let mut previous = Arc::clone(&self.data);
+        self.mips.clear();
+        while width > 1 || height > 1 {
+            let (mip_width, mip_height) = ((width / 2).max(1), (height / 2).max(1));
+            let mut mip = Vec::with_capacity(mip_width * mip_height * channels * size);
+            for y in 0..mip_height {
+                let (y0, y1) = ((y * 2).min(height - 1), (y * 2 + 1).min(height - 1));
+                for x in 0..mip_width {
+                    let (x0, x1) = ((x * 2).min(width - 1), (x * 2 + 1).min(width - 1));
+                    for c in 0..channels {
+                        let average = (sample(&previous, (y0 * width + x0) * channels + c)
+                            + sample(&previous, (y0 * width + x1) * channels + c)
+                            + sample(&previous, (y1 * width + x0) * channels + c)
+                            + sample(&previous, (y1 * width + x1) * channels + c)) / 4.0;
+                        match size {
+                            2 => mip.extend_from_slice(&(average.round() as u16).to_le_bytes()),
+                            4 => mip.extend_from_slice(&average.to_le_bytes()),
+                            _ => mip.push(average.round() as u8),
+                        }
+                    }
+                }
+            }
+            let mip = Arc::new(mip);
+            self.mips.push(Arc::clone(&mip));
+            previous = mip;
+            width = mip_width;
+            height = mip_height;
+        }
+        debug!("{:?} mip levels generated", self.mips.len());
+        self
+    }
+
+    /// count of mip levels including the full resolution.
+    pub fn levels(&self) -> usize {
+        1 + self.mips.len()
+    }
+
+    /// dimensions and data of a mip level, 0 is the full resolution.
+    pub fn level(&self, level: usize) -> (u32, u32, &Arc<Vec<u8>>) {
+        let width = (self.width >> level as u32).max(1);
+        let height = (self.height >> level as u32).max(1);
+        if level == 0 { (width, height, &self.data) } else { (width, height, &self.mips[level - 1]) }
+    }
+
+    /// mip level to upload for a level of detail, between the first level fitting in a texture
+    /// and the smallest level.
+    pub fn texture_level(&self, lod: f32, filter: TwFilter) -> usize {
+        let level = match filter {
+            TwFilter::Nearest => 0,
+            TwFilter::Linear => lod.round() as usize,
+        };
+        level.max(self.first_texture_level()).min(self.levels() - 1)
+    }
+
+    /// first mip level small enough to be a single texture, the larger levels of huge images are
+    /// streamed as tiles. Without mip chain the full resolution is used whatever its size.
+    pub fn first_texture_level(&self) -> usize {
+        (0..self.levels())
+            .find(|level| {
+                let (width, height, _) = self.level(*level);
+                width.max(height) <= MAX_TEXTURE_SIZE
             })
-        .with_raw_data(Cow::Owned(pixels), color_type)
-        .with_swizzle(swizzle);
-    debug!("TwImage and TextureData created");
-    (TwImage::new(dimensions.0, dimensions.1, name), TextureData(texture_builder))
+            .unwrap_or(0)
+    }
+
+    /// create the TextureData of the first mip level fitting in a texture, the texture shown
+    /// when the image is created, before TwImageLevelSystem picks the level of the camera Z.
+    pub fn texture_data(&self, filter: TwFilter) -> TextureData {
+        self.level_texture_data(self.first_texture_level(), filter)
+    }
+
+    /// create the TextureData of a single mip level from the pixel data.
+    /// The block compressed level is uploaded as it is when the file has it and the device
+    /// samples its format, the decoded pixels otherwise.
+    pub fn level_texture_data(&self, level: usize, filter: TwFilter) -> TextureData {
+        let (width, height, pixels) = self.level(level);
+        let (data, format, swizzle) = match &self.blocks {
+            Some(blocks) if level < blocks.levels.len() && is_block_format_sampled(blocks.format) => (blocks.levels[level].to_vec(), blocks.format, blocks.swizzle),
+            _ => (pixels.to_vec(), self.format, self.swizzle),
+        };
+        let min_filter = match filter {
+            TwFilter::Nearest => Filter::Nearest,
+            TwFilter::Linear => Filter::Linear,
+        };
+        let texture_builder = TextureBuilder::new()
+            .with_data_width(width)
+            .with_data_height(height)
+            .with_kind(Kind::D2(width, height, 1, 1))
+            .with_view_kind(ViewKind::D2)
+            .with_sampler_info(SamplerInfo {
+                min_filter,
+                mag_filter: Filter::Nearest,
+                mip_filter: Filter::Nearest,
+                wrap_mode: (WrapMode::Clamp, WrapMode::Clamp, WrapMode::Clamp),
+                lod_bias: 0.0.into(),
+                lod_range: std::ops::Range {
+                    start: 0.0.into(),
+                    end: 1.0.into(),
+                },
+                comparison: None,
+                border: PackedColor(0),
+                anisotropic: Anisotropic::On(8),
+                })
+            .with_raw_data(Cow::Owned(data), format)
+            .with_swizzle(swizzle);
+        TextureData(texture_builder)
+    }
+
+    /// get the pixel at x, y as straight alpha sRGB values between 0 and 1.
+    /// rgba pixels are stored premultiplied, they are un-premultiplied here.
+    pub fn rgba(&self, x: u32, y: u32) -> [f32; 4] {
+        let size = self.sample_size();
+        let channels = self.data.len() / size / (self.width as usize * self.height as usize).max(1);
+        let data = &self.data;
+        let sample = |i: usize| match size {
+            2 => u16::from_le_bytes([data[i * 2], data[i * 2 + 1]]) as f32 / 65535.0,
+            4 => f32::from_le_bytes([data[i * 4], data[i * 4 + 1], data[i * 4 + 2], data[i * 4 + 3]]).max(0.0).min(1.0),
+            _ => data[i] as f32 / 255.0,
+        };
+        let i = (y as usize * self.width as usize + x as usize) * channels;
+        match channels {
+            4 => {
+                let alpha = sample(i + 3);
+                if alpha == 0.0 { return [0.0, 0.0, 0.0, 0.0] }
+                [sample(i) / alpha, sample(i + 1) / alpha, sample(i + 2) / alpha, alpha]
+            }
+            3 => [sample(i), sample(i + 1), sample(i + 2), 1.0],
+            _ => {
+                let gray = sample(i);
+                [gray, gray, gray, 1.0]
+            }
+        }
+    }
+}
+
+
+/// expand gray alpha, rgb or rgba samples to premultiplied rgba.
+fn premultiplied_rgba<I: Iterator<Item = f32>>(samples: I, channels: usize) -> Vec<f32> {
+    let samples = samples.collect::<Vec<_>>();
+    let mut rgba = Vec::with_capacity(samples.len() / channels * 4);
+    for pixel in samples.chunks(channels) {
+        let (red, green, blue, alpha) = match channels {
+            2 => (pixel[0], pixel[0], pixel[0], pixel[1]),
+            3 => (pixel[0], pixel[1], pixel[2], 1.0),
+            _ => (pixel[0], pixel[1], pixel[2], pixel[3]),
+        };
+        rgba.extend_from_slice(&[red * alpha, green * alpha, blue * alpha, alpha]);
+    }
+    rgba
+}
+
+
+/// from an image path, create a full TwImage component and the TwPixels of the image.
+/// the file is decoded by the TwDecoder of its format, see decoder.rs
+pub fn load_texture_from_file(name: &str) -> io::Result<(TwImage, TwPixels)> {
+    let tw_pixels = load_pixels(Path::new(name))?;
+    debug!("TwImage and TwPixels created with format {:?}", tw_pixels.format);
+    let mut tw_image = TwImage::new(tw_pixels.width, tw_pixels.height, name);
+    tw_image.metadata = Some(Arc::new(read_metadata(name, &tw_pixels)));
+    Ok((tw_image, tw_pixels))
+}
+
+
+/// single gray pixel, stretched as card to preview an image not decoded yet.
+// TODO: gray card color as settings
+pub fn gray_card_pixels() -> TwPixels {
+    TwPixels {
+        width: 1,
+        height: 1,
+        data: Arc::new(vec![80, 80, 80, 255]),
+        format: Format::Rgba8Srgb,
+        swizzle: format::Swizzle::NO,
+        mips: Vec::new(),
+        blocks: None,
+    }
+}
+
+
+/// load a reduced version of the image, the largest side is at most size pixels.
+/// the pixels are always converted to premultiplied rgba 8bit.
+pub fn load_thumbnail(name: &str, size: u32) -> Result<(TwImage, TwPixels), image::ImageError> {
+    let buffer = decode_file(Path::new(name))?;
+    let (width, height) = (buffer.width, buffer.height);
+    let thumbnail = image::imageops::thumbnail(&buffer.to_rgba(), size, size);
+    Ok((TwImage::new(width, height, name), TwPixels::from_rgba(thumbnail)))
 }
 
 
-/// function run exclusively inside a new thread, it load the texture data from a path inside the
-/// the tower data cache, TowerData.cache: Arc<Mutex<HashMap<String, (TwImage, TextureData)>>>,
-/// if path TwImage is already presendt in the hashmap it skipped
-pub fn caching_image(mut cache: MutexGuard<'_, HashMap<String, (TwImage, TextureData)>>, path: String) {
+/// function run exclusively inside a new thread, it load the pixels from a path inside the
+/// the tower data cache, TowerData.cache: Arc<Mutex<HashMap<String, (TwImage, TwPixels)>>>,
+/// if path TwImage is already presendt in the hashmap it skipped.
+/// A file that can't be decoded is logged and added to failed, TwImageLoadFromCacheSystem deletes
+/// its TwPlaceHolder.
+pub fn caching_image(mut cache: MutexGuard<'_, HashMap<String, (TwImage, TwPixels)>>, path: String, failed: &Mutex<HashSet<String>>) {
     debug!("TwImage is loading in cache. {:?}", &path);
     if !cache.contains_key(&path) {
-        cache.insert(path.clone(), load_texture_from_file(&path));
-        debug!("TwImage loaded in cache. {:?}", &path);
+        failed.lock().unwrap().remove(&path);
+        match load_texture_from_file(&path) {
+            Ok(loaded) => {
+                cache.insert(path.clone(), loaded);
+                debug!("TwImage loaded in cache. {:?}", &path);
+            }
+            Err(e) => {
+                error!("Failed to load {:?}: {:?}", &path, e);
+                failed.lock().unwrap().insert(path);
+            }
+        }
     } else {
         debug!("Already in cache, skipped. {:?}", &path);
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use amethyst::core::math::Vector3;
+    use geo::Point;
+    use geo::algorithm::contains::Contains;
+    use geo::algorithm::bounding_rect::BoundingRect;
+
+    // 400x200 image centered on 100, 50
+    fn fixture(rotation: f32, scale: f32, tw_image: &TwImage) -> Polygon<f32> {
+        let mut transform = Transform::default();
+        transform.set_translation_xyz(100.0, 50.0, 0.0);
+        transform.set_rotation_z_axis(rotation.to_radians());
+        transform.set_scale(Vector3::new(scale, scale, 1.0));
+        sprite_world_polygon(&build_sprite(tw_image), &transform)
+    }
+
+    fn assert_bounds(polygon: &Polygon<f32>, min: (f32, f32), max: (f32, f32)) {
+        let bbox = polygon.bounding_rect().unwrap();
+        for (value, expected) in &[(bbox.min.x, min.0), (bbox.min.y, min.1), (bbox.max.x, max.0), (bbox.max.y, max.1)] {
+            assert!((value - expected).abs() < 0.01, "bounds {:?} expected {:?} {:?}", bbox, min, max);
+        }
+    }
+
+    #[test]
+    fn polygon_without_rotation() {
+        let polygon = fixture(0.0, 1.0, &TwImage::new(400, 200, "fixture.png"));
+        assert_bounds(&polygon, (-100.0, -50.0), (300.0, 150.0));
+        assert!(polygon.contains(&Point::new(290.0, 140.0)));
+        assert!(!polygon.contains(&Point::new(310.0, 50.0)));
+    }
+
+    #[test]
+    fn polygon_rotated_90() {
+        let polygon = fixture(90.0, 1.0, &TwImage::new(400, 200, "fixture.png"));
+        assert_bounds(&polygon, (0.0, -150.0), (200.0, 250.0));
+        // out of the unrotated image but inside the rotated one, and the opposite
+        assert!(polygon.contains(&Point::new(100.0, 240.0)));
+        assert!(!polygon.contains(&Point::new(250.0, 50.0)));
+    }
+
+    #[test]
+    fn polygon_rotated_45() {
+        let polygon = fixture(45.0, 1.0, &TwImage::new(400, 200, "fixture.png"));
+        let half = 300.0 * 45.0_f32.to_radians().cos();
+        assert_bounds(&polygon, (100.0 - half, 50.0 - half), (100.0 + half, 50.0 + half));
+        let diagonal = 45.0_f32.to_radians();
+        assert!(polygon.contains(&Point::new(100.0 + 150.0 * diagonal.cos(), 50.0 + 150.0 * diagonal.sin())));
+        // inside the bounding box but outside the oriented rectangle
+        assert!(!polygon.contains(&Point::new(310.0, 50.0)));
+    }
+
+    #[test]
+    fn polygon_with_scale_and_flips() {
+        let mut tw_image = TwImage::new(400, 200, "fixture.png");
+        tw_image.flip_horizontal = true;
+        tw_image.flip_vertical = true;
+        let polygon = fixture(0.0, 2.0, &tw_image);
+        assert_bounds(&polygon, (-300.0, -150.0), (500.0, 250.0));
+        let polygon = fixture(90.0, 2.0, &tw_image);
+        assert_bounds(&polygon, (-100.0, -350.0), (300.0, 450.0));
+    }
+
+    #[test]
+    fn polygon_of_crop() {
+        let mut tw_image = TwImage::new(400, 200, "fixture.png");
+        tw_image.crop = Some(TwCrop { x: 300, y: 0, width: 100, height: 50 });
+        assert_bounds(&fixture(0.0, 1.0, &tw_image), (50.0, 25.0), (150.0, 75.0));
+        assert_bounds(&fixture(90.0, 1.0, &tw_image), (75.0, 0.0), (125.0, 100.0));
+    }
+
+    #[test]
+    fn pixel_local_round_trip_with_flips_and_crop() {
+        let mut tw_image = TwImage::new(400, 200, "fixture.png");
+        tw_image.crop = Some(TwCrop { x: 300, y: 0, width: 100, height: 50 });
+        assert_eq!(tw_image.local_to_pixel((-50.0, 25.0)), (300.0, 0.0));
+        tw_image.flip_horizontal = true;
+        tw_image.flip_vertical = true;
+        assert_eq!(tw_image.local_to_pixel((-50.0, 25.0)), (400.0, 50.0));
+        assert_eq!(tw_image.pixel_to_local((400.0, 50.0)), (-50.0, 25.0));
+    }
+
+    fn pixels(width: u32, height: u32, data: Vec<u8>, format: Format) -> TwPixels {
+        TwPixels { width, height, data: Arc::new(data), format, swizzle: format::Swizzle::NO, mips: Vec::new(), blocks: None }
+    }
+
+    #[test]
+    fn mipmaps_of_16bit_samples() {
+        let samples: Vec<u8> = [0u16, 100, 200, 65535].iter().flat_map(|s| s.to_le_bytes().to_vec()).collect();
+        let tw_pixels = pixels(2, 2, samples, Format::R16Unorm).with_mipmaps();
+        assert_eq!(tw_pixels.levels(), 2);
+        let (width, height, data) = tw_pixels.level(1);
+        assert_eq!((width, height), (1, 1));
+        assert_eq!(u16::from_le_bytes([data[0], data[1]]), 16459);
+    }
+
+    #[test]
+    fn mipmaps_of_float_samples() {
+        let samples: Vec<u8> = [0.5f32, 1.5, 2.0, 4.0].iter().flat_map(|s| s.to_le_bytes().to_vec()).collect();
+        let tw_pixels = pixels(4, 1, samples, Format::R32Sfloat).with_mipmaps();
+        let (width, _, data) = tw_pixels.level(1);
+        assert_eq!(width, 2);
+        let level = data.chunks(4).map(|s| f32::from_le_bytes([s[0], s[1], s[2], s[3]])).collect::<Vec<_>>();
+        assert_eq!(level, vec![1.0, 3.0]);
+    }
+
+    #[test]
+    fn huge_16bit_image_fits_in_a_texture() {
+        let tw_pixels = pixels(MAX_TEXTURE_SIZE * 2 + 2, 1, vec![0; (MAX_TEXTURE_SIZE as usize * 2 + 2) * 2], Format::R16Unorm).with_mipmaps();
+        assert_eq!(tw_pixels.first_texture_level(), 2);
+        assert_eq!(tw_pixels.texture_level(0.0, TwFilter::Linear), 2);
+        assert_eq!(tw_pixels.texture_level(2.6, TwFilter::Linear), 3);
+        assert_eq!(tw_pixels.texture_level(2.6, TwFilter::Nearest), 2);
+    }
+}
@@ -1,29 +1,36 @@
 /// image_system.rs contains all the image related systems
 /// Most of the systems use TwActiveComponent created and removed by the raytracing system
 /// TwActiveComponent is attached to the active TwImage which the one has the mouse on it
-use amethyst::core::{SystemDesc, Transform};
+use amethyst::core::{SystemDesc, Transform, math::{Point3, Vector3}};
 use amethyst::derive::SystemDesc;
 use amethyst::input::{VirtualKeyCode};
 use amethyst::ecs::{Join, Read, System, SystemData, World, WriteStorage};
 use amethyst::ecs::prelude::*;
-use amethyst::renderer::{sprite::{SpriteRender, SpriteSheet, Sprite},
+use amethyst::renderer::{sprite::{SpriteRender, SpriteSheet},
                         resources::Tint,
+                        debug_drawing::DebugLines,
                         palette::Srgba,
-                        Texture, Transparent,
+                        Texture, Transparent, Camera,
 
 };
-use amethyst::assets::{AssetStorage, Loader};
+use amethyst::assets::{AssetStorage, Handle, Loader};
 
 
 use std::{time};
 
-use crate::image::{TwImage, TwActiveComponent};
+use crate::image::{TwImage, TwActiveComponent, TwCrop, TwFilter, MIN_SCALE, build_sprite};
 use crate::inputshandler::{TwInputsHandler};
 use crate::tower::{TowerData};
 use crate::placeholder::TwPlaceHolder;
+use crate::annotation::TwAnnotation;
+use crate::layout::grid_layout;
+use crate::remote::TwRemoteActions;
+use crate::svg::is_svg_file;
+use crate::tile::required_lod;
 
-use std::cmp::Ordering::Equal;
 use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
 use std::ffi::OsString;
 use std::ops::Index;
 
@@ -74,15 +81,17 @@ impl<'s> System<'s> for TwImageMoveSystem {
 #[derive(SystemDesc, Default)]
 pub struct TwImageLayoutSystem;
 /// spread all images as a grid, try to get a square grid as mush as possible.
-/// get the ceil of the sqrt of the images count to defined the size of the grid.
+/// images are sorted by name and placed by layout::grid_layout.
 /// the size of each cell is the max height and max width from all images
 /// An offset is apply between each cell
+/// L key or the remote control layout request.
 impl<'s> System<'s> for TwImageLayoutSystem {
     type SystemData = (Read<'s, TwInputsHandler>,
                        ReadStorage<'s, TwImage>,
                        WriteStorage<'s, Transform>,
                        ReadStorage<'s, SpriteRender>,
                        Read<'s, AssetStorage<SpriteSheet>>,
+                       Write<'s, TwRemoteActions>,
                        Entities<'s>);
     fn run(&mut self, (
         tw_in,
@@ -90,44 +99,27 @@ impl<'s> System<'s> for TwImageLayoutSystem {
         mut transforms,
         sprites,
         sprite_sheets,
+        mut remote_actions,
         entities
     ): Self::SystemData) {
-        if tw_in.keys_pressed.contains(&VirtualKeyCode::L) && tw_in.keys_pressed.len() == 1 {
-            // get the ceil of the sqrt of the images count, to defined the size of the grid
-            let twimage_count = tw_images.count() as f32;
-            let xy_limit = match twimage_count.sqrt().ceil() {
-                xy_limit if xy_limit < 2.0 => 2.0,
-                _ => twimage_count.sqrt().ceil()
-            };
-            let mut comp_iter = (&tw_images, &mut transforms, &sprites, &*entities).join();
-            let mut sprite_heights = Vec::new();
-            let mut sprite_widths = Vec::new();
+        let remote_layout = std::mem::replace(&mut remote_actions.layout, false);
+        if (tw_in.keys_pressed.contains(&VirtualKeyCode::L) && tw_in.keys_pressed.len() == 1) || remote_layout {
             let mut join_entities = Vec::new();
-            // get max width and height
-            for _j in 0..twimage_count as usize {
-                let (tw_image, _transform, sprite, entity) = comp_iter.next().unwrap();
-                join_entities.push((entity, tw_image));
+            for (tw_image, sprite, entity) in (&tw_images, &sprites, &*entities).join() {
                 let sprite_sheet = sprite_sheets.get(&sprite.sprite_sheet).unwrap();
                 let sprite = &sprite_sheet.sprites[sprite.sprite_number];
-                sprite_heights.push(sprite.height);
-                sprite_widths.push(sprite.width);
+                join_entities.push((entity, tw_image, (sprite.width * tw_image.scale, sprite.height * tw_image.scale)));
             }
             join_entities.sort_by(|a, b| a.1.file_name.to_lowercase().cmp(&b.1.file_name.to_lowercase()));
-            sprite_heights.sort_by(|a, b| a.partial_cmp(&b).unwrap_or(Equal));
-            sprite_widths.sort_by(|a, b| a.partial_cmp(&b).unwrap_or(Equal));
             // TODO: offset as settings
             let offset = 10.0;
-            let mut i = 0;
-            for y in 0..xy_limit as usize {
-                for x in 0..xy_limit as usize {
-                    if i >= twimage_count as usize { continue }
-                    debug!("image_name {:?}", join_entities[i].1.file_name);
-                    let e = join_entities[i].0;
-                    let transform = transforms.get_mut(e).unwrap();
-                    transform.set_translation_x((sprite_widths.last().unwrap() + offset) * -(x as f32));
-                    transform.set_translation_y((sprite_heights.last().unwrap() + offset) * -(y as f32));
-                    i += 1;
-                }
+            let sizes = join_entities.iter().map(|(_, _, size)| *size).collect::<Vec<_>>();
+            let (grid, cells) = grid_layout(&sizes, None, offset);
+            for ((e, tw_image, _), (x, y)) in join_entities.iter().zip(cells) {
+                debug!("image_name {:?}", tw_image.file_name);
+                let transform = transforms.get_mut(*e).unwrap();
+                transform.set_translation_x(grid.cell_width * -(x as f32));
+                transform.set_translation_y(grid.cell_height * -(y as f32));
             }
             debug!("Images are layout as an atlas with an offset of {:?}", offset);
         }
@@ -136,52 +128,411 @@ impl<'s> System<'s> for TwImageLayoutSystem {
 
 
 #[derive(SystemDesc, Default)]
-pub struct TwImageRotateSystem;
-/// rotate 90 degree clockwise the active image
+pub struct TwImageRotateSystem {
+    key_down: bool,
+    dragged: bool,
+    click_angle: Option<f32>,
+    start_rotation: f32,
+}
+/// rotate the active image, R + click and drag rotate freely the image around its center,
+/// the angle follow the mouse as a drag handle.
+/// a simple tap on R, without drag, rotate of 90 degree the active image.
 impl<'s> System<'s> for TwImageRotateSystem {
     type SystemData = (WriteExpect<'s, TwInputsHandler>,
-                       WriteExpect<'s, TowerData>,
-                       WriteStorage<'s, Transform>,
-                       Entities<'s>);
+                       WriteStorage<'s, Transform>);
     fn run(&mut self, (
         mut tw_in,
-        mut tw_data,
         mut transforms,
-        entities
     ): Self::SystemData) {
         if tw_in.keys_pressed.contains(&VirtualKeyCode::R) && tw_in.keys_pressed.len() == 1 {
-            if time::Duration::from_millis(5000) <= tw_in.stopwatch.elapsed() {
+            self.key_down = true;
+            if tw_in.mouse_button_pressed.is_some() {
+                if let Some(active_entity) = tw_in.active_entities.last().cloned() {
+                    if let Some(world_pos) = tw_in.mouse_world_position {
+                        tw_in.active_busy = true;
+                        let trans = transforms.get_mut(active_entity).unwrap();
+                        let center = (trans.translation().x, trans.translation().y);
+                        let mouse_angle = (world_pos.1 - center.1).atan2(world_pos.0 - center.0);
+                        if self.click_angle.is_none() {
+                            self.click_angle = Some(mouse_angle);
+                            self.start_rotation = trans.euler_angles().2;
+                        }
+                        if let Some(click_angle) = self.click_angle {
+                            trans.set_rotation_z_axis(self.start_rotation + mouse_angle - click_angle);
+                            self.dragged = true;
+                            debug!("TwImage is rotated of {:?} degrees", (mouse_angle - click_angle).to_degrees());
+                        }
+                    }
+                }
+            } else if self.click_angle.is_some() {
+                self.click_angle = None;
+                tw_in.active_busy = false;
+            }
+        } else if self.key_down {
+            if !self.dragged {
                 if let Some(active_entity) = tw_in.active_entities.last() {
-                    debug!("TwImage is rotating, {:?}", active_entity);
+                    debug!("TwImage is rotating of 90 degrees, {:?}", active_entity);
                     let trans = transforms.get_mut(*active_entity).unwrap();
                     trans.append_rotation_z_axis(90.0_f32.to_radians());
-                    // time offset
-                    tw_in.keys_pressed.remove(0);
                 }
+            } else {
+                tw_in.active_busy = false;
+            }
+            self.key_down = false;
+            self.dragged = false;
+            self.click_angle = None;
+        }
+    }
+}
+
+
+#[derive(SystemDesc, Default)]
+pub struct TwImageFlipSystem;
+/// flip the active image, H key flip horizontally and V key flip vertically.
+/// The flip flags are stored in the TwImage and the sprite of the image sprite sheet is rebuilt
+/// with them.
+impl<'s> System<'s> for TwImageFlipSystem {
+    type SystemData = (WriteExpect<'s, TwInputsHandler>,
+                       WriteStorage<'s, TwImage>,
+                       ReadStorage<'s, SpriteRender>,
+                       Write<'s, AssetStorage<SpriteSheet>>);
+    fn run(&mut self, (
+        mut tw_in,
+        mut tw_images,
+        sprites,
+        mut sprite_sheets,
+    ): Self::SystemData) {
+        let horizontal = tw_in.keys_pressed.contains(&VirtualKeyCode::H) && tw_in.keys_pressed.len() == 1;
+        let vertical = tw_in.keys_pressed.contains(&VirtualKeyCode::V) && tw_in.keys_pressed.len() == 1;
+        if horizontal || vertical {
+            if time::Duration::from_millis(500) <= tw_in.stopwatch.elapsed() {
+                if let Some(active_entity) = tw_in.active_entities.last() {
+                    if let (Some(tw_image), Some(sprite)) = (tw_images.get_mut(*active_entity), sprites.get(*active_entity)) {
+                        if horizontal { tw_image.flip_horizontal = !tw_image.flip_horizontal }
+                        if vertical { tw_image.flip_vertical = !tw_image.flip_vertical }
+                        if let Some(sprite_sheet) = sprite_sheets.get_mut(&sprite.sprite_sheet) {
+                            sprite_sheet.sprites[sprite.sprite_number] = build_sprite(tw_image);
+                        }
+                        debug!("TwImage is flipped, horizontal {:?} vertical {:?}", tw_image.flip_horizontal, tw_image.flip_vertical);
+                    }
+                }
+                tw_in.stopwatch.restart();
+            }
+        }
+    }
+}
+
+
+#[derive(SystemDesc, Default)]
+pub struct TwImageFilterSystem;
+/// change the texture filtering, nearest or linear.
+/// M key cycle the filter of the active image, shift + M cycle the global filter used by all the
+/// images without their own filter.
+/// The sampler is part of the texture, TwImageLevelSystem rebuilds it from the cached TwPixels.
+impl<'s> System<'s> for TwImageFilterSystem {
+    type SystemData = (WriteExpect<'s, TwInputsHandler>,
+                       WriteStorage<'s, TwImage>,
+                       Write<'s, TowerData>);
+    fn run(&mut self, (
+        mut tw_in,
+        mut tw_images,
+        mut tw_data,
+    ): Self::SystemData) {
+        let key_m = tw_in.keys_pressed.contains(&VirtualKeyCode::M);
+        let shift = tw_in.keys_pressed.contains(&VirtualKeyCode::LShift);
+        if key_m && time::Duration::from_millis(500) <= tw_in.stopwatch.elapsed() {
+            if shift && tw_in.keys_pressed.len() == 2 {
+                tw_data.filter = tw_data.filter.next();
+                info!("Global filter is {:?}", tw_data.filter);
+                tw_in.stopwatch.restart();
+            } else if tw_in.keys_pressed.len() == 1 {
+                if let Some(active_entity) = tw_in.active_entities.last() {
+                    if let Some(tw_image) = tw_images.get_mut(*active_entity) {
+                        let filter = tw_image.filter.unwrap_or(tw_data.filter).next();
+                        tw_image.filter = Some(filter);
+                        debug!("TwImage filter is {:?}", filter);
+                    }
+                }
+                tw_in.stopwatch.restart();
+            }
+        }
+    }
+}
+
+
+#[derive(SystemDesc, Default)]
+pub struct TwImageLevelSystem {
+    // mip level and filter of the texture uploaded for each image, the texture handle is None
+    // once an other system replaced it, a surface, a layer composition or a raster.
+    uploads: HashMap<Entity, (usize, TwFilter, Option<Handle<Texture>>)>,
+}
+/// mip mapping done on the CPU, each image texture holds the single mip level matching the camera
+/// Z and the filter of the image. The texture is uploaded again when the level or the filter
+/// change, the sprite keeps the full resolution size whatever the texture size.
+/// Huge images stop at their first level fitting in a texture, TwTileStreamingSystem draws the
+/// finer levels, and the svg images are left to TwSvgRasterSystem.
+impl<'s> System<'s> for TwImageLevelSystem {
+    type SystemData = (ReadStorage<'s, TwImage>,
+                       ReadStorage<'s, SpriteRender>,
+                       ReadStorage<'s, Camera>,
+                       ReadStorage<'s, Transform>,
+                       Read<'s, TowerData>,
+                       Write<'s, AssetStorage<SpriteSheet>>,
+                       Read<'s, AssetStorage<Texture>>,
+                       ReadExpect<'s, Loader>,
+                       Entities<'s>);
+    fn run(&mut self, (
+        tw_images,
+        sprites,
+        cameras,
+        transforms,
+        tw_data,
+        mut sprite_sheets,
+        texture_storage,
+        loader,
+        entities,
+    ): Self::SystemData) {
+        self.uploads.retain(|entity, _| entities.is_alive(*entity));
+        let camera_z = match (&cameras, &transforms).join().next() {
+            Some((_, transform)) => transform.translation().z,
+            None => return
+        };
+        if tw_data.real_size_z <= 0.0 { return }
+        let cache = match tw_data.cache.try_lock() {
+            Ok(cache) => cache,
+            Err(_e) => return
+        };
+        for (tw_image, sprite, entity) in (&tw_images, &sprites, &*entities).join() {
+            if is_svg_file(Path::new(&tw_image.file_name)) { continue }
+            let tw_pixels = match cache.get(&tw_image.file_name) {
+                Some((_, tw_pixels)) => tw_pixels,
+                None => continue
+            };
+            let sprite_sheet = match sprite_sheets.get_mut(&sprite.sprite_sheet) {
+                Some(sprite_sheet) => sprite_sheet,
+                None => continue
+            };
+            let filter = tw_image.filter.unwrap_or(tw_data.filter);
+            let lod = required_lod(camera_z, tw_data.real_size_z, tw_image.scale);
+            let level = tw_pixels.texture_level(lod, filter);
+            // the texture of a new image is the first level, see TwPixels::texture_data
+            let upload = self.uploads.entry(entity)
+                .or_insert_with(|| (tw_pixels.first_texture_level(), filter, Some(sprite_sheet.texture.clone())));
+            match &upload.2 {
+                Some(texture) if *texture != sprite_sheet.texture => {
+                    upload.2 = None;
+                    continue
+                }
+                // a replaced texture is rebuilt from the cache only on a filter change
+                None if upload.1 == filter => continue,
+                Some(_) if upload.0 == level && upload.1 == filter => continue,
+                _ => {}
+            }
+            let texture = loader.load_from_data(tw_pixels.level_texture_data(level, filter), (), &texture_storage);
+            sprite_sheet.texture = texture.clone();
+            *upload = (level, filter, Some(texture));
+            debug!("Mip level {:?} is uploaded for {:?}", level, &tw_image.file_name);
+        }
+    }
+}
+
+
+/// scale change of the active image asked by the keys.
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum TwScaleStep {
+    Up,
+    Down,
+    Reset,
+}
+
+
+#[derive(SystemDesc, Default)]
+pub struct TwImageScaleSystem {
+    // TwImage scale last written in the Transform of each image
+    applied: HashMap<Entity, f32>,
+}
+/// uniform scale of the active image, handy to enlarge a small reference next to big plates.
+/// + key scale up, - key scale down and 0 key reset the scale.
+/// The TwImage scale is applied to the Transform scale of the images when it changes, whatever
+/// changed it, the keys, a script, the remote control or a scene.
+impl<'s> System<'s> for TwImageScaleSystem {
+    type SystemData = (WriteExpect<'s, TwInputsHandler>,
+                       WriteStorage<'s, TwImage>,
+                       WriteStorage<'s, Transform>,
+                       Entities<'s>);
+    fn run(&mut self, (
+        mut tw_in,
+        mut tw_images,
+        mut transforms,
+        entities,
+    ): Self::SystemData) {
+        let step = if tw_in.keys_pressed.len() != 1 {
+            None
+        } else if tw_in.keys_pressed.contains(&VirtualKeyCode::Add) || tw_in.keys_pressed.contains(&VirtualKeyCode::Equals) {
+            Some(TwScaleStep::Up)
+        } else if tw_in.keys_pressed.contains(&VirtualKeyCode::Subtract) || tw_in.keys_pressed.contains(&VirtualKeyCode::Minus) {
+            Some(TwScaleStep::Down)
+        } else if tw_in.keys_pressed.contains(&VirtualKeyCode::Key0) || tw_in.keys_pressed.contains(&VirtualKeyCode::Numpad0) {
+            Some(TwScaleStep::Reset)
+        } else {
+            None
+        };
+        if let Some(step) = step {
+            if time::Duration::from_millis(200) <= tw_in.stopwatch.elapsed() {
+                if let Some(active_entity) = tw_in.active_entities.last() {
+                    if let Some(tw_image) = tw_images.get_mut(*active_entity) {
+                        // TODO: scale step as settings
+                        tw_image.scale = match step {
+                            TwScaleStep::Up => tw_image.scale * 1.25,
+                            TwScaleStep::Down => (tw_image.scale * 0.8).max(MIN_SCALE),
+                            TwScaleStep::Reset => 1.0,
+                        };
+                        debug!("TwImage scale is set to {:?}", tw_image.scale);
+                    }
+                }
+                tw_in.stopwatch.restart();
             }
         }
+        self.applied.retain(|entity, _| entities.is_alive(*entity));
+        for (tw_image, transform, entity) in (&tw_images, &mut transforms, &*entities).join() {
+            if self.applied.get(&entity) == Some(&tw_image.scale) { continue }
+            transform.set_scale(Vector3::new(tw_image.scale, tw_image.scale, 1.0));
+            self.applied.insert(entity, tw_image.scale);
+        }
     }
 }
 
 
+#[derive(SystemDesc, Default)]
+pub struct TwImageCropSystem {
+    cropping: Option<Entity>,
+    start: Option<(f32, f32)>,
+    end: Option<(f32, f32)>,
+}
+/// non destructive crop of the active image. Shift + click and drag draw the crop rectangle on the
+/// active image, on release the crop is stored in the TwImage and the sprite is rebuilt with only
+/// this sub rectangle. The image is moved to keep the cropped area at the same place on the board.
+/// K key reset the crop of the active image.
+impl<'s> System<'s> for TwImageCropSystem {
+    type SystemData = (WriteExpect<'s, TwInputsHandler>,
+                       WriteStorage<'s, TwImage>,
+                       WriteStorage<'s, Transform>,
+                       ReadStorage<'s, SpriteRender>,
+                       Write<'s, AssetStorage<SpriteSheet>>,
+                       Write<'s, DebugLines>);
+    fn run(&mut self, (
+        mut tw_in,
+        mut tw_images,
+        mut transforms,
+        sprites,
+        mut sprite_sheets,
+        mut debug_lines,
+    ): Self::SystemData) {
+        if tw_in.shift_mouse_button_pressed.is_some() {
+            if self.cropping.is_none() {
+                self.cropping = tw_in.active_entities.last().cloned();
+                self.start = tw_in.mouse_world_clicked_position;
+            }
+            if self.cropping.is_some() {
+                tw_in.active_busy = true;
+                self.end = tw_in.mouse_world_position;
+                if let (Some(start), Some(end)) = (self.start, self.end) {
+                    let corners = [(start.0, start.1), (end.0, start.1), (end.0, end.1), (start.0, end.1)];
+                    for i in 0..corners.len() {
+                        let (a, b) = (corners[i], corners[(i + 1) % corners.len()]);
+                        debug_lines.draw_line([a.0, a.1, 0.0].into(), [b.0, b.1, 0.0].into(),
+                                              Srgba::new(1.0, 0.8, 0.0, 1.0));
+                    }
+                }
+            }
+        } else if let Some(entity) = self.cropping.take() {
+            tw_in.active_busy = false;
+            if let (Some(start), Some(end), Some(tw_image), Some(transform)) =
+                (self.start.take(), self.end.take(), tw_images.get_mut(entity), transforms.get_mut(entity)) {
+                if let Some(inverse) = transform.matrix().try_inverse() {
+                    let to_local = |p: (f32, f32)| {
+                        let local = inverse.transform_point(&Point3::new(p.0, p.1, 0.0));
+                        (local.x, local.y)
+                    };
+                    let (p0, p1) = (tw_image.local_to_pixel(to_local(start)), tw_image.local_to_pixel(to_local(end)));
+                    let rect = tw_image.visible_rect();
+                    let min_x = p0.0.min(p1.0).max(rect.x as f32).floor() as u32;
+                    let max_x = p0.0.max(p1.0).min((rect.x + rect.width) as f32).ceil() as u32;
+                    let min_y = p0.1.min(p1.1).max(rect.y as f32).floor() as u32;
+                    let max_y = p0.1.max(p1.1).min((rect.y + rect.height) as f32).ceil() as u32;
+                    // TODO: minimum crop size as settings
+                    if max_x.saturating_sub(min_x) >= 2 && max_y.saturating_sub(min_y) >= 2 {
+                        let crop = TwCrop { x: min_x, y: min_y, width: max_x - min_x, height: max_y - min_y };
+                        apply_crop(tw_image, transform, Some(crop));
+                        if let Some(sprite) = sprites.get(entity) {
+                            if let Some(sprite_sheet) = sprite_sheets.get_mut(&sprite.sprite_sheet) {
+                                sprite_sheet.sprites[sprite.sprite_number] = build_sprite(tw_image);
+                            }
+                        }
+                        debug!("TwImage is cropped to {:?}", crop);
+                    }
+                }
+            }
+        }
+        if tw_in.keys_pressed.contains(&VirtualKeyCode::K) && tw_in.keys_pressed.len() == 1 {
+            if time::Duration::from_millis(500) <= tw_in.stopwatch.elapsed() {
+                if let Some(active_entity) = tw_in.active_entities.last() {
+                    if let (Some(tw_image), Some(transform)) = (tw_images.get_mut(*active_entity), transforms.get_mut(*active_entity)) {
+                        if tw_image.crop.is_some() {
+                            apply_crop(tw_image, transform, None);
+                            if let Some(sprite) = sprites.get(*active_entity) {
+                                if let Some(sprite_sheet) = sprite_sheets.get_mut(&sprite.sprite_sheet) {
+                                    sprite_sheet.sprites[sprite.sprite_number] = build_sprite(tw_image);
+                                }
+                            }
+                            debug!("TwImage crop is reset");
+                        }
+                    }
+                }
+                tw_in.stopwatch.restart();
+            }
+        }
+    }
+}
+
+/// set the new crop of the TwImage and move its transform to keep the new visible rectangle at the
+/// same world position.
+fn apply_crop(tw_image: &mut TwImage, transform: &mut Transform, crop: Option<TwCrop>) {
+    let rect = crop.unwrap_or(TwCrop { x: 0, y: 0, width: tw_image.width, height: tw_image.height });
+    let center = tw_image.pixel_to_local((rect.x as f32 + rect.width as f32 * 0.5,
+                                          rect.y as f32 + rect.height as f32 * 0.5));
+    let world_center = transform.matrix().transform_point(&Point3::new(center.0, center.1, 0.0));
+    transform.set_translation_x(world_center.x);
+    transform.set_translation_y(world_center.y);
+    tw_image.crop = crop;
+}
+
+
 #[derive(SystemDesc)]
 pub struct TwImageDeleteSystem;
-/// delete the active image more precisely the entity
+/// delete the active image more precisely the entity, with the annotations attached to it
 /// and clean the active_entities vector and also the z_ordered_entities in case of two images
 /// are stack each other.
 impl<'s> System<'s> for TwImageDeleteSystem {
     type SystemData = (WriteExpect<'s, TwInputsHandler>,
                        WriteExpect<'s, TowerData>,
+                       ReadStorage<'s, TwAnnotation>,
                        Entities<'s>);
     fn run(&mut self, (
         mut tw_in,
         mut tw_data,
+        annotations,
         entities
     ): Self::SystemData) {
         if tw_in.keys_pressed.contains(&VirtualKeyCode::Delete) && tw_in.keys_pressed.len() == 1 {
             if time::Duration::from_millis(500) <= tw_in.stopwatch.elapsed() {
                 if let Some(active_entity) = tw_in.active_entities.last() {
                     debug!("TwImage is deleting, {:?}", active_entity);
+                    for (annotation, annotation_entity) in (&annotations, &*entities).join() {
+                        if annotation.attached_to == Some(*active_entity) {
+                            entities.delete(annotation_entity).expect("Fail error to delete entity");
+                        }
+                    }
                     entities.delete(*active_entity).expect("Fail error to delete entity");
                     // clean entities copies in tw_in and tw_data
                     tw_in.active_entities.clear();
@@ -256,8 +607,8 @@ impl<'s> System<'s> for TwImageApplyBlendingSystem {
 
 #[derive(SystemDesc)]
 pub struct TwImageLoadFromCacheSystem;
-/// create TwImage from the TowerData cache. For each TwPlaceHolder TextureData and TwImage is retrieve
-/// from cache, then sprite and transform is created
+/// create TwImage from the TowerData cache. For each TwPlaceHolder TwPixels and TwImage is retrieve
+/// from cache, the TextureData is made from the pixels, then sprite and transform is created
 /// then the entity's PlaceHolder is deleted
 impl<'s> System<'s> for TwImageLoadFromCacheSystem {
     type SystemData = (WriteStorage<'s, TwImage>,
@@ -269,7 +620,8 @@ impl<'s> System<'s> for TwImageLoadFromCacheSystem {
                        Read<'s, AssetStorage<SpriteSheet>>,
                        WriteExpect<'s, Loader>,
                        Write<'s, LazyUpdate>,
-                       WriteExpect<'s, TwInputsHandler>);
+                       WriteExpect<'s, TwInputsHandler>,
+                       WriteStorage<'s, TwAnnotation>);
     fn run(&mut self, (
         _tw_images,
         mut tw_places,
@@ -280,9 +632,19 @@ impl<'s> System<'s> for TwImageLoadFromCacheSystem {
         asset_sprite,
         mut loader,
         world,
-        mut tw_in
+        mut tw_in,
+        mut annotations,
     ): Self::SystemData) {
+        // the placeholders of the files that can't be decoded are dropped, the error is logged
+        // by the loader thread
+        if let Ok(mut failed) = tw_data.failed.try_lock() {
+            if !failed.is_empty() {
+                drop_failed_placeholders(&mut tw_places, &entities, &failed);
+                failed.clear();
+            }
+        }
         for (tw_place, transform, entity) in (&mut tw_places, &mut transforms, &*entities).join() {
+            if !entities.is_alive(entity) { continue }
             let arc_cache = Arc::clone(&tw_data.cache);
             let cache_res = match arc_cache.try_lock() {
                 Ok(cache) => Some(cache),
@@ -291,18 +653,18 @@ impl<'s> System<'s> for TwImageLoadFromCacheSystem {
             if !cache_res.is_none() {
                 let cache = cache_res.unwrap();
                 if !cache.is_empty() {
-                    if let Some((tw_image, texture_data)) = cache.get(&tw_place.twimage_path) {
+                    if let Some((cached_tw_image, tw_pixels)) = cache.get(&tw_place.twimage_path) {
+                        // keep the user attributes of the replaced image, from next or scene
+                        let mut tw_image = cached_tw_image.clone();
+                        if let Some(previous) = &tw_place.inherit {
+                            tw_image.inherit(previous);
+                        }
                         // create entity
                         let texture_storage = &mut asset_texture;
                         let mut sprites = Vec::with_capacity(1);
                         let loader = &mut loader;
-                        let texture = loader.load_from_data(texture_data.clone(), (), &texture_storage);
-                        let sprite = Sprite::from_pixel_values(
-                            tw_image.width, tw_image.height, tw_image.width,
-                            tw_image.height, 0, 0, [0.0, 0.0],
-                            false, false,
-                        );
-                        sprites.push(sprite);
+                        let texture = loader.load_from_data(tw_pixels.texture_data(tw_image.filter.unwrap_or(tw_data.filter)), (), &texture_storage);
+                        sprites.push(build_sprite(&tw_image));
                         let sprite_sheet = SpriteSheet {
                             texture,
                             sprites,
@@ -319,13 +681,19 @@ impl<'s> System<'s> for TwImageLoadFromCacheSystem {
                             sprite_number: 0,
                         };
                         let tint = Tint(Srgba::new(1.0, 1.0, 1.0, 1.0));
-                        world.create_entity(&*entities)
+                        let new_entity = world.create_entity(&*entities)
                             .with(transform)
                             .with(sprite_render)
-                            .with(tw_image.clone())
+                            .with(tw_image)
                             .with(Transparent)
                             .with(tint)
                             .build();
+                        // annotations follow the new image entity
+                        for annotation in (&mut annotations).join() {
+                            if annotation.attached_to == Some(entity) {
+                                annotation.attached_to = Some(new_entity);
+                            }
+                        }
                         if !tw_place.from_next {
                             tw_data.twimage_count = tw_data.twimage_count + 1.0;
                         }
@@ -341,6 +709,25 @@ impl<'s> System<'s> for TwImageLoadFromCacheSystem {
     }
 }
 
+/// drop the TwPlaceHolder of the files that can't be decoded. The placeholder of next or previous
+/// is inserted on the displayed image, only the component is removed and the image stays in place,
+/// the placeholders from a drop or a scene are deleted with their entity.
+fn drop_failed_placeholders(tw_places: &mut WriteStorage<TwPlaceHolder>, entities: &Entities, failed: &HashSet<String>) {
+    let dropped = (&*tw_places, &**entities).join()
+        .filter(|(tw_place, _)| failed.contains(&tw_place.twimage_path))
+        .map(|(tw_place, entity)| (entity, tw_place.from_next, tw_place.twimage_path.clone()))
+        .collect::<Vec<_>>();
+    for (entity, from_next, path) in dropped {
+        if from_next {
+            tw_places.remove(entity);
+            warn!("{:?} can't be loaded, the current TwImage is kept", &path);
+        } else {
+            entities.delete(entity).expect("Failed to delete TwPlaceHolder.");
+            warn!("TwPlaceHolder of {:?} is dropped, the file can't be loaded", &path);
+        }
+    }
+}
+
 #[derive(SystemDesc)]
 pub struct TwImageNextSystem;
 /// when arrows key are pushed, the current entity's TwImage is deleted and a new TwPlaceHolder
@@ -371,7 +758,7 @@ impl<'s> System<'s> for TwImageNextSystem {
                         if index < tower_data.files_order.len() as i16 {
                             let new_path = tower_data.files_order[index as usize].clone();
                             debug!("Next TwImage is loading {:?}", new_path);
-                            world.insert(*active_entity, TwPlaceHolder { from_next: true, to_cache: true, twimage_path: new_path.to_str().unwrap().to_owned() });
+                            world.insert(*active_entity, TwPlaceHolder { from_next: true, to_cache: true, twimage_path: new_path.to_str().unwrap().to_owned(), inherit: Some(tw_image.clone()) });
                             tower_data.file_to_cache.push(new_path);
                         }
                     }
@@ -389,7 +776,7 @@ impl<'s> System<'s> for TwImageNextSystem {
                         if index >= 0 {
                             let new_path = tower_data.files_order[index as usize].clone();
                             debug!("Next TwImage is loading {:?}", new_path);
-                            world.insert(*active_entity, TwPlaceHolder { from_next: true, to_cache: true, twimage_path: new_path.to_str().unwrap().to_owned() });
+                            world.insert(*active_entity, TwPlaceHolder { from_next: true, to_cache: true, twimage_path: new_path.to_str().unwrap().to_owned(), inherit: Some(tw_image.clone()) });
                             tower_data.file_to_cache.push(new_path);
                         }
                     }
@@ -399,3 +786,42 @@ impl<'s> System<'s> for TwImageNextSystem {
         }
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use amethyst::ecs::WorldExt;
+
+    fn placeholder(path: &str, from_next: bool, inherit: Option<TwImage>) -> TwPlaceHolder {
+        TwPlaceHolder { from_next, to_cache: true, twimage_path: path.to_owned(), inherit }
+    }
+
+    #[test]
+    fn failed_next_keeps_the_active_image() {
+        let mut world = World::new();
+        world.register::<TwImage>();
+        world.register::<TwPlaceHolder>();
+        let tw_image = TwImage::new(400, 200, "a.png");
+        let active = world.create_entity().with(tw_image.clone()).build();
+        // next on the active image reaches a file that can't be decoded
+        world.write_storage::<TwPlaceHolder>()
+            .insert(active, placeholder("broken.png", true, Some(tw_image)))
+            .unwrap();
+        let dropped = world.create_entity().with(placeholder("broken.png", false, None)).build();
+        let waiting = world.create_entity().with(placeholder("b.png", false, None)).build();
+        let failed = vec!["broken.png".to_owned()].into_iter().collect::<HashSet<_>>();
+        {
+            let mut tw_places = world.write_storage::<TwPlaceHolder>();
+            let entities = world.entities();
+            drop_failed_placeholders(&mut tw_places, &entities, &failed);
+        }
+        world.maintain();
+        assert!(world.is_alive(active));
+        assert!(world.read_storage::<TwPlaceHolder>().get(active).is_none());
+        assert_eq!(world.read_storage::<TwImage>().get(active).map(|tw_image| tw_image.file_name.as_str()), Some("a.png"));
+        assert!(!world.is_alive(dropped));
+        assert!(world.is_alive(waiting));
+        assert!(world.read_storage::<TwPlaceHolder>().get(waiting).is_some());
+    }
+}
@@ -97,6 +97,38 @@ pub fn ctrl_mouse_released(event: &Event) -> Option<MouseButton> {
     }
 }
 
+/// match if shift key and mousse pressed, return an option of the mouse button pressed.
+pub fn shift_mouse_pressed(event: &Event) -> Option<MouseButton> {
+    match *event {
+        Event::WindowEvent { ref event, .. } => match event {
+            WindowEvent::MouseInput { state: ElementState::Pressed, button, modifiers: ModifiersState {
+                shift: true,
+                ctrl: false,
+                alt: false,
+                logo: false}, ..
+            } => Some(button.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// match if shift key and mousse released, return an option of the mouse button released.
+pub fn shift_mouse_released(event: &Event) -> Option<MouseButton> {
+    match *event {
+        Event::WindowEvent { ref event, .. } => match event {
+            WindowEvent::MouseInput { state: ElementState::Released, button, modifiers: ModifiersState {
+                shift: true,
+                ctrl: false,
+                alt: false,
+                logo: false}, ..
+            } => Some(button.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 /// return option of the current mouse button pressed
 pub fn mouse_pressed(event: &Event) -> Option<MouseButton> {
     match *event {
@@ -171,6 +203,7 @@ pub struct TwInputsHandler {
     pub mouse_position_history: Vec<(f32, f32)>,
     pub alt_mouse_button_pressed: Option<MouseButton>,
     pub ctrl_mouse_button_pressed: Option<MouseButton>,
+    pub shift_mouse_button_pressed: Option<MouseButton>,
     pub keys_pressed: Vec<VirtualKeyCode>,
     pub last_key_released: Option<VirtualKeyCode>,
     pub twimages_under_mouse: Vec<(Uuid, f32)>,
@@ -179,6 +212,8 @@ pub struct TwInputsHandler {
     pub z_ordered_entities: Vec<Entity>,
     pub window_zoom_factor: f32,
     pub active_busy: bool,
+    /// an imgui text field has the keyboard, the keys are not board shortcuts
+    pub ui_wants_keyboard: bool,
 }
 
 impl TwInputsHandler {
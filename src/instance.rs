@@ -0,0 +1,211 @@
+/// instance.rs contains the single instance mode, enabled with --single-instance.
+/// The first Tower listens on a local Unix socket, a second invocation sends it its inputs then
+/// exits, the running Tower opens them like dropped files.
+///
+/// Protocol, plain UTF-8 text over the socket $XDG_RUNTIME_DIR/tower.sock (or
+/// /tmp/tower-$USER.sock without runtime dir):
+/// - the client writes one input per line, absolute paths, URLs or .tower scenes
+/// - the client shuts down its write side to end the message
+/// - the server answers "ok <count>\n" with the count of received inputs, then closes
+/// - a client silent for INSTANCE_TIMEOUT is disconnected, each client has its own thread
+/// Any client can drive it, for example: printf '/tmp/a.png\n' | nc -N -U $XDG_RUNTIME_DIR/tower.sock
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::archive::resolve_input;
+use crate::download::is_url;
+
+
+/// path of a Tower socket in the runtime directory, or in the temporary directory per user.
+pub fn runtime_socket(name: &str) -> PathBuf {
+    match dirs::runtime_dir() {
+        Some(dir) => dir.join(format!("{}.sock", name)),
+        None => std::env::temp_dir().join(format!("{}-{}.sock", name, std::env::var("USER").unwrap_or_default())),
+    }
+}
+
+
+pub fn socket_path() -> PathBuf {
+    runtime_socket("tower")
+}
+
+
+// TODO: timeout as settings
+const INSTANCE_TIMEOUT: Duration = Duration::from_secs(10);
+
+
+/// inputs as sent over the socket, the paths are made absolute as the running Tower has its own
+/// current directory, stdin is read here.
+fn absolute_inputs(inputs: &[String]) -> Vec<String> {
+    inputs.iter()
+        .map(|input| resolve_input(input))
+        .map(|input| {
+            if is_url(&input) { return input }
+            std::fs::canonicalize(Path::new(&input))
+                .map_or(input, |path| path.to_string_lossy().into_owned())
+        })
+        .collect()
+}
+
+
+/// send the inputs to the running Tower, false if no Tower is listening.
+#[cfg(unix)]
+pub fn send_to_running_instance(inputs: &[String]) -> io::Result<bool> {
+    send_inputs(&socket_path(), &absolute_inputs(inputs))
+}
+
+
+#[cfg(unix)]
+fn send_inputs(path: &Path, inputs: &[String]) -> io::Result<bool> {
+    use std::io::{Read, Write};
+    use std::net::Shutdown;
+    use std::os::unix::net::UnixStream;
+
+    let mut stream = match UnixStream::connect(path) {
+        Ok(stream) => stream,
+        Err(_e) => return Ok(false)
+    };
+    stream.set_read_timeout(Some(INSTANCE_TIMEOUT))?;
+    for input in inputs {
+        writeln!(stream, "{}", input)?;
+    }
+    stream.shutdown(Shutdown::Write)?;
+    let mut answer = String::new();
+    stream.read_to_string(&mut answer)?;
+    if !answer.starts_with("ok") {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Unexpected answer {:?}", answer)))
+    }
+    Ok(true)
+}
+
+
+/// listen on the socket in a new thread, every received input is pushed in the queue.
+/// A socket left by a crashed Tower is replaced.
+#[cfg(unix)]
+pub fn start_instance_server(queue: Arc<Mutex<Vec<String>>>) -> io::Result<()> {
+    listen_instance(&socket_path(), queue)
+}
+
+
+#[cfg(unix)]
+fn listen_instance(path: &Path, queue: Arc<Mutex<Vec<String>>>) -> io::Result<()> {
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(io::ErrorKind::AddrInUse, "An other Tower is already listening"))
+        }
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    info!("Single instance listening on {:?}", path);
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("Single instance connection failed: {:?}", e);
+                    continue
+                }
+            };
+            // a client that never ends its message doesn't hold the next ones
+            let queue = Arc::clone(&queue);
+            std::thread::spawn(move || {
+                if let Err(e) = answer_client(stream, &queue) {
+                    warn!("Single instance failed to answer: {:?}", e);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+
+/// read the inputs of a client until it shuts down its write side or the timeout, the inputs
+/// read before the timeout are kept.
+#[cfg(unix)]
+fn answer_client(mut stream: std::os::unix::net::UnixStream, queue: &Arc<Mutex<Vec<String>>>) -> io::Result<()> {
+    use std::io::{BufRead, BufReader, Write};
+
+    stream.set_read_timeout(Some(INSTANCE_TIMEOUT))?;
+    let mut inputs = Vec::new();
+    for line in BufReader::new(&stream).lines() {
+        match line {
+            Ok(line) => if !line.trim().is_empty() { inputs.push(line.trim().to_owned()) },
+            Err(e) => {
+                warn!("Single instance client stopped: {:?}", e);
+                break
+            }
+        }
+    }
+    debug!("Single instance received {:?}", &inputs);
+    let count = inputs.len();
+    queue.lock().unwrap().extend(inputs);
+    writeln!(stream, "ok {}", count)
+}
+
+
+#[cfg(not(unix))]
+pub fn send_to_running_instance(_inputs: &[String]) -> io::Result<bool> {
+    Err(io::Error::new(io::ErrorKind::Other, "Single instance mode needs Unix sockets"))
+}
+
+
+#[cfg(not(unix))]
+pub fn start_instance_server(_queue: Arc<Mutex<Vec<String>>>) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other, "Single instance mode needs Unix sockets"))
+}
+
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixStream;
+    use std::thread;
+
+    #[test]
+    fn inputs_sent_to_the_server() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tower.sock");
+        let queue = Arc::new(Mutex::new(Vec::new()));
+        listen_instance(&path, Arc::clone(&queue)).unwrap();
+        // a second server on the same socket is refused
+        assert_eq!(listen_instance(&path, Arc::new(Mutex::new(Vec::new()))).unwrap_err().kind(), io::ErrorKind::AddrInUse);
+        // a stub client that never ends its message
+        let mut silent = UnixStream::connect(&path).unwrap();
+        writeln!(silent, "/tmp/silent.png").unwrap();
+        let inputs = vec!["/tmp/a.png".to_owned(), "https://example.com/b.jpg".to_owned()];
+        assert!(send_inputs(&path, &inputs).unwrap());
+        assert_eq!(*queue.lock().unwrap(), inputs);
+        drop(silent);
+        for _ in 0..100 {
+            if queue.lock().unwrap().len() == 3 { break }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(queue.lock().unwrap().last().unwrap(), "/tmp/silent.png");
+    }
+
+    #[test]
+    fn answer_of_a_raw_client() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tower.sock");
+        let queue = Arc::new(Mutex::new(Vec::new()));
+        listen_instance(&path, Arc::clone(&queue)).unwrap();
+        let mut stream = UnixStream::connect(&path).unwrap();
+        stream.write_all(b"/tmp/a.png\n\n  /tmp/b.png  \n").unwrap();
+        stream.shutdown(std::net::Shutdown::Write).unwrap();
+        let mut answer = String::new();
+        stream.read_to_string(&mut answer).unwrap();
+        assert_eq!(answer, "ok 2\n");
+        assert_eq!(*queue.lock().unwrap(), vec!["/tmp/a.png".to_owned(), "/tmp/b.png".to_owned()]);
+    }
+
+    #[test]
+    fn no_server_listening() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(!send_inputs(&dir.path().join("tower.sock"), &["/tmp/a.png".to_owned()]).unwrap());
+    }
+}
@@ -0,0 +1,70 @@
+/// layout.rs contains the pure layout functions, they place sizes on the board without any entity
+/// or window, so they are shared by TwImageLayoutSystem and the headless contact sheet.
+
+
+/// a grid of cells, the cell size include the offset between two cells.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TwGridLayout {
+    pub columns: usize,
+    pub rows: usize,
+    pub cell_width: f32,
+    pub cell_height: f32,
+}
+
+
+/// spread the sizes as a grid, try to get a square grid as mush as possible.
+/// without a columns count, get the ceil of the sqrt of the sizes count to defined the size of the grid.
+/// the size of each cell is the max height and max width from all sizes plus the offset.
+/// return the grid and the (column, row) cell of each size, in the same order.
+pub fn grid_layout(sizes: &[(f32, f32)], columns: Option<usize>, offset: f32) -> (TwGridLayout, Vec<(usize, usize)>) {
+    let count = sizes.len();
+    let columns = columns.filter(|columns| *columns > 0)
+        .unwrap_or_else(|| ((count as f32).sqrt().ceil() as usize).max(2));
+    let rows = (count + columns - 1) / columns;
+    let cell_width = sizes.iter().map(|size| size.0).fold(0.0, f32::max) + offset;
+    let cell_height = sizes.iter().map(|size| size.1).fold(0.0, f32::max) + offset;
+    let cells = (0..count).map(|i| (i % columns, i / columns)).collect();
+    (TwGridLayout { columns, rows, cell_width, cell_height }, cells)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_of_nothing() {
+        let (grid, cells) = grid_layout(&[], None, 10.0);
+        assert_eq!(grid, TwGridLayout { columns: 2, rows: 0, cell_width: 10.0, cell_height: 10.0 });
+        assert!(cells.is_empty());
+    }
+
+    #[test]
+    fn grid_of_a_single_image() {
+        let (grid, cells) = grid_layout(&[(640.0, 480.0)], None, 10.0);
+        assert_eq!(grid, TwGridLayout { columns: 2, rows: 1, cell_width: 650.0, cell_height: 490.0 });
+        assert_eq!(cells, vec![(0, 0)]);
+    }
+
+    #[test]
+    fn grid_cells_fit_the_widest_and_the_tallest() {
+        // landscape, portrait and square images share the same cell
+        let (grid, cells) = grid_layout(&[(400.0, 200.0), (100.0, 300.0), (250.0, 250.0)], None, 0.0);
+        assert_eq!(grid, TwGridLayout { columns: 2, rows: 2, cell_width: 400.0, cell_height: 300.0 });
+        assert_eq!(cells, vec![(0, 0), (1, 0), (0, 1)]);
+    }
+
+    #[test]
+    fn grid_columns_count() {
+        let sizes = vec![(100.0, 100.0); 10];
+        // square as much as possible without columns count
+        let (grid, _) = grid_layout(&sizes, None, 0.0);
+        assert_eq!((grid.columns, grid.rows), (4, 3));
+        let (grid, cells) = grid_layout(&sizes, Some(3), 0.0);
+        assert_eq!((grid.columns, grid.rows), (3, 4));
+        assert_eq!(cells[2], (2, 0));
+        assert_eq!(cells[9], (0, 3));
+        let (grid, _) = grid_layout(&sizes, Some(0), 0.0);
+        assert_eq!(grid.columns, 4);
+    }
+}
@@ -19,37 +19,76 @@ use amethyst::{
     input::{InputBundle, StringBindings},
 };
 use amethyst_imgui::RenderImgui;
+use structopt::StructOpt;
 
 mod args_cli;
 mod image;
+mod decoder;
+mod texture;
+mod svg;
+mod svg_system;
+mod web_formats;
+mod metadata;
 mod image_system;
 mod camera;
 mod camera_system;
 mod inputshandler;
 mod tower;
 mod utils;
+mod scene;
 mod scene_system;
 mod raycasting_system;
 mod ui_system;
 mod placeholder;
 mod placeholder_system;
+mod annotation;
+mod annotation_system;
+mod export;
+mod export_system;
+mod layout;
+mod contact_sheet;
+mod thumbnail_cache;
+mod tile;
+mod tile_system;
+mod archive;
+mod download;
+mod clipboard;
+mod clipboard_system;
+mod instance;
+mod remote;
+mod remote_system;
+mod script;
+mod script_system;
 
 
+use crate::args_cli::{Opt, Command};
+use crate::contact_sheet::contact_sheet;
 use crate::tower::{Tower, BACKGROUNDCOLOR, BACKGROUNDCOLOR2};
 use crate::camera_system::{CameraTranslateNavigationSystem, CameraKeepRatioSystem, CameraZoomNavigationSystem, CameraFitNavigationSystem, CameraCenterSystem, CameraOriginalScaleSystem};
 use crate::image_system::{TwImageMoveSystem, TwImageLayoutSystem, TwImageDeleteSystem,
                           TwImageToFrontSystem, TwImageApplyBlendingSystem, TwImageLoadFromCacheSystem,
-                          TwImageNextSystem, TwImageRotateSystem};
+                          TwImageNextSystem, TwImageRotateSystem, TwImageFlipSystem, TwImageScaleSystem,
+                          TwImageCropSystem, TwImageFilterSystem, TwImageLevelSystem};
 use crate::raycasting_system::{TwImageActiveSystem, TwInputsHandlerScreenToWorldSystem};
-use crate::scene_system::{SceneBoundingBox};
-use crate::ui_system::{SliderChannelsSystem};
-use crate::placeholder_system::{TwCachingImages, TwImageDroppedSystem};
+use crate::scene_system::{SceneBoundingBox, SceneSaveSystem};
+use crate::ui_system::{UiKeyboardCaptureSystem, SliderChannelsSystem, FilmstripSystem, TextureSurfaceSystem, FramesSystem, LayersSystem, MetadataSystem};
+use crate::export_system::TwExportSystem;
+use crate::instance::send_to_running_instance;
+use crate::clipboard_system::TwClipboardSystem;
+use crate::remote_system::TwRemoteSystem;
+use crate::script_system::TwScriptSystem;
+use crate::tile_system::TwTileStreamingSystem;
+use crate::svg_system::TwSvgRasterSystem;
+use crate::placeholder_system::{TwCachingImages, TwImageDroppedSystem, TwCachingThumbnails, TwPlaceHolderPreviewSystem,
+                                TwDownloadSystem};
+use crate::annotation_system::{TwAnnotationCreateSystem, TwAnnotationDrawSystem, TwAnnotationEditSystem};
 
 
 /// Entry point of tower program.
 /// Create the logger of tower and one for amethyst engine.
 /// Init the main loop of the program.
 /// Manage the display from display.ron
+/// Run the headless subcommands, like contact-sheet, without creating any window
 /// Add all the tower systems to the GameDataBuilder
 /// And finally run the loop application
 fn main() -> amethyst::Result<()> {
@@ -65,39 +104,83 @@ fn main() -> amethyst::Result<()> {
     })
     .filter(None, LevelFilter::Debug)
     .init();
-    
+
+    let opt = Opt::from_args();
+    // headless subcommands
+    if let Some(Command::ContactSheet(sheet_opt)) = &opt.command {
+        return contact_sheet(sheet_opt).map_err(amethyst::Error::from_string);
+    }
+    if opt.single_instance {
+        match send_to_running_instance(&opt.inputs) {
+            Ok(true) => {
+                info!("Inputs sent to the running Tower");
+                return Ok(())
+            }
+            Ok(false) => debug!("No running Tower, this one becomes the single instance"),
+            Err(e) => warn!("Failed to send the inputs to the running Tower: {:?}", e),
+        }
+    }
+
     amethyst::start_logger(Default::default());
     let app_root = application_root_dir()?;
     let config_dir = app_root.join("config");
     // the display size present in the ron is not changeable, Z MAGICNUMBER is dependent of this size.
     let display_config_path = config_dir.join("display.ron");
     let tower_data = GameDataBuilder::default()
+        // Keyboard captured by the UI text fields
+        .with(UiKeyboardCaptureSystem, "ui_keyboard_capture_system", &[])
         // Active image system
         .with(TwImageActiveSystem::default(), "image_active_system", &[])
         // debug
 //        .with(DebugLinesSystem, "ex", &[])
+        // Remote control, before the systems executing its actions
+        .with(TwRemoteSystem, "remote_system", &[])
+        // Scripts
+        .with(TwScriptSystem::default(), "script_system", &["image_active_system"])
         // Camera system
         .with(CameraTranslateNavigationSystem::default(), "camera_translate_system", &[])
         .with(CameraKeepRatioSystem{previous_size: LogicalSize{width: 0.0, height: 0.0}}, "camera_ratio_system", &[])
         .with(CameraZoomNavigationSystem::default(), "camera_zoom_system", &["image_active_system"])
-        .with(CameraFitNavigationSystem, "camera_fit_system", &["image_active_system"])
+        .with(CameraFitNavigationSystem, "camera_fit_system", &["image_active_system", "remote_system"])
         .with(CameraCenterSystem::default(), "camera_center_system", &["image_active_system"])
         .with(CameraOriginalScaleSystem, "camera_original_system", &["image_active_system"])
         // Image system
-        .with(TwImageLayoutSystem::default(), "image_layout_system", &["image_active_system"])
+        .with(TwImageLayoutSystem::default(), "image_layout_system", &["image_active_system", "remote_system"])
         .with(TwImageDeleteSystem, "image_delete_system", &["image_active_system"])
         .with(SceneBoundingBox::default(), "scene_bounding_system", &["image_active_system"])
         .with(TwImageToFrontSystem, "image_tofront_system", &["image_active_system"])
         .with(TwImageApplyBlendingSystem, "image_apply_blending_system", &["image_active_system"])
         .with(TwImageMoveSystem::default(), "image_move_system", &["image_active_system"])
         .with(TwImageRotateSystem::default(), "image_rotate_system", &["image_active_system"])
+        .with(TwImageFlipSystem, "image_flip_system", &["image_active_system"])
+        .with(TwImageFilterSystem, "image_filter_system", &["image_active_system"])
+        .with(TwImageScaleSystem::default(), "image_scale_system", &["image_active_system"])
+        .with(TwImageLevelSystem::default(), "image_level_system", &["image_filter_system", "image_scale_system"])
+        .with(TwImageCropSystem::default(), "image_crop_system", &["image_active_system"])
+        .with(SceneSaveSystem, "scene_save_system", &["image_active_system", "remote_system"])
         .with(TwImageDroppedSystem, "dropped_images", &[])
-        .with(TwCachingImages::default(), "caching_image_system", &["dropped_images"])
+        .with(TwClipboardSystem::default(), "clipboard_system", &["image_active_system"])
+        .with(TwDownloadSystem, "download_system", &["dropped_images"])
+        .with(TwCachingImages::default(), "caching_image_system", &["dropped_images", "download_system"])
         .with(TwImageLoadFromCacheSystem, "image_load_from_cache", &["caching_image_system"])
+        .with(TwCachingThumbnails::default(), "caching_thumbnail_system", &["dropped_images"])
+        .with(TwPlaceHolderPreviewSystem::default(), "placeholder_preview_system", &["caching_image_system"])
+        .with(TwTileStreamingSystem, "tile_streaming_system", &["image_load_from_cache"])
+        .with(TwSvgRasterSystem::default(), "svg_raster_system", &["image_load_from_cache", "camera_zoom_system"])
         .with(TwImageNextSystem, "image_next_cache", &[])
         .with(TwInputsHandlerScreenToWorldSystem, "convert_screen_to_world", &[])
+        // Annotation system
+        .with(TwAnnotationCreateSystem::default(), "annotation_create_system", &["image_active_system"])
+        .with(TwAnnotationEditSystem::default(), "annotation_edit_system", &["image_active_system"])
+        .with(TwAnnotationDrawSystem, "annotation_draw_system", &["annotation_create_system", "annotation_edit_system"])
         // UI
         .with(SliderChannelsSystem{open: false}, "slider_alpha_system", &["image_active_system"])
+        .with(TwExportSystem::default(), "export_system", &["scene_bounding_system", "remote_system"])
+        .with(FilmstripSystem::default(), "filmstrip_system", &["image_active_system"])
+        .with(TextureSurfaceSystem::default(), "texture_surface_system", &["image_active_system"])
+        .with(FramesSystem::default(), "frames_system", &["image_active_system"])
+        .with(LayersSystem::default(), "layers_system", &["image_active_system"])
+        .with(MetadataSystem::default(), "metadata_system", &["image_active_system"])
         // bundle + plugins
         .with_bundle(TransformBundle::new())?
         .with_bundle(InputBundle::<StringBindings>::new())?
@@ -0,0 +1,317 @@
+/// metadata.rs contains the reading of the image metadata shown by the metadata panel, file info,
+/// color type and bit depth, embedded ICC profile name, EXIF and EXR header attributes.
+/// The metadata are read by the loader thread with the pixels and stored in the cached TwImage.
+use flate2::read::ZlibDecoder;
+
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use crate::decoder::split_sub_image;
+use crate::image::TwPixels;
+use crate::texture::{TwTextureFormat, read_texture_header};
+
+
+/// what an image file is.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct TwMetadata {
+    pub path: String,
+    pub file_size: u64,
+    pub dimensions: (u32, u32),
+    pub color_type: String,
+    pub bit_depth: u32,
+    pub icc_profile: Option<String>,
+    pub exif: Vec<(String, String)>,
+    pub exr_attributes: Vec<(String, String)>,
+}
+
+
+// TODO: header size as settings
+const HEADER_SIZE: u64 = 1 << 20;
+
+
+fn extension(path: &Path) -> String {
+    path.extension().map_or(String::new(), |ext| ext.to_string_lossy().to_lowercase())
+}
+
+
+/// first bytes of a file, enough for the headers, the ICC profiles and the EXR attributes without
+/// reading the pixels.
+fn read_header(path: &Path) -> Vec<u8> {
+    let mut header = Vec::new();
+    if let Ok(file) = File::open(path) {
+        if let Err(e) = file.take(HEADER_SIZE).read_to_end(&mut header) {
+            warn!("Failed to read the header of {:?}: {:?}", path, e);
+        }
+    }
+    header
+}
+
+
+/// read the metadata of a file, the pixels give the color type when the header is not parsed.
+pub fn read_metadata(name: &str, tw_pixels: &TwPixels) -> TwMetadata {
+    let (path, _) = split_sub_image(Path::new(name));
+    let data = read_header(&path);
+    let (color_type, bit_depth) = color_from_header(&path, &data).unwrap_or_else(|| color_from_pixels(tw_pixels));
+    TwMetadata {
+        path: name.to_owned(),
+        file_size: fs::metadata(&path).map(|metadata| metadata.len()).unwrap_or(0),
+        dimensions: (tw_pixels.width, tw_pixels.height),
+        color_type,
+        bit_depth,
+        icc_profile: read_icc_profile(&path, &data),
+        exif: read_exif(&path),
+        exr_attributes: if extension(&path) == "exr" { read_exr_attributes(&data) } else { Vec::new() },
+    }
+}
+
+
+/// color type and bit depth of the file, from the header of the formats read by Tower.
+fn color_from_header(path: &Path, data: &[u8]) -> Option<(String, u32)> {
+    match extension(path).as_str() {
+        "png" => {
+            let color_type = match data.get(25)? {
+                0 => "Gray",
+                2 => "RGB",
+                3 => "Indexed",
+                4 => "Gray alpha",
+                6 => "RGBA",
+                _ => return None
+            };
+            Some((color_type.to_owned(), *data.get(24)? as u32))
+        }
+        "jpg" | "jpeg" => {
+            let (precision, components) = jpeg_frame(data)?;
+            let color_type = match components {
+                1 => "Gray",
+                3 => "YCbCr",
+                4 => "CMYK",
+                _ => return None
+            };
+            Some((color_type.to_owned(), precision as u32))
+        }
+        "psd" => {
+            let channels = u16::from_be_bytes([*data.get(12)?, *data.get(13)?]);
+            let depth = u16::from_be_bytes([*data.get(22)?, *data.get(23)?]);
+            let mode = match u16::from_be_bytes([*data.get(24)?, *data.get(25)?]) {
+                0 => "Bitmap",
+                1 => "Grayscale",
+                2 => "Indexed",
+                3 => "RGB",
+                4 => "CMYK",
+                7 => "Multichannel",
+                8 => "Duotone",
+                9 => "Lab",
+                _ => return None
+            };
+            Some((format!("{}, {} channels", mode, channels), depth as u32))
+        }
+        "dds" | "ktx2" => {
+            let texture = read_texture_header(path).ok()?;
+            let color_type = format!("{:?}{}, {} mip levels, {} faces", texture.format, if texture.srgb { " sRGB" } else { "" },
+                                     texture.levels, texture.faces);
+            Some((color_type, if texture.format == TwTextureFormat::Bc6h { 16 } else { 8 }))
+        }
+        _ => None
+    }
+}
+
+
+/// precision and components of the frame header of a jpeg.
+fn jpeg_frame(data: &[u8]) -> Option<(u8, u8)> {
+    let mut i = 2;
+    while i + 9 < data.len() {
+        if data[i] != 0xff { return None }
+        let marker = data[i + 1];
+        let length = u16::from_be_bytes([data[i + 2], data[i + 3]]) as usize;
+        // start of frame markers, the others are huffman and arithmetic tables
+        if (0xc0..=0xcf).contains(&marker) && marker != 0xc4 && marker != 0xc8 && marker != 0xcc {
+            return Some((data[i + 4], data[i + 9]))
+        }
+        i += 2 + length;
+    }
+    None
+}
+
+
+/// color type of the decoded pixels.
+fn color_from_pixels(tw_pixels: &TwPixels) -> (String, u32) {
+    let format = format!("{:?}", tw_pixels.format);
+    let color_type = if format.starts_with("Rgba") { "RGBA" } else if format.starts_with("Rgb") { "RGB" } else { "Gray" };
+    let bit_depth = tw_pixels.sample_size() as u32 * 8;
+    let kind = if format.ends_with("Sfloat") { " float" } else { "" };
+    (format!("{}{}", color_type, kind), bit_depth)
+}
+
+
+/// name of the embedded ICC profile of png and jpeg files.
+fn read_icc_profile(path: &Path, data: &[u8]) -> Option<String> {
+    match extension(path).as_str() {
+        "png" => {
+            let mut i = 8;
+            while i + 8 <= data.len() {
+                let length = u32::from_be_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]) as usize;
+                let chunk = data.get(i + 8..i + 8 + length)?;
+                if &data[i + 4..i + 8] == b"iCCP" {
+                    let end = chunk.iter().position(|b| *b == 0)?;
+                    let keyword = String::from_utf8_lossy(&chunk[..end]).into_owned();
+                    // the keyword is the profile name given by the writer, the description is better
+                    let mut icc = Vec::new();
+                    ZlibDecoder::new(chunk.get(end + 2..)?).read_to_end(&mut icc).ok()?;
+                    return icc_description(&icc).or(Some(keyword))
+                }
+                if &data[i + 4..i + 8] == b"IDAT" { return None }
+                i += 12 + length;
+            }
+            None
+        }
+        "jpg" | "jpeg" => {
+            // the profile can be split in several APP2 segments
+            let mut icc = Vec::new();
+            let mut i = 2;
+            while i + 4 <= data.len() && data[i] == 0xff && data[i + 1] != 0xda {
+                let length = u16::from_be_bytes([data[i + 2], data[i + 3]]) as usize;
+                let segment = data.get(i + 4..i + 2 + length)?;
+                if data[i + 1] == 0xe2 && segment.starts_with(b"ICC_PROFILE\0") {
+                    icc.extend_from_slice(segment.get(14..)?);
+                }
+                i += 2 + length;
+            }
+            if icc.is_empty() { None } else { icc_description(&icc) }
+        }
+        _ => None
+    }
+}
+
+
+/// description tag of an ICC profile, desc of the version 2 or mluc of the version 4.
+fn icc_description(icc: &[u8]) -> Option<String> {
+    let u32_at = |i: usize| icc.get(i..i + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize);
+    let count = u32_at(128)?;
+    for entry in (0..count).map(|n| 132 + n * 12) {
+        if icc.get(entry..entry + 4)? != b"desc" { continue }
+        let (offset, size) = (u32_at(entry + 4)?, u32_at(entry + 8)?);
+        let tag = icc.get(offset..offset + size)?;
+        return match tag.get(0..4)? {
+            b"desc" => {
+                let length = u32_at(offset + 8)?;
+                let text = tag.get(12..12 + length)?;
+                Some(String::from_utf8_lossy(text).trim_end_matches('\0').to_owned())
+            }
+            b"mluc" => {
+                // first record, utf-16 big endian
+                let (length, start) = (u32_at(offset + 20)?, u32_at(offset + 24)?);
+                let text = tag.get(start..start + length)?.chunks(2)
+                    .map(|c| u16::from_be_bytes([c[0], *c.get(1).unwrap_or(&0)]))
+                    .collect::<Vec<_>>();
+                Some(String::from_utf16_lossy(&text).trim_end_matches('\0').to_owned())
+            }
+            _ => None
+        }
+    }
+    None
+}
+
+
+/// camera, exposure, date and orientation from the EXIF of the file.
+fn read_exif(path: &Path) -> Vec<(String, String)> {
+    let exif = match File::open(path).map_err(exif::Error::Io)
+        .and_then(|file| exif::Reader::new().read_from_container(&mut BufReader::new(file))) {
+        Ok(exif) => exif,
+        Err(_e) => return Vec::new()
+    };
+    let tags = [("Camera make", exif::Tag::Make),
+                ("Camera model", exif::Tag::Model),
+                ("Lens", exif::Tag::LensModel),
+                ("Exposure time", exif::Tag::ExposureTime),
+                ("Aperture", exif::Tag::FNumber),
+                ("ISO", exif::Tag::PhotographicSensitivity),
+                ("Focal length", exif::Tag::FocalLength),
+                ("Date", exif::Tag::DateTimeOriginal),
+                ("Orientation", exif::Tag::Orientation)];
+    tags.iter()
+        .filter_map(|(name, tag)| exif.get_field(*tag, exif::In::PRIMARY)
+            .map(|field| (name.to_string(), field.display_value().with_unit(&exif).to_string())))
+        .collect()
+}
+
+
+/// attributes of the header of an exr file, the values of the common types are shown, the
+/// others only with their type.
+fn read_exr_attributes(data: &[u8]) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    if !data.starts_with(&[0x76, 0x2f, 0x31, 0x01]) { return attributes }
+    let read_string = |i: &mut usize| -> Option<String> {
+        let end = *i + data.get(*i..)?.iter().position(|b| *b == 0)?;
+        let text = String::from_utf8_lossy(&data[*i..end]).into_owned();
+        *i = end + 1;
+        Some(text)
+    };
+    let i32_at = |i: usize| data.get(i..i + 4).map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+    let f32_at = |i: usize| data.get(i..i + 4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+    // single part header after the magic number and the version
+    let mut i = 8;
+    loop {
+        let name = match read_string(&mut i) {
+            Some(name) if !name.is_empty() => name,
+            _ => break
+        };
+        let kind = match read_string(&mut i) {
+            Some(kind) => kind,
+            None => break
+        };
+        let size = match i32_at(i) {
+            Some(size) if size >= 0 => size as usize,
+            _ => break
+        };
+        i += 4;
+        let value = match kind.as_str() {
+            "string" => data.get(i..i + size).map(|v| String::from_utf8_lossy(v).into_owned()),
+            "int" => i32_at(i).map(|v| v.to_string()),
+            "float" => f32_at(i).map(|v| v.to_string()),
+            "v2f" => f32_at(i).and_then(|x| f32_at(i + 4).map(|y| format!("{} {}", x, y))),
+            "box2i" => (0..4).map(|n| i32_at(i + n * 4)).collect::<Option<Vec<_>>>()
+                .map(|b| format!("({}, {}) - ({}, {})", b[0], b[1], b[2], b[3])),
+            "compression" => data.get(i).map(|c| match c {
+                0 => "none", 1 => "rle", 2 => "zips", 3 => "zip", 4 => "piz", 5 => "pxr24",
+                6 => "b44", 7 => "b44a", 8 => "dwaa", 9 => "dwab", _ => "unknown",
+            }.to_owned()),
+            "chlist" => {
+                // channel names separated by the pixel type, sampling and reserved bytes
+                let mut j = i;
+                let mut names = Vec::new();
+                while let Some(channel) = read_string(&mut j) {
+                    if channel.is_empty() { break }
+                    names.push(channel);
+                    j += 16;
+                }
+                Some(names.join(", "))
+            }
+            _ => Some(format!("<{}>", kind)),
+        };
+        attributes.push((name, value.unwrap_or_default()));
+        i += size;
+    }
+    attributes
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exr::prelude::write_rgba_file;
+
+    #[test]
+    fn exr_attributes_from_the_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pixels.exr");
+        write_rgba_file(&path, 64, 32, |_, _| (0.5, 0.5, 0.5, 1.0)).unwrap();
+        let header = read_header(&path);
+        let attributes = read_exr_attributes(&header);
+        let value = |name: &str| attributes.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str());
+        assert_eq!(value("channels"), Some("A, B, G, R"));
+        assert_eq!(value("dataWindow"), Some("(0, 0) - (63, 31)"));
+        assert!(value("compression").is_some());
+    }
+}
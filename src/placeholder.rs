@@ -1,12 +1,15 @@
 /// contains all TwHolderPlace related function and component
 use amethyst::ecs::prelude::{Component, DenseVecStorage, };
 
+use crate::image::TwImage;
+
 
 #[derive(PartialEq, Debug)]
 pub struct TwPlaceHolder {
     pub twimage_path: String,
     pub to_cache: bool,
     pub from_next: bool,
+    pub inherit: Option<TwImage>,
 }
 
 impl Component for TwPlaceHolder {
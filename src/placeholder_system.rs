@@ -1,25 +1,39 @@
 /// contains all system related to TwPlaceHolder
-use amethyst::core::{SystemDesc, Transform};
+use amethyst::core::{SystemDesc, Transform, math::{Point3, Vector2}};
 use amethyst::derive::SystemDesc;
 use amethyst::ecs::{Join, Read, System, SystemData, World, WriteStorage};
 use amethyst::ecs::prelude::*;
 use amethyst::window::ScreenDimensions;
 use amethyst::renderer::{camera::{Camera},
-                        sprite::{SpriteSheet},
+                        sprite::{SpriteSheet, SpriteRender},
                         Texture};
 use amethyst::assets::{AssetStorage, Loader};
+use amethyst::renderer::rendy::wsi::winit::Window;
+use amethyst_imgui::{
+    imgui,
+    imgui::{Condition, ImString},
+};
 
-use std::sync::Arc;
-use std::path::Path;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::path::{Path, PathBuf};
 use std::ffi::OsString;
 use std::thread;
+use std::collections::HashSet;
 
 use crate::placeholder::{TwPlaceHolder};
 use crate::image::*;
-use crate::tower::{TowerData};
+use crate::tower::{TowerData, THUMBNAILSIZE};
 use crate::inputshandler::TwInputsHandler;
 use crate::utils::{is_valid_file, list_valid_files};
 use crate::raycasting_system::screen_to_world;
+use crate::camera::world_to_screen;
+use crate::scene::{is_scene_file, load_scene};
+use crate::annotation::TwAnnotation;
+use crate::thumbnail_cache::load_cached_thumbnail;
+use crate::decoder::probe_dimensions;
+use crate::archive::{resolve_input, is_archive_file, start_extraction};
+use crate::download::{is_url, start_download};
 
 
 #[derive(SystemDesc)]
@@ -28,8 +42,8 @@ pub struct TwImageDroppedSystem;
 /// add it to the list of TwPlaceHolder to create for each path in this list create a new TwPlaceHolder
 /// entity wih transform entity, set with mouse screen to world position.
 /// This handle also the inputs flag CLI path, add them to the list, if a path is not present in
-/// the TowerData.file_orders so it's a new working directory, so we flush the cache and generate a new one
-/// with a new TowerData.file_orders.
+/// the TowerData.file_orders its directory is added to the working set, the cache is kept and
+/// TowerData.file_orders is listed again with every directory of the set.
 impl<'s> System<'s> for TwImageDroppedSystem {
     type SystemData = (WriteExpect<'s, TwInputsHandler>,
                        Write<'s, LazyUpdate>,
@@ -54,25 +68,36 @@ impl<'s> System<'s> for TwImageDroppedSystem {
         screen_dimensions,
     ): Self::SystemData) {
         let mut path_to_load = Vec::new();
-        if let Some(drop_file) = &tw_in.last_dropped_file_path.pop() {
-            if Path::new(drop_file).is_dir() {
-                tw_data.working_dir = Path::new(drop_file).as_os_str().to_owned();
-                tw_data.file_to_cache = list_valid_files(&tw_data.working_dir);
-                tw_data.files_order = tw_data.file_to_cache.clone();
-                tw_data.cache.lock().unwrap().clear();
-                info!("New working dir: current cache cleared.");
-                for file in &tw_data.file_to_cache {
-                    let copy_file = file.clone();
-                    path_to_load.push(copy_file.into_string().unwrap());
+        let mut scene_to_load = Vec::new();
+        let mut url_to_load = Vec::new();
+        // inputs sent by an other Tower in single instance mode
+        if let Ok(mut remote_inputs) = tw_data.remote_inputs.try_lock() {
+            tw_in.last_dropped_file_path.extend(remote_inputs.drain(..));
+        }
+        // directories of the archives extracted by the threads
+        if let Ok(mut extractions) = tw_data.extractions.try_lock() {
+            tw_in.last_dropped_file_path.extend(extractions.done.drain(..));
+        }
+        if let Some(drop_file) = &tw_in.last_dropped_file_path.pop().map(|file| resolve_input(&file)) {
+            if is_url(drop_file) {
+                url_to_load.push(drop_file.clone());
+            }
+            else if is_scene_file(Path::new(drop_file)) {
+                scene_to_load.push(drop_file.clone());
+            }
+            else if is_archive_file(Path::new(drop_file)) {
+                start_extraction(Path::new(drop_file), &tw_data.extractions);
+            }
+            else if Path::new(drop_file).is_dir() {
+                tw_data.add_working_dir(Path::new(drop_file));
+                for file in list_valid_files(Path::new(drop_file).as_os_str(), &tw_data.list_options) {
+                    path_to_load.push(file.into_string().unwrap());
                 }
             }
             else {
+                // a file from an other directory adds its directory to the working set
                 if !tw_data.files_order.contains(&OsString::from(drop_file)) {
-                    tw_data.working_dir = Path::new(drop_file).parent().unwrap().as_os_str().to_owned();
-                    tw_data.file_to_cache = list_valid_files(&tw_data.working_dir);
-                    tw_data.files_order = tw_data.file_to_cache.clone();
-                    tw_data.cache.lock().unwrap().clear();
-                    info!("New working dir: current cache cleared.")
+                    tw_data.add_working_dir(Path::new(drop_file).parent().unwrap());
                 }
                 path_to_load.push(drop_file.clone());
             }
@@ -80,15 +105,19 @@ impl<'s> System<'s> for TwImageDroppedSystem {
         if !tw_data.inputs_path.is_empty() {
             while !tw_data.inputs_path.is_empty() {
                 if let Some(path) = tw_data.inputs_path.pop() {
-                    if Path::new(&path).is_dir() {
-                        tw_data.working_dir = Path::new(&path).as_os_str().to_owned();
-                        tw_data.file_to_cache = list_valid_files(&tw_data.working_dir);
-                        tw_data.files_order = tw_data.file_to_cache.clone();
-                        tw_data.cache.lock().unwrap().clear();
-                        info!("New working dir: current cache cleared.");
-                        for file in &tw_data.file_to_cache {
-                            let copy_file = file.clone();
-                            path_to_load.push(copy_file.into_string().unwrap());
+                    if is_url(&path) {
+                        url_to_load.push(path.clone());
+                    }
+                    else if is_scene_file(Path::new(&path)) {
+                        scene_to_load.push(path.clone());
+                    }
+                    else if is_archive_file(Path::new(&path)) {
+                        start_extraction(Path::new(&path), &tw_data.extractions);
+                    }
+                    else if Path::new(&path).is_dir() {
+                        tw_data.add_working_dir(Path::new(&path));
+                        for file in list_valid_files(Path::new(&path).as_os_str(), &tw_data.list_options) {
+                            path_to_load.push(file.into_string().unwrap());
                         }
                     }
                     else {
@@ -100,8 +129,40 @@ impl<'s> System<'s> for TwImageDroppedSystem {
                 }
             }
         }
-        for path in path_to_load {
-            if is_valid_file(Path::new(&path)) {
+        for scene_path in scene_to_load {
+            match load_scene(Path::new(&scene_path)) {
+                Ok(scene) => {
+                    let mut image_entities = Vec::new();
+                    for scene_image in &scene.images {
+                        image_entities.push(world.create_entity(&*entities)
+                            .with(scene_image.transform())
+                            .with(TwPlaceHolder {from_next: false, twimage_path: scene_image.path.clone(), to_cache: true,
+                                                 inherit: Some(scene_image.tw_image()) })
+                            .build());
+                        tw_data.file_to_cache.push(OsString::from(&scene_image.path));
+                    }
+                    for scene_annotation in &scene.annotations {
+                        let attached_to = scene_annotation.attached_to.and_then(|i| image_entities.get(i).cloned());
+                        let mut annotation = TwAnnotation::new(scene_annotation.kind.clone(), attached_to);
+                        annotation.points = scene_annotation.points.clone();
+                        annotation.color = scene_annotation.color;
+                        world.create_entity(&*entities)
+                            .with(annotation)
+                            .build();
+                    }
+                    tw_data.scene_path = Some(PathBuf::from(&scene_path));
+                    info!("Scene {:?} loaded with {:?} images", &scene_path, scene.images.len());
+                }
+                Err(e) => error!("Failed to load scene {:?}: {:?}", &scene_path, e)
+            }
+        }
+        // URLs are downloaded first, TwDownloadSystem send their TwPlaceHolder to cache once done
+        for url in &url_to_load {
+            start_download(url, &tw_data.downloads);
+        }
+        for (path, to_cache) in path_to_load.into_iter().map(|path| (path, true))
+            .chain(url_to_load.into_iter().map(|url| (url, false))) {
+            if !to_cache || is_valid_file(Path::new(&path)) {
                 let (camera, transform) = (&cameras, &transforms).join().next().unwrap();
                 let mut position = Transform::default();
                 if let Some(mouse_position) = tw_in.mouse_position {
@@ -113,9 +174,9 @@ impl<'s> System<'s> for TwImageDroppedSystem {
                 }
                 world.create_entity(&*entities)
                     .with(position)
-                    .with(TwPlaceHolder {from_next: false, twimage_path: path.clone(), to_cache: true })
+                    .with(TwPlaceHolder {from_next: false, twimage_path: path.clone(), to_cache, inherit: None })
                     .build();
-                tw_data.file_to_cache.push( OsString::from(&path));
+                if to_cache { tw_data.file_to_cache.push( OsString::from(&path)) }
                 debug!("TwPlaceHolder is created for path {:?}", &path);
             } else {
                 warn!("Invalid format for {:?}", &path);
@@ -133,7 +194,7 @@ pub struct TwCachingImages {
     // I guess is the LazyUpdate from TwImageDroppedSystem which create the un-sync
     pub ready_to_cache: bool
 }
-/// Run a new thread to cache TwImage and TextureData in background then put them in a Arc<Mutex<HashMap>>
+/// Run a new thread to cache TwImage and TwPixels in background then put them in a Arc<Mutex<HashMap>>
 /// Calculate as prio the TwHolderPlace that has .to_cache then all the path present in TowerData.file_to_cache
 impl<'s> System<'s> for TwCachingImages {
     type SystemData = (WriteStorage<'s, TwPlaceHolder>,
@@ -145,24 +206,238 @@ impl<'s> System<'s> for TwCachingImages {
         entities,
         mut td,
     ): Self::SystemData) {
-        if tw_holders.count() != 0 {
-            let (tw_holder, _e) = (&mut tw_holders, &*entities).join().next().unwrap();
-            if tw_holder.to_cache {
-                let cache = Arc::clone(&td.cache);
-                let path = tw_holder.twimage_path.clone();
-                thread::spawn(move || {caching_image(cache.lock().unwrap(), path);});
-                tw_holder.to_cache = false;
-                // dirty hack
-                self.ready_to_cache = true;
-            }
+        // the TwPlaceHolder of a downloading URL wait without to_cache
+        if let Some((tw_holder, _e)) = (&mut tw_holders, &*entities).join().find(|(h, _)| h.to_cache) {
+            // the thumbnail from disk cache is ready long before the full resolution decode
+            let thumbnails = Arc::clone(&td.thumbnails);
+            let path = tw_holder.twimage_path.clone();
+            thread::spawn(move || {
+                if thumbnails.lock().unwrap().contains_key(&path) { return }
+                if let Ok(thumbnail) = load_cached_thumbnail(&path, THUMBNAILSIZE) {
+                    thumbnails.lock().unwrap().insert(path, thumbnail);
+                }
+            });
+            let (cache, failed) = (Arc::clone(&td.cache), Arc::clone(&td.failed));
+            let path = tw_holder.twimage_path.clone();
+            thread::spawn(move || {caching_image(cache.lock().unwrap(), path, &failed);});
+            tw_holder.to_cache = false;
+            // dirty hack
+            self.ready_to_cache = true;
         }
 
         if tw_holders.count() == 0 && self.ready_to_cache && !td.file_to_cache.is_empty() {
             let path = td.file_to_cache.pop().unwrap();
-            let cache = Arc::clone(&td.cache);
+            let (cache, failed) = (Arc::clone(&td.cache), Arc::clone(&td.failed));
             debug!("A new thread spawned to load in cache {:?}", &path);
-            thread::spawn(move || {caching_image(cache.lock().unwrap(), path.to_str().unwrap().to_owned());});
+            thread::spawn(move || {caching_image(cache.lock().unwrap(), path.to_string_lossy().into_owned(), &failed);});
         }
     }
 }
 
+
+
+#[derive(SystemDesc, Default)]
+pub struct TwCachingThumbnails {
+    requested: HashSet<OsString>,
+}
+/// generate in background the reduced size thumbnails of all the files of TowerData.files_order
+/// then put them in TowerData.thumbnails, they are used by the files strip and the previews.
+/// thumbnails are read from the disk cache when valid.
+/// new files are sent to a single new thread, one after the other.
+impl<'s> System<'s> for TwCachingThumbnails {
+    type SystemData = (Read<'s, TowerData>,);
+    fn run(&mut self, (
+        td,
+    ): Self::SystemData) {
+        let batch = td.files_order.iter()
+            .filter(|path| !self.requested.contains(*path))
+            .cloned()
+            .collect::<Vec<_>>();
+        if batch.is_empty() { return }
+        self.requested.extend(batch.iter().cloned());
+        let thumbnails = Arc::clone(&td.thumbnails);
+        debug!("A new thread spawned to generate {:?} thumbnails", batch.len());
+        thread::spawn(move || {
+            for path in batch {
+                let path = path.to_str().unwrap().to_owned();
+                if thumbnails.lock().unwrap().contains_key(&path) { continue }
+                match load_cached_thumbnail(&path, THUMBNAILSIZE) {
+                    Ok(thumbnail) => { thumbnails.lock().unwrap().insert(path, thumbnail); }
+                    Err(e) => warn!("Failed to generate thumbnail of {:?}: {:?}", &path, e)
+                }
+            }
+        });
+    }
+}
+
+
+#[derive(SystemDesc, Default)]
+pub struct TwPlaceHolderPreviewSystem {
+    // placeholders showing the gray card, waiting for their thumbnail
+    cards: HashSet<Entity>,
+    // placeholders whose header is read by a thread
+    probing: HashSet<Entity>,
+    // placeholders whose header can't be read, waiting for their thumbnail without a card
+    failures: HashSet<Entity>,
+    probed: Arc<Mutex<Vec<(Entity, String, io::Result<(u32, u32)>)>>>,
+}
+/// progressive loading of the TwPlaceHolder while the full resolution decode runs:
+/// - a gray card sized from the image header is shown, the header is read in a new thread
+/// - the card is replaced by the low resolution thumbnail of TowerData.thumbnails when ready
+/// Both are drawn at the original image size, the sprite is removed with the placeholder entity
+/// when TwImageLoadFromCacheSystem swap it with the full resolution TwImage.
+impl<'s> System<'s> for TwPlaceHolderPreviewSystem {
+    type SystemData = (ReadStorage<'s, TwPlaceHolder>,
+                       WriteStorage<'s, SpriteRender>,
+                       Read<'s, TowerData>,
+                       Read<'s, AssetStorage<Texture>>,
+                       Read<'s, AssetStorage<SpriteSheet>>,
+                       ReadExpect<'s, Loader>,
+                       Entities<'s>);
+    fn run(&mut self, (
+        tw_places,
+        mut sprite_renders,
+        td,
+        texture_storage,
+        sprite_storage,
+        loader,
+        entities,
+    ): Self::SystemData) {
+        self.cards.retain(|entity| entities.is_alive(*entity));
+        self.probing.retain(|entity| entities.is_alive(*entity));
+        self.failures.retain(|entity| entities.is_alive(*entity));
+        let thumbnails = match td.thumbnails.try_lock() {
+            Ok(thumbnails) => thumbnails,
+            Err(_e) => return
+        };
+        let mut previews = Vec::new();
+        // headers read by the threads
+        if let Ok(mut probed) = self.probed.try_lock() {
+            for (entity, path, dimensions) in probed.drain(..) {
+                self.probing.remove(&entity);
+                // the placeholder is gone, took an other path or already shows its thumbnail
+                if tw_places.get(entity).map(|tw_place| &tw_place.twimage_path) != Some(&path) { continue }
+                if sprite_renders.get(entity).is_some() { continue }
+                match dimensions {
+                    Ok((width, height)) => {
+                        self.cards.insert(entity);
+                        debug!("Gray card preview is shown for the TwPlaceHolder {:?}", &path);
+                        previews.push((entity, TwImage::new(width, height, &path), gray_card_pixels().texture_data(td.filter)));
+                    }
+                    Err(e) => {
+                        // the header is not probed again on the next frames
+                        self.failures.insert(entity);
+                        debug!("No gray card preview for the TwPlaceHolder {:?}: {:?}", &path, e);
+                    }
+                }
+            }
+        }
+        let cards = &self.cards;
+        let waiting = (&tw_places, &*entities).join()
+            .filter(|(_, entity)| sprite_renders.get(*entity).is_none() || cards.contains(entity))
+            .map(|(tw_place, entity)| (tw_place.twimage_path.clone(), entity))
+            .collect::<Vec<_>>();
+        for (path, entity) in waiting {
+            match thumbnails.get(&path) {
+                Some((tw_image, pixels)) => {
+                    self.cards.remove(&entity);
+                    self.failures.remove(&entity);
+                    debug!("Thumbnail preview is shown for the TwPlaceHolder {:?}", &path);
+                    previews.push((entity, tw_image.clone(), pixels.texture_data(td.filter)));
+                }
+                None => {
+                    if self.cards.contains(&entity) || self.probing.contains(&entity) || self.failures.contains(&entity) { continue }
+                    // the header can be slow to read on a network share, it's read in a new thread
+                    self.probing.insert(entity);
+                    let probed = Arc::clone(&self.probed);
+                    thread::spawn(move || {
+                        let dimensions = probe_dimensions(Path::new(&path));
+                        probed.lock().unwrap().push((entity, path, dimensions));
+                    });
+                }
+            }
+        }
+        for (entity, mut tw_image, texture_data) in previews {
+            if let Some(previous) = tw_places.get(entity).and_then(|tw_place| tw_place.inherit.as_ref()) {
+                tw_image.inherit(previous);
+            }
+            let texture = loader.load_from_data(texture_data, (), &texture_storage);
+            let sprite_sheet = SpriteSheet {
+                texture,
+                sprites: vec![build_preview_sprite(&tw_image)],
+            };
+            let sprite_sheet = loader.load_from_data(sprite_sheet, (), &sprite_storage);
+            sprite_renders.insert(entity, SpriteRender { sprite_sheet, sprite_number: 0 })
+                .expect("Failed to insert preview sprite.");
+        }
+    }
+}
+
+
+#[derive(SystemDesc, Default)]
+pub struct TwDownloadSystem;
+/// follow the downloads of the TwPlaceHolder created from an URL, the progress is drawn on the
+/// placeholder. Once downloaded the placeholder takes the local file and is sent to cache, a
+/// failed download delete its placeholder.
+impl<'s> System<'s> for TwDownloadSystem {
+    type SystemData = (WriteStorage<'s, TwPlaceHolder>,
+                       ReadStorage<'s, Transform>,
+                       ReadStorage<'s, Camera>,
+                       ReadExpect<'s, Window>,
+                       Read<'s, TowerData>,
+                       Entities<'s>);
+    fn run(&mut self, (
+        mut tw_places,
+        transforms,
+        cameras,
+        window,
+        td,
+        entities,
+    ): Self::SystemData) {
+        let downloads = match td.downloads.try_lock() {
+            Ok(downloads) => downloads,
+            Err(_e) => return
+        };
+        let (camera, cam_transform) = (&cameras, &transforms).join().next().unwrap();
+        let win_size = window.get_inner_size().unwrap();
+        let diag = Vector2::new(win_size.width as f32, win_size.height as f32);
+        for (tw_place, transform, entity) in (&mut tw_places, &transforms, &*entities).join() {
+            if !is_url(&tw_place.twimage_path) { continue }
+            let download = match downloads.get(&tw_place.twimage_path) {
+                Some(download) => download,
+                None => continue
+            };
+            if let Some(path) = &download.path {
+                tw_place.twimage_path = path.to_str().unwrap().to_owned();
+                // the placeholder is cached first by TwCachingImages, it's not queued in file_to_cache
+                tw_place.to_cache = true;
+                debug!("TwPlaceHolder is sent to cache with the downloaded file {:?}", path);
+            } else if let Some(error) = &download.error {
+                error!("Failed to download {:?}: {:?}", &tw_place.twimage_path, error);
+                entities.delete(entity).expect("Failed to delete entity.");
+            } else {
+                let text = match download.progress() {
+                    Some(progress) => format!("Downloading {:.0}%", progress * 100.0),
+                    None => format!("Downloading {:.1} MB", download.received as f32 / 1_000_000.0),
+                };
+                let screen = world_to_screen(camera, Point3::new(transform.translation().x, transform.translation().y, 0.0),
+                                             diag, cam_transform);
+                let label = ImString::new(format!("##download{}", entity.id()));
+                amethyst_imgui::with(|ui| {
+                    imgui::Window::new(&label)
+                        .title_bar(false)
+                        .resizable(false)
+                        .movable(false)
+                        .always_auto_resize(true)
+                        .position([screen.x, screen.y], Condition::Always)
+                        .position_pivot([0.5, 0.5])
+                        .build(ui, || {
+                            ui.text(&text);
+                            imgui::ProgressBar::new(download.progress().unwrap_or(0.0)).size([200.0, 0.0]).build(ui);
+                            ui.text_disabled(&tw_place.twimage_path);
+                        });
+                });
+            }
+        }
+    }
+}
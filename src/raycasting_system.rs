@@ -13,10 +13,13 @@ use amethyst::{
         window::ScreenDimensions,
 };
 
+use geo::Point;
+use geo::algorithm::contains::Contains;
+
 use std::cmp::Ordering::Equal;
 
 use crate::inputshandler::{TwInputsHandler};
-use crate::image::{TwImage, TwActiveUiComponent, TwActiveComponent};
+use crate::image::{TwImage, TwActiveUiComponent, TwActiveComponent, sprite_world_polygon};
 
 /// Utility function which convert screen position to world position coord with a 0 z intersect plane
 pub fn screen_to_world(mouse_position: (f32, f32), camera: &Camera, transform: &Transform, screen_dimensions: &ScreenDimensions) -> Point3<f32>{
@@ -97,21 +100,10 @@ impl<'s> System<'s> for TwImageActiveSystem {
                 if !is_present {
                     tw_in.z_ordered_entities.push(entity);
                 }
-                let (min_x, max_x, min_y, max_y) = {
-                    (
-                        transform.translation().x - (sprite.width * 0.5),
-                        transform.translation().x + (sprite.width * 0.5),
-                        transform.translation().y - (sprite.height * 0.5),
-                        transform.translation().y + (sprite.height * 0.5),
-                    )
-                };
+                let polygon = sprite_world_polygon(sprite, transform);
                 if let Some(mouse_world_position) = tw_in.mouse_world_position {
-                    // if mouse inside sprite
-                    if mouse_world_position.0 > min_x
-                        && mouse_world_position.0 < max_x
-                        && mouse_world_position.1 > min_y
-                        && mouse_world_position.1 < max_y
-                    {
+                    // if mouse inside the oriented sprite
+                    if polygon.contains(&Point::new(mouse_world_position.0, mouse_world_position.1)) {
                         // if active is busy (used by action) or actives empty
                         if !tw_in.active_busy || tw_in.active_entities.is_empty() {
                             tw_actives.insert(entity, TwActiveComponent).expect("Failed to add TwActiveComponent.");
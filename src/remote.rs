@@ -0,0 +1,331 @@
+/// remote.rs contains the remote control of Tower, enabled with --remote, to push images from the
+/// DCC tools and pipeline scripts into an open board.
+///
+/// Protocol, JSON-RPC 2.0 over the Unix socket $XDG_RUNTIME_DIR/tower-rpc.sock (or
+/// /tmp/tower-rpc-$USER.sock without runtime directory), one request per line and one response
+/// per line. Methods:
+/// - load_image {path, x?, y?}: open an image, a scene, a directory or an URL, placed at x, y if given
+/// - set_channels {path, alpha?, red?, green?, blue?}: channels of the images opened from path
+/// - fit_camera {target?}: "all" fit the whole board, "active" the active image
+/// - layout {}: arrange the images as an atlas, like the L key
+/// - snapshot {path, width?}: export the board as a flattened image, like ctrl + E, the response
+///   comes once the file is written
+/// - save_scene {path?}: save the board as a .tower scene, like ctrl + S, the response comes once
+///   the file is written with its path
+/// - list_images {}: path, position, rotation, scale and channels of every image
+///
+/// The requests are executed by the ECS systems, the actions are applied on the next frames.
+/// A request without id is a notification, executed without response.
+/// A client silent for REMOTE_TIMEOUT is disconnected, each client has its own thread.
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, mpsc};
+use std::time::Duration;
+
+use crate::instance::runtime_socket;
+
+
+#[derive(PartialEq, Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TwFitTarget {
+    All,
+    Active,
+}
+
+impl Default for TwFitTarget {
+    fn default() -> Self {
+        TwFitTarget::All
+    }
+}
+
+
+/// a remote request, the method and params of the JSON-RPC message.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "method", content = "params", rename_all = "snake_case")]
+pub enum TwRemoteRequest {
+    LoadImage { path: String, x: Option<f32>, y: Option<f32> },
+    SetChannels { path: String, alpha: Option<f32>, red: Option<f32>, green: Option<f32>, blue: Option<f32> },
+    FitCamera { #[serde(default)] target: TwFitTarget },
+    Layout {},
+    Snapshot { path: PathBuf, width: Option<u32> },
+    SaveScene { path: Option<PathBuf> },
+    ListImages {},
+}
+
+// TODO: remote timeouts as settings
+const REMOTE_TIMEOUT: Duration = Duration::from_secs(60);
+
+const METHODS: &[&str] = &["load_image", "set_channels", "fit_camera", "layout", "snapshot", "save_scene", "list_images"];
+
+impl TwRemoteRequest {
+    /// time given to the ECS to answer, the snapshot answers once the export is written.
+    // TODO: remote timeouts as settings
+    pub fn timeout(&self) -> Duration {
+        match self {
+            TwRemoteRequest::Snapshot { .. } => Duration::from_secs(120),
+            _ => Duration::from_secs(10),
+        }
+    }
+}
+
+
+pub type TwRemoteReply = mpsc::Sender<Result<Value, String>>;
+
+
+/// a request waiting for the TwRemoteSystem, the result is sent back to the connection thread.
+pub struct TwRemoteCall {
+    pub request: TwRemoteRequest,
+    pub reply: TwRemoteReply,
+}
+
+
+/// a snapshot asked by the remote control, the export thread sends the result.
+#[derive(Debug)]
+pub struct TwSnapshot {
+    pub path: PathBuf,
+    pub width: Option<u32>,
+    pub reply: TwRemoteReply,
+}
+
+
+/// a scene save asked by the remote control, SceneSaveSystem sends the result once the file is
+/// written.
+#[derive(Debug)]
+pub struct TwSceneSave {
+    pub path: Option<PathBuf>,
+    pub reply: TwRemoteReply,
+}
+
+
+/// actions asked by the remote control to the existing systems, each system take its own request.
+#[derive(Debug, Default)]
+pub struct TwRemoteActions {
+    pub fit: Option<TwFitTarget>,
+    pub layout: bool,
+    pub snapshot: Option<TwSnapshot>,
+    pub save_scene: Option<TwSceneSave>,
+}
+
+
+pub fn remote_socket_path() -> PathBuf {
+    runtime_socket("tower-rpc")
+}
+
+
+/// answer one JSON-RPC line, the request is sent to the ECS through the calls queue.
+/// None for the notifications, they have no response.
+fn handle_line(line: &str, calls: &Arc<Mutex<Vec<TwRemoteCall>>>) -> Option<Value> {
+    let message: Value = match serde_json::from_str(line) {
+        Ok(message) => message,
+        Err(e) => return Some(json!({"jsonrpc": "2.0", "id": null, "error": {"code": -32700, "message": e.to_string()}}))
+    };
+    let id = message.get("id").cloned();
+    let response = |content: (&str, Value)| {
+        id.clone().map(|id| {
+            let mut response = json!({"jsonrpc": "2.0", "id": id});
+            response[content.0] = content.1;
+            response
+        })
+    };
+    let error = |code: i32, message: String| response(("error", json!({"code": code, "message": message})));
+    let method = match message.get("method").and_then(|method| method.as_str()) {
+        Some(method) => method,
+        None => return error(-32600, "No method in the request".to_owned())
+    };
+    if !METHODS.contains(&method) {
+        return error(-32601, format!("Method not found {:?}", method))
+    }
+    let call = json!({
+        "method": method,
+        "params": message.get("params").cloned().unwrap_or_else(|| json!({})),
+    });
+    let request = match serde_json::from_value::<TwRemoteRequest>(call) {
+        Ok(request) => request,
+        Err(e) => return error(-32602, e.to_string())
+    };
+    let timeout = request.timeout();
+    let (reply, result) = mpsc::channel();
+    calls.lock().unwrap().push(TwRemoteCall { request, reply });
+    // the notifications don't wait for the result
+    if id.is_none() { return None }
+    match result.recv_timeout(timeout) {
+        Ok(Ok(value)) => response(("result", value)),
+        Ok(Err(message)) => error(-32000, message),
+        Err(_e) => error(-32001, "Tower did not answer".to_owned()),
+    }
+}
+
+
+/// listen on the remote socket in a new thread, one thread per connection.
+#[cfg(unix)]
+pub fn start_remote_server(calls: Arc<Mutex<Vec<TwRemoteCall>>>) -> io::Result<()> {
+    listen_remote(&remote_socket_path(), calls)
+}
+
+
+#[cfg(unix)]
+fn listen_remote(path: &Path, calls: Arc<Mutex<Vec<TwRemoteCall>>>) -> io::Result<()> {
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(io::ErrorKind::AddrInUse, "An other Tower is already remote controlled"))
+        }
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    info!("Remote control listening on {:?}", path);
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("Remote connection failed: {:?}", e);
+                    continue
+                }
+            };
+            let calls = Arc::clone(&calls);
+            std::thread::spawn(move || {
+                if let Err(e) = answer_client(stream, &calls) {
+                    warn!("Remote control failed to answer: {:?}", e);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+
+/// answer the requests of a client line by line until it disconnects or stays silent for
+/// REMOTE_TIMEOUT.
+#[cfg(unix)]
+fn answer_client(stream: std::os::unix::net::UnixStream, calls: &Arc<Mutex<Vec<TwRemoteCall>>>) -> io::Result<()> {
+    use std::io::{BufRead, BufReader, Write};
+
+    stream.set_read_timeout(Some(REMOTE_TIMEOUT))?;
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(&stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("Remote client stopped: {:?}", e);
+                break
+            }
+        };
+        if line.trim().is_empty() { continue }
+        if let Some(response) = handle_line(&line, calls) {
+            writeln!(writer, "{}", response)?;
+        }
+    }
+    Ok(())
+}
+
+
+#[cfg(not(unix))]
+pub fn start_remote_server(_calls: Arc<Mutex<Vec<TwRemoteCall>>>) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other, "Remote control needs Unix sockets"))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    // answer the calls like TwRemoteSystem, the method name is the result
+    fn fake_ecs() -> Arc<Mutex<Vec<TwRemoteCall>>> {
+        let calls = Arc::new(Mutex::new(Vec::<TwRemoteCall>::new()));
+        let queue = Arc::clone(&calls);
+        thread::spawn(move || loop {
+            for call in queue.lock().unwrap().drain(..) {
+                let result = match call.request {
+                    // written before the answer, like SceneSaveSystem
+                    TwRemoteRequest::SaveScene { path: Some(path) } => std::fs::write(&path, "scene")
+                        .map(|()| json!({"path": path}))
+                        .map_err(|e| e.to_string()),
+                    TwRemoteRequest::SaveScene { path: None } => Err("No scene path".to_owned()),
+                    request => Ok(json!(format!("{:?}", request))),
+                };
+                let _ = call.reply.send(result);
+            }
+            thread::sleep(Duration::from_millis(5));
+        });
+        calls
+    }
+
+    fn handle(line: &str, calls: &Arc<Mutex<Vec<TwRemoteCall>>>) -> Value {
+        handle_line(line, calls).expect("A response is expected")
+    }
+
+    #[test]
+    fn request_answered_by_the_ecs() {
+        let calls = fake_ecs();
+        let response = handle(r#"{"jsonrpc": "2.0", "id": 7, "method": "fit_camera", "params": {"target": "active"}}"#, &calls);
+        assert_eq!(response, json!({"jsonrpc": "2.0", "id": 7, "result": "FitCamera { target: Active }"}));
+        let response = handle(r#"{"jsonrpc": "2.0", "id": "a", "method": "layout"}"#, &calls);
+        assert_eq!(response["result"], json!("Layout"));
+        let response = handle(r#"{"jsonrpc": "2.0", "id": 8, "method": "save_scene", "params": {}}"#, &calls);
+        assert_eq!(response, json!({"jsonrpc": "2.0", "id": 8, "error": {"code": -32000, "message": "No scene path"}}));
+    }
+
+    #[test]
+    fn errors_of_the_protocol() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        assert_eq!(handle("{not json", &calls)["error"]["code"], json!(-32700));
+        assert_eq!(handle(r#"{"jsonrpc": "2.0", "id": 1}"#, &calls)["error"]["code"], json!(-32600));
+        let response = handle(r#"{"jsonrpc": "2.0", "id": 2, "method": "explode"}"#, &calls);
+        assert_eq!((&response["id"], &response["error"]["code"]), (&json!(2), &json!(-32601)));
+        let response = handle(r#"{"jsonrpc": "2.0", "id": 3, "method": "load_image", "params": {"x": 1}}"#, &calls);
+        assert_eq!(response["error"]["code"], json!(-32602));
+        // none of them reached the ECS
+        assert!(calls.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn notifications_without_response() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        assert!(handle_line(r#"{"jsonrpc": "2.0", "method": "layout"}"#, &calls).is_none());
+        assert!(handle_line(r#"{"jsonrpc": "2.0", "method": "explode"}"#, &calls).is_none());
+        // the notification is still executed
+        let calls = calls.lock().unwrap();
+        assert_eq!(calls.len(), 1);
+        match calls[0].request {
+            TwRemoteRequest::Layout {} => {}
+            ref request => panic!("Unexpected request {:?}", request)
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn requests_of_a_socket_client() {
+        use std::io::{BufRead, BufReader, Write};
+        use std::os::unix::net::UnixStream;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tower-rpc.sock");
+        listen_remote(&path, fake_ecs()).unwrap();
+        // a second server on the same socket is refused
+        assert_eq!(listen_remote(&path, fake_ecs()).unwrap_err().kind(), io::ErrorKind::AddrInUse);
+        let scene = dir.path().join("board.tower");
+        let mut stream = UnixStream::connect(&path).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut answer = |request: &str| {
+            writeln!(stream, "{}", request).unwrap();
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            serde_json::from_str::<Value>(&line).unwrap()
+        };
+        let response = answer(&json!({"jsonrpc": "2.0", "id": 1, "method": "save_scene", "params": {"path": &scene}}).to_string());
+        assert_eq!(response, json!({"jsonrpc": "2.0", "id": 1, "result": {"path": &scene}}));
+        // the file is there when the answer comes
+        assert!(scene.is_file());
+        let response = answer(r#"{"jsonrpc": "2.0", "id": 2, "method": "save_scene"}"#);
+        assert_eq!(response["error"], json!({"code": -32000, "message": "No scene path"}));
+        // the notification has no answer, the next line is the answer of the next request
+        let response = answer("{\"jsonrpc\": \"2.0\", \"method\": \"layout\"}\n\n{\"jsonrpc\": \"2.0\", \"id\": 3, \"method\": \"layout\"}");
+        assert_eq!(response, json!({"jsonrpc": "2.0", "id": 3, "result": "Layout"}));
+    }
+}
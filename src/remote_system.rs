@@ -0,0 +1,130 @@
+/// remote_system.rs contains the system executing the remote control requests,
+/// the actions shared with the shortcuts are forwarded to their systems with TwRemoteActions.
+use amethyst::{
+    core::{SystemDesc, Transform},
+    derive::SystemDesc,
+    ecs::{Join, System, SystemData, World},
+    ecs::prelude::*,
+};
+use serde_json::json;
+
+use std::ffi::OsString;
+use std::path::Path;
+
+use crate::image::TwImage;
+use crate::inputshandler::TwInputsHandler;
+use crate::placeholder::TwPlaceHolder;
+use crate::remote::{TwRemoteActions, TwRemoteRequest, TwSceneSave, TwSnapshot};
+use crate::tower::TowerData;
+use crate::utils::is_valid_file;
+
+
+#[derive(SystemDesc)]
+pub struct TwRemoteSystem;
+/// take the requests received by the remote server and answer them.
+/// load_image create a TwPlaceHolder at the given position or open the path like a dropped file,
+/// set_channels and list_images are done here, fit_camera, layout, snapshot and save_scene are
+/// forwarded to the camera, layout, export and scene systems. The snapshot is answered by the
+/// export thread and save_scene by SceneSaveSystem, once their file is written.
+impl<'s> System<'s> for TwRemoteSystem {
+    type SystemData = (WriteExpect<'s, TwInputsHandler>,
+                       Write<'s, TowerData>,
+                       Write<'s, TwRemoteActions>,
+                       WriteStorage<'s, TwImage>,
+                       ReadStorage<'s, Transform>,
+                       Write<'s, LazyUpdate>,
+                       Entities<'s>);
+    fn run(&mut self, (
+        mut tw_in,
+        mut tw_data,
+        mut actions,
+        mut twimages,
+        transforms,
+        world,
+        entities,
+    ): Self::SystemData) {
+        let calls = match tw_data.remote_calls.try_lock() {
+            Ok(mut calls) => calls.drain(..).collect::<Vec<_>>(),
+            Err(_e) => return
+        };
+        for call in calls {
+            debug!("Remote request {:?}", &call.request);
+            let result = match call.request {
+                TwRemoteRequest::LoadImage { path, x, y } => {
+                    if (x.is_some() || y.is_some()) && is_valid_file(Path::new(&path)) {
+                        if !tw_data.files_order.contains(&OsString::from(&path)) {
+                            tw_data.add_working_dir(Path::new(&path).parent().unwrap());
+                        }
+                        let mut position = Transform::default();
+                        position.set_translation_xyz(x.unwrap_or(0.0), y.unwrap_or(0.0), 0.0);
+                        world.create_entity(&*entities)
+                            .with(position)
+                            .with(TwPlaceHolder { from_next: false, to_cache: true, twimage_path: path.clone(), inherit: None })
+                            .build();
+                        tw_data.file_to_cache.push(OsString::from(&path));
+                    } else {
+                        tw_in.last_dropped_file_path.push(path.clone());
+                    }
+                    Ok(json!({"queued": path}))
+                }
+                TwRemoteRequest::SetChannels { path, alpha, red, green, blue } => {
+                    let mut count = 0;
+                    for twimage in (&mut twimages).join().filter(|twimage| twimage.file_name == path) {
+                        if let Some(alpha) = alpha { twimage.alpha = alpha.max(0.0).min(1.0) }
+                        if let Some(red) = red { twimage.red = red.max(0.0).min(1.0) }
+                        if let Some(green) = green { twimage.green = green.max(0.0).min(1.0) }
+                        if let Some(blue) = blue { twimage.blue = blue.max(0.0).min(1.0) }
+                        count += 1;
+                    }
+                    if count == 0 {
+                        Err(format!("No image opened from {}", path))
+                    } else {
+                        Ok(json!({"images": count}))
+                    }
+                }
+                TwRemoteRequest::FitCamera { target } => {
+                    actions.fit = Some(target);
+                    Ok(json!({"queued": "fit_camera"}))
+                }
+                TwRemoteRequest::Layout {} => {
+                    actions.layout = true;
+                    Ok(json!({"queued": "layout"}))
+                }
+                TwRemoteRequest::Snapshot { path, width } => {
+                    // answered by the export thread once the file is written
+                    let snapshot = TwSnapshot { path, width, reply: call.reply.clone() };
+                    if let Some(previous) = actions.snapshot.replace(snapshot) {
+                        let _ = previous.reply.send(Err("Replaced by an other snapshot".to_owned()));
+                    }
+                    continue
+                }
+                TwRemoteRequest::SaveScene { path } => {
+                    // answered by SceneSaveSystem once the file is written
+                    let save = TwSceneSave { path, reply: call.reply.clone() };
+                    if let Some(previous) = actions.save_scene.replace(save) {
+                        let _ = previous.reply.send(Err("Replaced by an other save_scene".to_owned()));
+                    }
+                    continue
+                }
+                TwRemoteRequest::ListImages {} => {
+                    let images = (&twimages, &transforms).join().map(|(twimage, transform)| {
+                        json!({
+                            "path": twimage.file_name,
+                            "width": twimage.width,
+                            "height": twimage.height,
+                            "x": transform.translation().x,
+                            "y": transform.translation().y,
+                            "z": transform.translation().z,
+                            "rotation": transform.rotation().euler_angles().2,
+                            "scale": twimage.scale,
+                            "channels": [twimage.red, twimage.green, twimage.blue, twimage.alpha],
+                        })
+                    }).collect::<Vec<_>>();
+                    Ok(json!(images))
+                }
+            };
+            // the client may be gone after the timeout
+            let _ = call.reply.send(result);
+        }
+    }
+}
@@ -0,0 +1,184 @@
+/// scene.rs contains the tower scene file format, a ron file that save the board.
+/// Every non system function about scene saving and loading are placed here.
+use amethyst::core::Transform;
+use serde::{Serialize, Deserialize};
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::image::{TwImage, TwCrop, TwFilter};
+use crate::annotation::{TwAnnotation, TwAnnotationKind};
+
+
+pub const SCENE_EXTENSION: &str = "tower";
+
+
+/// one image of the board, its path, its placement and the user attributes of its TwImage.
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct TwSceneImage {
+    pub path: String,
+    pub translation: [f32; 3],
+    pub rotation: f32,
+    pub scale: f32,
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+    #[serde(default)]
+    pub crop: Option<TwCrop>,
+    #[serde(default)]
+    pub filter: Option<TwFilter>,
+    pub alpha: f32,
+    pub red: f32,
+    pub green: f32,
+    pub blue: f32,
+}
+
+impl TwSceneImage {
+    pub fn new(tw_image: &TwImage, transform: &Transform) -> Self {
+        Self {
+            path: tw_image.file_name.clone(),
+            translation: [transform.translation().x, transform.translation().y, transform.translation().z],
+            rotation: transform.euler_angles().2,
+            scale: tw_image.scale,
+            flip_horizontal: tw_image.flip_horizontal,
+            flip_vertical: tw_image.flip_vertical,
+            crop: tw_image.crop,
+            filter: tw_image.filter,
+            alpha: tw_image.alpha,
+            red: tw_image.red,
+            green: tw_image.green,
+            blue: tw_image.blue,
+        }
+    }
+
+    /// build the Transform of the image as saved in the scene.
+    pub fn transform(&self) -> Transform {
+        let mut transform = Transform::default();
+        transform.set_translation_xyz(self.translation[0], self.translation[1], self.translation[2]);
+        transform.set_rotation_z_axis(self.rotation);
+        transform
+    }
+
+    /// build a TwImage holding the saved user attributes, used as TwPlaceHolder.inherit.
+    pub fn tw_image(&self) -> TwImage {
+        let mut tw_image = TwImage::new(0, 0, &self.path);
+        tw_image.scale = self.scale;
+        tw_image.flip_horizontal = self.flip_horizontal;
+        tw_image.flip_vertical = self.flip_vertical;
+        tw_image.crop = self.crop;
+        tw_image.filter = self.filter;
+        tw_image.alpha = self.alpha;
+        tw_image.red = self.red;
+        tw_image.green = self.green;
+        tw_image.blue = self.blue;
+        tw_image
+    }
+}
+
+
+/// one annotation of the board, attached_to is the index of the attached image in TwScene.images
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct TwSceneAnnotation {
+    pub kind: TwAnnotationKind,
+    pub points: Vec<(f32, f32)>,
+    pub color: [f32; 4],
+    pub attached_to: Option<usize>,
+}
+
+impl TwSceneAnnotation {
+    pub fn new(annotation: &TwAnnotation, attached_to: Option<usize>) -> Self {
+        Self {
+            kind: annotation.kind.clone(),
+            points: annotation.points.clone(),
+            color: annotation.color,
+            attached_to,
+        }
+    }
+}
+
+
+/// the whole board, saved as a .tower file.
+#[derive(PartialEq, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct TwScene {
+    pub images: Vec<TwSceneImage>,
+    #[serde(default)]
+    pub annotations: Vec<TwSceneAnnotation>,
+}
+
+
+pub fn is_scene_file(file: &Path) -> bool {
+    file.extension().map_or(false, |ext| ext.to_str().unwrap().to_lowercase() == SCENE_EXTENSION)
+}
+
+
+/// write the scene as a pretty ron file.
+pub fn save_scene(path: &Path, scene: &TwScene) -> io::Result<()> {
+    let content = ron::ser::to_string_pretty(scene, ron::ser::PrettyConfig::default())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    fs::write(path, content)
+}
+
+
+/// read a scene from a .tower ron file.
+pub fn load_scene(path: &Path) -> io::Result<TwScene> {
+    let content = fs::read_to_string(path)?;
+    ron::de::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scene_round_trip() {
+        let mut tw_image = TwImage::new(400, 200, "plates/shot_010.exr");
+        tw_image.scale = 1.5;
+        tw_image.flip_horizontal = true;
+        tw_image.crop = Some(TwCrop { x: 10, y: 20, width: 300, height: 150 });
+        tw_image.filter = Some(TwFilter::Nearest);
+        tw_image.alpha = 0.5;
+        tw_image.red = 0.0;
+        let mut transform = Transform::default();
+        transform.set_translation_xyz(120.0, -40.5, 0.0);
+        transform.set_rotation_z_axis(30.0_f32.to_radians());
+        let mut arrow = TwAnnotation::new(TwAnnotationKind::Arrow, None);
+        arrow.points = vec![(10.0, 20.0), (110.0, 60.0)];
+        let mut note = TwAnnotation::new(TwAnnotationKind::Text("keep the grain".to_owned()), None);
+        note.points = vec![(-5.0, 300.0)];
+        note.color = [1.0, 0.0, 0.0, 1.0];
+        let scene = TwScene {
+            images: vec![TwSceneImage::new(&tw_image, &transform), TwSceneImage::new(&TwImage::new(64, 64, "ref.png"), &Transform::default())],
+            annotations: vec![TwSceneAnnotation::new(&arrow, Some(0)), TwSceneAnnotation::new(&note, None)],
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("board.tower");
+        save_scene(&path, &scene).unwrap();
+        let loaded = load_scene(&path).unwrap();
+        assert_eq!(loaded, scene);
+
+        // the attributes and the placement rebuilt from the file
+        let image = &loaded.images[0];
+        let inherit = image.tw_image();
+        assert_eq!((inherit.scale, inherit.flip_horizontal, inherit.flip_vertical), (1.5, true, false));
+        assert_eq!(inherit.crop, Some(TwCrop { x: 10, y: 20, width: 300, height: 150 }));
+        assert_eq!((inherit.filter, inherit.alpha, inherit.red, inherit.green), (Some(TwFilter::Nearest), 0.5, 0.0, 1.0));
+        let rebuilt = image.transform();
+        assert_eq!((rebuilt.translation().x, rebuilt.translation().y), (120.0, -40.5));
+        assert!((rebuilt.euler_angles().2 - 30.0_f32.to_radians()).abs() < 1e-5);
+        assert_eq!(loaded.annotations[0].attached_to, Some(0));
+        assert_eq!(loaded.annotations[1].kind, TwAnnotationKind::Text("keep the grain".to_owned()));
+    }
+
+    #[test]
+    fn scene_without_annotations() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("old.tower");
+        fs::write(&path, "(images: [])").unwrap();
+        assert_eq!(load_scene(&path).unwrap(), TwScene::default());
+        assert!(is_scene_file(&path));
+        fs::write(&path, "(images: [(path: 1)])").unwrap();
+        assert_eq!(load_scene(&path).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
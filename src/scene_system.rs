@@ -11,15 +11,24 @@ use amethyst::{
                palette::Srgba},
     assets::{AssetStorage},
 };
+use amethyst::input::VirtualKeyCode;
 use geo::{LineString};
 use geo::algorithm::bounding_rect::BoundingRect;
+use serde_json::json;
 
 
 
 use crate::tower::{TowerData};
-use crate::image::{TwImage};
+use crate::image::{TwImage, sprite_world_polygon};
 use crate::inputshandler::TwInputsHandler;
 use crate::camera::world_to_screen;
+use crate::scene::{TwScene, TwSceneImage, TwSceneAnnotation, SCENE_EXTENSION, save_scene};
+use crate::annotation::TwAnnotation;
+use crate::remote::TwRemoteActions;
+
+use std::cmp::Ordering::Equal;
+use std::path::Path;
+use std::time::Duration;
 
 
 #[derive(SystemDesc, Default)]
@@ -55,15 +64,13 @@ impl<'s> System<'s> for SceneBoundingBox {
         for (sprite, _twimage, transform) in (&sprites, &twimages, &transforms).join() {
             let sprite_sheet = sprite_sheet.get(&sprite.sprite_sheet).unwrap();
             let sprite = &sprite_sheet.sprites[sprite.sprite_number];
-            let top_l_point = (transform.translation().x - (sprite.width * 0.5), transform.translation().y + (sprite.height * 0.5));
-            let top_r_point = (transform.translation().x + (sprite.width * 0.5), transform.translation().y + (sprite.height * 0.5));
-            let bottom_l_point = (transform.translation().x - (sprite.width * 0.5), transform.translation().y - (sprite.height * 0.5));
-            let bottom_r_point = (transform.translation().x + (sprite.width * 0.5), transform.translation().y - (sprite.height * 0.5));
+            let polygon = sprite_world_polygon(sprite, transform);
+            // the corners order changes with the rotation, the left points come from the
+            // bounding box of all the corners
+            let bbox = polygon.bounding_rect().unwrap();
+            let (top_l_point, bottom_l_point) = ((bbox.min.x, bbox.max.y), (bbox.min.x, bbox.min.y));
             points.push((transform.translation().x, transform.translation().y));
-            points.push(top_l_point);
-            points.push(top_r_point);
-            points.push(bottom_l_point);
-            points.push(bottom_r_point);
+            points.extend(polygon.exterior().points_iter().map(|p| (p.x(), p.y())));
 
             let win_size =  window.get_inner_size().unwrap();
             let diag = Vector2::new(win_size.width as f32, win_size.height as f32);
@@ -80,8 +87,7 @@ impl<'s> System<'s> for SceneBoundingBox {
             let sprite_sheet = sprite_sheet.get(&sprite.sprite_sheet).unwrap();
             let sprite = &sprite_sheet.sprites[sprite.sprite_number];
             active_points.push((transform.translation().x, transform.translation().y));
-            active_points.push((transform.translation().x - (sprite.width * 0.5), transform.translation().y - (sprite.height * 0.5)));
-            active_points.push((transform.translation().x + (sprite.width * 0.5), transform.translation().y + (sprite.height * 0.5)));
+            active_points.extend(sprite_world_polygon(sprite, transform).exterior().points_iter().map(|p| (p.x(), p.y())));
         }
         if let Some(bbox) = LineString::from(points).bounding_rect() {
             tw_data.scene_middle_point = Point2::new((bbox.min.x + bbox.max.x) / 2.0, (bbox.min.y + bbox.max.y) / 2.0);
@@ -94,6 +100,78 @@ impl<'s> System<'s> for SceneBoundingBox {
 }
 
 
+#[derive(SystemDesc)]
+pub struct SceneSaveSystem;
+/// save the board as a .tower scene file with Ctrl + S.
+/// The scene is written back to the loaded scene file if any, otherwise a scene.tower is
+/// created in the working directory.
+/// The remote control save_scene request saves it too, to the given path if any, and is answered
+/// with the path of the written file.
+impl<'s> System<'s> for SceneSaveSystem {
+    type SystemData = (Write<'s, TowerData>,
+                       WriteExpect<'s, TwInputsHandler>,
+                       Write<'s, TwRemoteActions>,
+                       ReadStorage<'s, Transform>,
+                       ReadStorage<'s, TwImage>,
+                       ReadStorage<'s, SpriteRender>,
+                       ReadStorage<'s, TwAnnotation>,
+                       Entities<'s>);
+
+    fn run(&mut self, (
+        mut tw_data,
+        mut tw_in,
+        mut remote_actions,
+        transforms,
+        twimages,
+        sprites,
+        annotations,
+        entities,
+    ): Self::SystemData) {
+        let remote_save = remote_actions.save_scene.take();
+        if (tw_in.keys_pressed.contains(&VirtualKeyCode::S) && tw_in.keys_pressed.contains(&VirtualKeyCode::LControl) && tw_in.keys_pressed.len() == 2) || remote_save.is_some() {
+            if Duration::from_millis(500) <= tw_in.stopwatch.elapsed() || remote_save.is_some() {
+                let mut scene = TwScene::default();
+                let mut image_entities = (&twimages, &transforms, &sprites, &*entities).join()
+                    .map(|(_, transform, _, entity)| (transform.translation().z, entity)).collect::<Vec<_>>();
+                image_entities.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Equal));
+                for (_, entity) in &image_entities {
+                    scene.images.push(TwSceneImage::new(twimages.get(*entity).unwrap(), transforms.get(*entity).unwrap()));
+                }
+                for annotation in (&annotations).join() {
+                    let attached_to = match annotation.attached_to {
+                        Some(attached) => match image_entities.iter().position(|(_, e)| *e == attached) {
+                            Some(index) => Some(index),
+                            // attached image is not on the board anymore
+                            None => continue
+                        },
+                        None => None
+                    };
+                    scene.annotations.push(TwSceneAnnotation::new(annotation, attached_to));
+                }
+                let scene_path = remote_save.as_ref().and_then(|save| save.path.clone()).or_else(|| tw_data.scene_path.clone()).unwrap_or_else(||
+                    Path::new(&tw_data.working_dir).join(format!("scene.{}", SCENE_EXTENSION)));
+                let result = save_scene(&scene_path, &scene);
+                if let Some(save) = remote_save {
+                    // the client may be gone after the timeout
+                    let _ = save.reply.send(match &result {
+                        Ok(()) => Ok(json!({"path": &scene_path})),
+                        Err(e) => Err(format!("Failed to save scene {:?}: {}", &scene_path, e)),
+                    });
+                }
+                match result {
+                    Ok(()) => {
+                        info!("Scene saved in {:?}", &scene_path);
+                        tw_data.scene_path = Some(scene_path);
+                    }
+                    Err(e) => error!("Failed to save scene {:?}: {:?}", &scene_path, e)
+                }
+                tw_in.stopwatch.restart();
+            }
+        }
+    }
+}
+
+
 #[derive(SystemDesc)]
 pub struct DebugLinesSystem;
 /// draw GL line from TowerData.debug_line_start and TowerData.debug_line_end.
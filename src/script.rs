@@ -0,0 +1,247 @@
+/// script.rs contains the Lua scripting of Tower, small custom actions bound to keys.
+/// The scripts and their bindings are read from the Tower config directory,
+/// $XDG_CONFIG_HOME/tower/scripts/scripts.ron list the bindings:
+/// [
+///     (key: D, shift: false, ctrl: false, script: "diffuse_row.lua"),
+/// ]
+/// A script gets the board as Lua globals, `images`, `camera`, `files_order` and `active`,
+/// the changes of the images transforms and channels and of the camera are applied back after the
+/// run, paths appended to `open` are opened like dropped files.
+use amethyst::input::VirtualKeyCode;
+use rlua::{Lua, Table, HookTriggers};
+use serde::Deserialize;
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+
+// TODO: instruction limit as settings
+const SCRIPT_MAX_INSTRUCTIONS: u32 = 10_000_000;
+const SCRIPT_HOOK_STEP: u32 = 10_000;
+
+
+/// keys of the board shortcuts as key, shift, ctrl, see the README. The drag modifiers are
+/// included, a script bound to them would run at the start of the drag.
+const BUILTIN_SHORTCUTS: &[(VirtualKeyCode, bool, bool)] = &[
+    (VirtualKeyCode::A, false, false), (VirtualKeyCode::B, false, false), (VirtualKeyCode::C, false, true),
+    (VirtualKeyCode::C, true, false), (VirtualKeyCode::C, true, true), (VirtualKeyCode::D, false, false),
+    (VirtualKeyCode::Delete, false, false), (VirtualKeyCode::E, false, false), (VirtualKeyCode::E, false, true),
+    (VirtualKeyCode::Escape, false, false), (VirtualKeyCode::F, false, false), (VirtualKeyCode::F, true, false),
+    (VirtualKeyCode::H, false, false), (VirtualKeyCode::I, false, false), (VirtualKeyCode::K, false, false),
+    (VirtualKeyCode::L, false, false), (VirtualKeyCode::L, true, false), (VirtualKeyCode::M, false, false),
+    (VirtualKeyCode::M, true, false), (VirtualKeyCode::N, false, false), (VirtualKeyCode::O, false, false),
+    (VirtualKeyCode::O, true, false), (VirtualKeyCode::P, false, false), (VirtualKeyCode::R, false, false),
+    (VirtualKeyCode::S, false, false), (VirtualKeyCode::S, false, true), (VirtualKeyCode::T, true, false),
+    (VirtualKeyCode::V, false, false), (VirtualKeyCode::V, false, true), (VirtualKeyCode::X, false, false),
+    (VirtualKeyCode::Add, false, false), (VirtualKeyCode::Equals, false, false), (VirtualKeyCode::Subtract, false, false),
+    (VirtualKeyCode::Minus, false, false), (VirtualKeyCode::Key0, false, false), (VirtualKeyCode::Numpad0, false, false),
+    (VirtualKeyCode::Left, false, false), (VirtualKeyCode::Right, false, false), (VirtualKeyCode::Tab, false, false),
+    (VirtualKeyCode::Space, false, false), (VirtualKeyCode::G, false, false),
+];
+
+
+/// a binding of scripts.ron.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TwScriptBinding {
+    pub key: VirtualKeyCode,
+    #[serde(default)]
+    pub shift: bool,
+    #[serde(default)]
+    pub ctrl: bool,
+    pub script: String,
+}
+
+
+/// a script loaded from the config directory with its binding.
+#[derive(Debug, Clone)]
+pub struct TwScript {
+    pub binding: TwScriptBinding,
+    pub source: String,
+}
+
+
+/// an image of the board as seen by the scripts, id is its index in TwScriptBoard.images.
+#[derive(Debug, Clone, Default)]
+pub struct TwScriptImage {
+    pub path: String,
+    pub width: u32,
+    pub height: u32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub rotation: f32,
+    pub scale: f32,
+    pub alpha: f32,
+    pub red: f32,
+    pub green: f32,
+    pub blue: f32,
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+}
+
+
+/// the board given to a script and read back after its run.
+#[derive(Debug, Clone, Default)]
+pub struct TwScriptBoard {
+    pub images: Vec<TwScriptImage>,
+    pub active: Option<usize>,
+    pub camera: (f32, f32, f32),
+    pub files_order: Vec<String>,
+    pub open: Vec<String>,
+}
+
+
+pub fn scripts_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("tower").join("scripts"))
+}
+
+
+/// read scripts.ron and the script files it binds, a missing script is skipped.
+pub fn load_scripts() -> io::Result<Vec<TwScript>> {
+    let dir = scripts_dir().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No config directory"))?;
+    let bindings_path = dir.join("scripts.ron");
+    if !bindings_path.exists() {
+        return Ok(Vec::new())
+    }
+    let bindings: Vec<TwScriptBinding> = ron::de::from_str(&fs::read_to_string(&bindings_path)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    let mut scripts = Vec::new();
+    for binding in bindings {
+        match fs::read_to_string(dir.join(&binding.script)) {
+            Ok(source) => {
+                info!("Script {:?} bound to {:?}, shift {:?}, ctrl {:?}", &binding.script, binding.key, binding.shift, binding.ctrl);
+                if BUILTIN_SHORTCUTS.contains(&(binding.key, binding.shift, binding.ctrl)) {
+                    warn!("Script {:?} is bound to a Tower shortcut, both run on {:?}", &binding.script, binding.key);
+                }
+                scripts.push(TwScript { binding, source });
+            }
+            Err(e) => warn!("Failed to read script {:?}: {:?}", &binding.script, e)
+        }
+    }
+    Ok(scripts)
+}
+
+
+/// run a script on the board, the board is updated with the globals left by the script.
+/// A script is stopped after SCRIPT_MAX_INSTRUCTIONS, an endless loop doesn't freeze the board.
+pub fn run_script(script: &TwScript, board: &mut TwScriptBoard) -> rlua::Result<()> {
+    let lua = Lua::new();
+    let mut executed = 0;
+    lua.set_hook(HookTriggers { every_nth_instruction: Some(SCRIPT_HOOK_STEP), ..Default::default() }, move |_ctx, _debug| {
+        executed += SCRIPT_HOOK_STEP;
+        if executed < SCRIPT_MAX_INSTRUCTIONS { return Ok(()) }
+        Err(rlua::Error::RuntimeError(format!("Script stopped after {} instructions", SCRIPT_MAX_INSTRUCTIONS)))
+    });
+    lua.context(|ctx| {
+        let globals = ctx.globals();
+        let images = ctx.create_table()?;
+        for (id, image) in board.images.iter().enumerate() {
+            let table = ctx.create_table()?;
+            table.set("id", id + 1)?;
+            table.set("path", image.path.clone())?;
+            table.set("width", image.width)?;
+            table.set("height", image.height)?;
+            table.set("x", image.x)?;
+            table.set("y", image.y)?;
+            table.set("z", image.z)?;
+            table.set("rotation", image.rotation)?;
+            table.set("scale", image.scale)?;
+            table.set("alpha", image.alpha)?;
+            table.set("red", image.red)?;
+            table.set("green", image.green)?;
+            table.set("blue", image.blue)?;
+            table.set("flip_horizontal", image.flip_horizontal)?;
+            table.set("flip_vertical", image.flip_vertical)?;
+            images.set(id + 1, table)?;
+        }
+        globals.set("images", images)?;
+        globals.set("active", board.active.map(|id| id + 1))?;
+        let camera = ctx.create_table()?;
+        camera.set("x", board.camera.0)?;
+        camera.set("y", board.camera.1)?;
+        camera.set("z", board.camera.2)?;
+        globals.set("camera", camera)?;
+        globals.set("files_order", board.files_order.clone())?;
+        globals.set("open", ctx.create_table()?)?;
+        globals.set("log", ctx.create_function(|_, message: String| {
+            info!("Script: {}", message);
+            Ok(())
+        })?)?;
+
+        ctx.load(&script.source).exec()?;
+
+        // the scripts can sort or filter the images table, the id keep the link to the image
+        let images: Table = globals.get("images")?;
+        for table in images.sequence_values::<Table>() {
+            let table = table?;
+            let id: usize = table.get("id")?;
+            if let Some(image) = board.images.get_mut(id.wrapping_sub(1)) {
+                image.x = table.get("x")?;
+                image.y = table.get("y")?;
+                image.rotation = table.get("rotation")?;
+                image.scale = table.get("scale")?;
+                image.alpha = table.get("alpha")?;
+                image.red = table.get("red")?;
+                image.green = table.get("green")?;
+                image.blue = table.get("blue")?;
+                image.flip_horizontal = table.get("flip_horizontal")?;
+                image.flip_vertical = table.get("flip_vertical")?;
+            }
+        }
+        let camera: Table = globals.get("camera")?;
+        board.camera = (camera.get("x")?, camera.get("y")?, camera.get("z")?);
+        board.open = globals.get("open")?;
+        Ok(())
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn script(source: &str) -> TwScript {
+        TwScript {
+            binding: TwScriptBinding { key: VirtualKeyCode::J, shift: false, ctrl: false, script: "test.lua".to_owned() },
+            source: source.to_owned(),
+        }
+    }
+
+    #[test]
+    fn script_changes_the_board() {
+        let mut board = TwScriptBoard::default();
+        board.images.push(TwScriptImage { path: "a.png".to_owned(), scale: 1.0, ..TwScriptImage::default() });
+        let source = "images[1].x = 10; images[1].scale = 2; camera.z = 5; table.insert(open, images[1].path)";
+        run_script(&script(source), &mut board).unwrap();
+        assert_eq!((board.images[0].x, board.images[0].scale), (10.0, 2.0));
+        assert_eq!(board.camera.2, 5.0);
+        assert_eq!(board.open, vec!["a.png".to_owned()]);
+    }
+
+    #[test]
+    fn builtin_shortcuts_cover_the_keys_of_the_systems() {
+        // a key read by a system is a board shortcut, a script can't be bound to it
+        let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+        for entry in fs::read_dir(&src).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some("rs") || path.ends_with("script.rs") { continue }
+            let source = fs::read_to_string(&path).unwrap();
+            for name in source.split("VirtualKeyCode::").skip(1) {
+                let key = name.split(|c: char| !c.is_ascii_alphanumeric()).next().unwrap();
+                if key == "LShift" || key == "LControl" { continue }
+                assert!(BUILTIN_SHORTCUTS.iter().any(|(builtin, _, _)| format!("{:?}", builtin) == key),
+                        "{} of {:?} is missing from BUILTIN_SHORTCUTS", key, path);
+            }
+        }
+    }
+
+    #[test]
+    fn endless_script_is_stopped() {
+        let mut board = TwScriptBoard::default();
+        let error = run_script(&script("while true do end"), &mut board).unwrap_err();
+        // the hook error is the cause of a callback error
+        assert!(format!("{:?}", error).contains("Script stopped after"), "{:?}", error);
+    }
+}
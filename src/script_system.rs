@@ -0,0 +1,128 @@
+/// script_system.rs contains the system running the Lua scripts bound to keys.
+use amethyst::{
+    assets::AssetStorage,
+    core::{SystemDesc, Transform},
+    derive::SystemDesc,
+    ecs::{Join, System, SystemData, World},
+    ecs::prelude::*,
+    input::VirtualKeyCode,
+    renderer::sprite::{SpriteRender, SpriteSheet},
+};
+
+use std::time::Duration;
+
+use crate::camera::TwCamera;
+use crate::image::{TwImage, MIN_SCALE, build_sprite};
+use crate::inputshandler::TwInputsHandler;
+use crate::script::{TwScript, TwScriptBoard, TwScriptImage, load_scripts, run_script};
+use crate::tower::TowerData;
+
+
+#[derive(SystemDesc, Default)]
+pub struct TwScriptSystem {
+    scripts: Option<Vec<TwScript>>,
+}
+/// run the script bound to the pressed keys, the scripts are loaded from the config directory on
+/// the first frame.
+/// The board is copied into the script globals, the images transforms, channels and flips and the
+/// camera position are applied back after the run.
+impl<'s> System<'s> for TwScriptSystem {
+    type SystemData = (WriteExpect<'s, TwInputsHandler>,
+                       Read<'s, TowerData>,
+                       WriteStorage<'s, TwImage>,
+                       WriteStorage<'s, Transform>,
+                       ReadStorage<'s, TwCamera>,
+                       ReadStorage<'s, SpriteRender>,
+                       Write<'s, AssetStorage<SpriteSheet>>,
+                       Entities<'s>);
+    fn run(&mut self, (
+        mut tw_in,
+        tw_data,
+        mut tw_images,
+        mut transforms,
+        tw_cameras,
+        sprites,
+        mut sprite_sheets,
+        entities,
+    ): Self::SystemData) {
+        let scripts = self.scripts.get_or_insert_with(|| load_scripts().unwrap_or_else(|e| {
+            warn!("Failed to load the scripts: {:?}", e);
+            Vec::new()
+        }));
+        if tw_in.keys_pressed.is_empty() || Duration::from_millis(500) > tw_in.stopwatch.elapsed() { return }
+        let shift = tw_in.keys_pressed.contains(&VirtualKeyCode::LShift);
+        let ctrl = tw_in.keys_pressed.contains(&VirtualKeyCode::LControl);
+        let script = match scripts.iter().find(|script| {
+            script.binding.shift == shift && script.binding.ctrl == ctrl
+                && tw_in.keys_pressed.contains(&script.binding.key)
+                && tw_in.keys_pressed.len() == 1 + shift as usize + ctrl as usize
+        }) {
+            Some(script) => script,
+            None => return
+        };
+        tw_in.stopwatch.restart();
+        // copy the board
+        let mut board = TwScriptBoard::default();
+        let mut image_entities = Vec::new();
+        for (tw_image, transform, entity) in (&tw_images, &transforms, &*entities).join() {
+            if tw_in.active_entities.last() == Some(&entity) {
+                board.active = Some(image_entities.len());
+            }
+            image_entities.push(entity);
+            board.images.push(TwScriptImage {
+                path: tw_image.file_name.clone(),
+                width: tw_image.width,
+                height: tw_image.height,
+                x: transform.translation().x,
+                y: transform.translation().y,
+                z: transform.translation().z,
+                rotation: transform.euler_angles().2,
+                scale: tw_image.scale,
+                alpha: tw_image.alpha,
+                red: tw_image.red,
+                green: tw_image.green,
+                blue: tw_image.blue,
+                flip_horizontal: tw_image.flip_horizontal,
+                flip_vertical: tw_image.flip_vertical,
+            });
+        }
+        if let Some((_, transform)) = (&tw_cameras, &transforms).join().next() {
+            board.camera = (transform.translation().x, transform.translation().y, transform.translation().z);
+        }
+        board.files_order = tw_data.files_order.iter().map(|f| f.to_string_lossy().into_owned()).collect();
+        info!("Run script {:?}", &script.binding.script);
+        if let Err(e) = run_script(script, &mut board) {
+            error!("Script {:?} failed: {:?}", &script.binding.script, e);
+            return
+        }
+        // apply back the board
+        for (entity, image) in image_entities.iter().zip(&board.images) {
+            if let Some(transform) = transforms.get_mut(*entity) {
+                transform.set_translation_x(image.x);
+                transform.set_translation_y(image.y);
+                transform.set_rotation_z_axis(image.rotation);
+            }
+            if let Some(tw_image) = tw_images.get_mut(*entity) {
+                // a NaN scale is also replaced by the minimum
+                tw_image.scale = image.scale.max(MIN_SCALE);
+                tw_image.alpha = image.alpha.max(0.0).min(1.0);
+                tw_image.red = image.red.max(0.0).min(1.0);
+                tw_image.green = image.green.max(0.0).min(1.0);
+                tw_image.blue = image.blue.max(0.0).min(1.0);
+                if tw_image.flip_horizontal != image.flip_horizontal || tw_image.flip_vertical != image.flip_vertical {
+                    tw_image.flip_horizontal = image.flip_horizontal;
+                    tw_image.flip_vertical = image.flip_vertical;
+                    if let Some(sprite) = sprites.get(*entity) {
+                        if let Some(sprite_sheet) = sprite_sheets.get_mut(&sprite.sprite_sheet) {
+                            sprite_sheet.sprites[sprite.sprite_number] = build_sprite(tw_image);
+                        }
+                    }
+                }
+            }
+        }
+        if let Some((_, transform)) = (&tw_cameras, &mut transforms).join().next() {
+            transform.set_translation_xyz(board.camera.0, board.camera.1, board.camera.2);
+        }
+        tw_in.last_dropped_file_path.extend(board.open);
+    }
+}
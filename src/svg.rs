@@ -0,0 +1,58 @@
+/// svg.rs contains the rasterization of the vector images, the svg are rasterized at the scale
+/// needed by the camera, see svg_system.rs
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::decoder::{TwPixelBuffer, TwSamples, invalid_data};
+use crate::tile::MAX_TEXTURE_SIZE;
+
+
+pub fn is_svg_file(file: &Path) -> bool {
+    file.extension().map_or(false, |ext| {
+        let ext = ext.to_string_lossy().to_lowercase();
+        ext == "svg" || ext == "svgz"
+    })
+}
+
+
+fn read_tree(path: &Path) -> io::Result<usvg::Tree> {
+    let options = usvg::Options::default();
+    usvg::Tree::from_data(&fs::read(path)?, &options).map_err(invalid_data)
+}
+
+
+/// size of the svg document in pixels at scale 1.
+pub fn svg_size(path: &Path) -> io::Result<(u32, u32)> {
+    let size = read_tree(path)?.svg_node().size;
+    Ok((size.width().ceil().max(1.0) as u32, size.height().ceil().max(1.0) as u32))
+}
+
+
+/// largest scale keeping the raster of a document in a single texture.
+pub fn max_svg_scale(size: (u32, u32)) -> f32 {
+    MAX_TEXTURE_SIZE as f32 / size.0.max(size.1) as f32
+}
+
+
+/// rasterize the svg at scale, scale 1 is the document size, returns straight alpha rgba pixels.
+pub fn rasterize_svg(path: &Path, scale: f32) -> io::Result<TwPixelBuffer> {
+    let tree = read_tree(path)?;
+    let size = tree.svg_node().size;
+    let width = (size.width() as f32 * scale).ceil().max(1.0) as u32;
+    let height = (size.height() as f32 * scale).ceil().max(1.0) as u32;
+    let mut pixmap = tiny_skia::Pixmap::new(width, height).ok_or_else(|| invalid_data("Invalid svg size"))?;
+    resvg::render(&tree, usvg::FitTo::Size(width, height), pixmap.as_mut())
+        .ok_or_else(|| invalid_data("Failed to rasterize svg"))?;
+    // tiny-skia pixels are premultiplied, TwPixels premultiply them again
+    let mut rgba = pixmap.take();
+    for pixel in rgba.chunks_mut(4) {
+        let alpha = pixel[3] as f32 / 255.0;
+        if alpha > 0.0 {
+            for c in 0..3 {
+                pixel[c] = (pixel[c] as f32 / alpha).round().min(255.0) as u8;
+            }
+        }
+    }
+    Ok(TwPixelBuffer { width, height, channels: 4, samples: TwSamples::U8(rgba) })
+}
@@ -0,0 +1,91 @@
+/// svg_system.rs contains the system keeping the vector images sharp.
+use amethyst::{
+    assets::{AssetStorage, Loader},
+    core::{SystemDesc, Transform},
+    derive::SystemDesc,
+    ecs::{Join, Read, System, SystemData, World},
+    ecs::prelude::*,
+    renderer::{Camera, SpriteRender, SpriteSheet, Texture},
+};
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::image::{TwImage, TwPixels};
+use crate::svg::{is_svg_file, max_svg_scale, rasterize_svg};
+use crate::tower::TowerData;
+
+
+#[derive(SystemDesc, Default)]
+pub struct TwSvgRasterSystem {
+    // raster scale of the texture of each svg image, the cached pixels are at scale 1
+    scales: HashMap<Entity, f32>,
+    pending: Arc<Mutex<Vec<(Entity, f32, TwPixels)>>>,
+}
+/// rasterize again the svg images when the camera Z changes enough, the raster scale follows the
+/// screen pixels covered by one document pixel, rounded to the next power of two so a small zoom
+/// doesn't rasterize again. The new raster replaces the texture, the sprite keeps the document size.
+impl<'s> System<'s> for TwSvgRasterSystem {
+    type SystemData = (ReadStorage<'s, TwImage>,
+                       ReadStorage<'s, Transform>,
+                       ReadStorage<'s, Camera>,
+                       ReadStorage<'s, SpriteRender>,
+                       Read<'s, TowerData>,
+                       Write<'s, AssetStorage<SpriteSheet>>,
+                       Read<'s, AssetStorage<Texture>>,
+                       ReadExpect<'s, Loader>,
+                       Entities<'s>);
+    fn run(&mut self, (
+        tw_images,
+        transforms,
+        cameras,
+        sprites,
+        td,
+        mut sprite_sheets,
+        texture_storage,
+        loader,
+        entities,
+    ): Self::SystemData) {
+        if let Ok(mut pending) = self.pending.try_lock() {
+            for (entity, scale, tw_pixels) in pending.drain(..) {
+                if !entities.is_alive(entity) { continue }
+                // a raster finished after the one of a newer scale
+                if self.scales.get(&entity) != Some(&scale) { continue }
+                if let (Some(tw_image), Some(sprite)) = (tw_images.get(entity), sprites.get(entity)) {
+                    let texture = loader.load_from_data(tw_pixels.texture_data(tw_image.filter.unwrap_or(td.filter)), (), &texture_storage);
+                    if let Some(sprite_sheet) = sprite_sheets.get_mut(&sprite.sprite_sheet) {
+                        sprite_sheet.texture = texture;
+                        debug!("Svg {:?} is rasterized at scale {:?}", &tw_image.file_name, scale);
+                    }
+                }
+            }
+        }
+        self.scales.retain(|entity, _| entities.is_alive(*entity));
+        let camera_z = match (&cameras, &transforms).join().next() {
+            Some((_, transform)) => transform.translation().z,
+            None => return
+        };
+        if td.real_size_z <= 0.0 || camera_z <= 0.0 { return }
+        for (tw_image, _sprite, entity) in (&tw_images, &sprites, &*entities).join() {
+            if !is_svg_file(Path::new(&tw_image.file_name)) { continue }
+            let wanted = td.real_size_z / camera_z * tw_image.scale;
+            let scale = 2.0_f32.powi(wanted.log2().ceil() as i32)
+                .max(1.0)
+                .min(max_svg_scale((tw_image.width, tw_image.height)).max(1.0));
+            // the texture created from the cache is at scale 1
+            let current = self.scales.entry(entity).or_insert(1.0);
+            if *current == scale { continue }
+            *current = scale;
+            let pending = Arc::clone(&self.pending);
+            let path = tw_image.file_name.clone();
+            thread::spawn(move || {
+                match rasterize_svg(Path::new(&path), scale) {
+                    Ok(buffer) => pending.lock().unwrap().push((entity, scale, TwPixels::from_buffer(buffer).with_mipmaps())),
+                    Err(e) => error!("Failed to rasterize {:?}: {:?}", &path, e)
+                }
+            });
+        }
+    }
+}
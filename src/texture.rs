@@ -0,0 +1,337 @@
+/// texture.rs contains the readers of the GPU texture files, .dds and .ktx2, with their mip levels
+/// and cube faces. The block compressed formats BC1 to BC7 are decoded on the CPU for the
+/// thumbnails, the export and the pixels reading, the blocks without alpha are also uploaded as
+/// they are when the device samples their format, see TwPixels.blocks.
+use amethyst::renderer::{Format, rendy::{factory::Factory, hal::{Backend, adapter::PhysicalDevice, format}}};
+use bcndecode::{BcnDecoderFormat, BcnEncoding};
+
+use std::convert::TryInto;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::decoder::{TwPixelBuffer, TwSamples, invalid_data};
+use crate::image::{TwBlocks, TwPixels};
+
+
+const DDS_MAGIC: &[u8] = b"DDS ";
+const KTX2_MAGIC: &[u8] = &[0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+pub const CUBE_FACES: [&str; 6] = ["+X", "-X", "+Y", "-Y", "+Z", "-Z"];
+const BLOCK_FORMATS: [Format; 5] = [Format::Bc1RgbaSrgb, Format::Bc1RgbaUnorm, Format::Bc4Unorm, Format::Bc5Unorm, Format::Bc6hUfloat];
+/// one bit per block format of BLOCK_FORMATS sampled by the device, none until the renderer is
+/// checked so the decoded pixels are uploaded instead.
+static SAMPLED_BLOCK_FORMATS: AtomicUsize = AtomicUsize::new(0);
+
+
+/// check which block formats the device samples, called once the renderer is created.
+pub fn check_block_formats<B: Backend>(factory: &Factory<B>) {
+    let sampled = BLOCK_FORMATS.iter().enumerate()
+        .filter(|(_, block_format)| {
+            factory.physical().format_properties(Some(**block_format)).optimal_tiling.contains(format::ImageFeature::SAMPLED)
+        })
+        .fold(0, |bits, (i, _)| bits | 1 << i);
+    if sampled != (1 << BLOCK_FORMATS.len()) - 1 {
+        warn!("Some block compressed formats are not supported by the device, their textures are decoded");
+    }
+    SAMPLED_BLOCK_FORMATS.store(sampled, Ordering::Relaxed);
+}
+
+
+/// whether the blocks of a format can be uploaded as they are.
+pub fn is_block_format_sampled(block_format: Format) -> bool {
+    let sampled = SAMPLED_BLOCK_FORMATS.load(Ordering::Relaxed);
+    BLOCK_FORMATS.iter().position(|f| *f == block_format).map_or(false, |i| (sampled >> i) & 1 != 0)
+}
+
+
+/// pixel formats of the texture files.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum TwTextureFormat {
+    Bc1,
+    Bc2,
+    Bc3,
+    Bc4,
+    Bc5,
+    Bc6h,
+    Bc7,
+    Rgba8,
+    Bgra8,
+}
+
+impl TwTextureFormat {
+    /// bytes of a 4x4 block, or of a pixel for the uncompressed formats.
+    fn block_bytes(self) -> usize {
+        match self {
+            TwTextureFormat::Bc1 | TwTextureFormat::Bc4 => 8,
+            TwTextureFormat::Rgba8 | TwTextureFormat::Bgra8 => 4,
+            _ => 16,
+        }
+    }
+
+    fn is_compressed(self) -> bool {
+        self != TwTextureFormat::Rgba8 && self != TwTextureFormat::Bgra8
+    }
+}
+
+
+/// a texture file, surfaces are stored face by face, each face has every mip level.
+#[derive(Debug, Clone)]
+pub struct TwTextureFile {
+    pub width: u32,
+    pub height: u32,
+    pub levels: u32,
+    pub faces: u32,
+    pub format: TwTextureFormat,
+    pub srgb: bool,
+    /// DXT2 and DXT4 colors are already multiplied by the alpha
+    pub premultiplied: bool,
+    surfaces: Vec<Vec<u8>>,
+}
+
+impl TwTextureFile {
+    pub fn level_size(&self, level: u32) -> (u32, u32) {
+        ((self.width >> level).max(1), (self.height >> level).max(1))
+    }
+
+    fn surface_bytes(&self, level: u32) -> usize {
+        let (width, height) = self.level_size(level);
+        if self.format.is_compressed() {
+            ((width as usize + 3) / 4) * ((height as usize + 3) / 4) * self.format.block_bytes()
+        } else {
+            width as usize * height as usize * 4
+        }
+    }
+
+    pub fn surface(&self, face: u32, level: u32) -> &[u8] {
+        &self.surfaces[(face * self.levels + level) as usize]
+    }
+
+    /// decode a surface as rgba 8bit pixels, premultiplied for DXT2 and DXT4.
+    fn decode_rgba(&self, face: u32, level: u32) -> io::Result<Vec<u8>> {
+        let (width, height) = self.level_size(level);
+        let data = self.surface(face, level);
+        let encoding = match self.format {
+            TwTextureFormat::Rgba8 => return Ok(data.to_vec()),
+            TwTextureFormat::Bgra8 => return Ok(data.chunks(4).flat_map(|p| vec![p[2], p[1], p[0], p[3]]).collect()),
+            TwTextureFormat::Bc1 => BcnEncoding::Bc1,
+            TwTextureFormat::Bc2 => BcnEncoding::Bc2,
+            TwTextureFormat::Bc3 => BcnEncoding::Bc3,
+            TwTextureFormat::Bc4 => BcnEncoding::Bc4,
+            TwTextureFormat::Bc5 => BcnEncoding::Bc5,
+            TwTextureFormat::Bc6h => BcnEncoding::Bc6H,
+            TwTextureFormat::Bc7 => BcnEncoding::Bc7,
+        };
+        bcndecode::decode(data, width as usize, height as usize, encoding, BcnDecoderFormat::RGBA)
+            .map_err(|e| invalid_data(format!("{:?}", e)))
+    }
+
+    /// decode a surface as straight alpha rgba 8bit pixels.
+    pub fn decode_surface(&self, face: u32, level: u32) -> io::Result<TwPixelBuffer> {
+        let (width, height) = self.level_size(level);
+        let mut rgba = self.decode_rgba(face, level)?;
+        if self.premultiplied {
+            for pixel in rgba.chunks_mut(4).filter(|p| p[3] > 0) {
+                let alpha = pixel[3] as u32;
+                for c in pixel.iter_mut().take(3) {
+                    *c = (*c as u32 * 255 / alpha).min(255) as u8;
+                }
+            }
+        }
+        Ok(TwPixelBuffer { width, height, channels: 4, samples: TwSamples::U8(rgba) })
+    }
+
+    /// GPU format to upload the blocks as they are. The formats with alpha are decoded instead so
+    /// their alpha is premultiplied like every other image, BC1 transparent texels are black so
+    /// its blocks are already premultiplied.
+    pub fn block_format(&self) -> Option<Format> {
+        match (self.format, self.srgb) {
+            (TwTextureFormat::Bc1, true) => Some(Format::Bc1RgbaSrgb),
+            (TwTextureFormat::Bc1, false) => Some(Format::Bc1RgbaUnorm),
+            (TwTextureFormat::Bc4, _) => Some(Format::Bc4Unorm),
+            (TwTextureFormat::Bc5, _) => Some(Format::Bc5Unorm),
+            (TwTextureFormat::Bc6h, _) => Some(Format::Bc6hUfloat),
+            _ => None,
+        }
+    }
+
+    /// the TwPixels of a surface, the decoded pixels with their mip chain and the blocks of the
+    /// level and its smaller levels when they can be uploaded directly.
+    pub fn surface_pixels(&self, face: u32, level: u32) -> io::Result<TwPixels> {
+        let mut tw_pixels = if self.premultiplied {
+            let (width, height) = self.level_size(level);
+            TwPixels {
+                width,
+                height,
+                data: Arc::new(self.decode_rgba(face, level)?),
+                format: Format::Rgba8Srgb,
+                swizzle: format::Swizzle::NO,
+                mips: Vec::new(),
+                blocks: None,
+            }
+        } else {
+            TwPixels::from_buffer(self.decode_surface(face, level)?)
+        }.with_mipmaps();
+        match self.block_format() {
+            Some(block_format) => {
+                let levels = (level..self.levels).map(|l| Arc::new(self.surface(face, l).to_vec())).collect();
+                let swizzle = if self.format == TwTextureFormat::Bc4 {
+                    format::Swizzle(format::Component::R, format::Component::R, format::Component::R, format::Component::One)
+                } else {
+                    format::Swizzle::NO
+                };
+                tw_pixels.blocks = Some(TwBlocks { format: block_format, swizzle, levels });
+                Ok(tw_pixels)
+            }
+            None => Ok(tw_pixels)
+        }
+    }
+
+    /// split the data following the header in surfaces, face by face or level by level.
+    fn read_surfaces(&mut self, data: &[u8], level_offsets: Option<&[usize]>) -> io::Result<()> {
+        let mut surfaces = vec![Vec::new(); (self.faces * self.levels) as usize];
+        let mut offset = 0;
+        for face in 0..self.faces {
+            for level in 0..self.levels {
+                let size = self.surface_bytes(level);
+                let start = match level_offsets {
+                    // ktx2, the faces of a level are one after the other
+                    Some(offsets) => offsets[level as usize] + face as usize * size,
+                    // dds, the levels of a face are one after the other
+                    None => { let start = offset; offset += size; start }
+                };
+                let surface = data.get(start..start + size).ok_or_else(|| invalid_data("Truncated texture data"))?;
+                surfaces[(face * self.levels + level) as usize] = surface.to_vec();
+            }
+        }
+        self.surfaces = surfaces;
+        Ok(())
+    }
+}
+
+
+fn u32_at(data: &[u8], offset: usize) -> io::Result<u32> {
+    data.get(offset..offset + 4).map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| invalid_data("Truncated texture header"))
+}
+
+
+fn u64_at(data: &[u8], offset: usize) -> io::Result<u64> {
+    data.get(offset..offset + 8).map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| invalid_data("Truncated texture header"))
+}
+
+
+pub fn is_texture_header(header: &[u8]) -> bool {
+    header.starts_with(DDS_MAGIC) || header.starts_with(KTX2_MAGIC)
+}
+
+
+/// read the header of a dds file, returns the texture without surfaces and the data offset.
+fn read_dds_header(data: &[u8]) -> io::Result<(TwTextureFile, usize)> {
+    let height = u32_at(data, 12)?;
+    let width = u32_at(data, 16)?;
+    let levels = u32_at(data, 28)?.max(1);
+    let pixel_flags = u32_at(data, 80)?;
+    let four_cc = data.get(84..88).ok_or_else(|| invalid_data("Truncated dds header"))?;
+    let caps2 = u32_at(data, 112)?;
+    let mut faces = if caps2 & 0x200 != 0 { 6 } else { 1 };
+    let mut srgb = false;
+    let premultiplied = four_cc == b"DXT2" || four_cc == b"DXT4";
+    let mut offset = 128;
+    let format = match four_cc {
+        b"DXT1" => TwTextureFormat::Bc1,
+        b"DXT2" | b"DXT3" => TwTextureFormat::Bc2,
+        b"DXT4" | b"DXT5" => TwTextureFormat::Bc3,
+        b"ATI1" | b"BC4U" => TwTextureFormat::Bc4,
+        b"ATI2" | b"BC5U" => TwTextureFormat::Bc5,
+        b"DX10" => {
+            let dxgi_format = u32_at(data, 128)?;
+            if u32_at(data, 136)? & 0x4 != 0 { faces = 6 }
+            offset = 148;
+            srgb = [29, 72, 75, 78, 91, 99].contains(&dxgi_format);
+            match dxgi_format {
+                28 | 29 => TwTextureFormat::Rgba8,
+                87 | 91 => TwTextureFormat::Bgra8,
+                71 | 72 => TwTextureFormat::Bc1,
+                74 | 75 => TwTextureFormat::Bc2,
+                77 | 78 => TwTextureFormat::Bc3,
+                80 => TwTextureFormat::Bc4,
+                83 => TwTextureFormat::Bc5,
+                95 => TwTextureFormat::Bc6h,
+                98 | 99 => TwTextureFormat::Bc7,
+                _ => return Err(invalid_data(format!("Unsupported dds DXGI format {}", dxgi_format)))
+            }
+        }
+        // uncompressed 32bit, the red mask tells the order
+        _ if pixel_flags & 0x40 != 0 && u32_at(data, 88)? == 32 => {
+            if u32_at(data, 92)? == 0xff { TwTextureFormat::Rgba8 } else { TwTextureFormat::Bgra8 }
+        }
+        _ => return Err(invalid_data(format!("Unsupported dds format {:?}", four_cc)))
+    };
+    Ok((TwTextureFile { width, height, levels, faces, format, srgb, premultiplied, surfaces: Vec::new() }, offset))
+}
+
+
+/// read the header of a ktx2 file, returns the texture without surfaces and the level offsets.
+fn read_ktx2_header(data: &[u8]) -> io::Result<(TwTextureFile, Vec<usize>)> {
+    let vk_format = u32_at(data, 12)?;
+    let width = u32_at(data, 20)?;
+    let height = u32_at(data, 24)?.max(1);
+    let faces = u32_at(data, 36)?.max(1);
+    let levels = u32_at(data, 40)?.max(1);
+    if u32_at(data, 44)? != 0 {
+        return Err(invalid_data("Supercompressed ktx2 files are not supported"))
+    }
+    let srgb = [43, 50, 132, 134, 136, 138, 146].contains(&vk_format);
+    let format = match vk_format {
+        37 | 43 => TwTextureFormat::Rgba8,
+        44 | 50 => TwTextureFormat::Bgra8,
+        131..=134 => TwTextureFormat::Bc1,
+        135 | 136 => TwTextureFormat::Bc2,
+        137 | 138 => TwTextureFormat::Bc3,
+        139 => TwTextureFormat::Bc4,
+        141 => TwTextureFormat::Bc5,
+        143 => TwTextureFormat::Bc6h,
+        145 | 146 => TwTextureFormat::Bc7,
+        _ => return Err(invalid_data(format!("Unsupported ktx2 Vulkan format {}", vk_format)))
+    };
+    let offsets = (0..levels as usize)
+        .map(|level| u64_at(data, 80 + level * 24).map(|offset| offset as usize))
+        .collect::<io::Result<Vec<_>>>()?;
+    Ok((TwTextureFile { width, height, levels, faces, format, srgb, premultiplied: false, surfaces: Vec::new() }, offsets))
+}
+
+
+/// header of a texture file, without the surfaces.
+pub fn read_texture_header(path: &Path) -> io::Result<TwTextureFile> {
+    use std::io::Read;
+    let mut header = vec![0; 4096];
+    let read = fs::File::open(path)?.read(&mut header)?;
+    header.truncate(read);
+    if header.starts_with(DDS_MAGIC) {
+        read_dds_header(&header).map(|(texture, _)| texture)
+    } else if header.starts_with(KTX2_MAGIC) {
+        read_ktx2_header(&header).map(|(texture, _)| texture)
+    } else {
+        Err(invalid_data("Not a dds or ktx2 file"))
+    }
+}
+
+
+/// read a whole texture file.
+pub fn read_texture_file(path: &Path) -> io::Result<TwTextureFile> {
+    let data = fs::read(path)?;
+    if data.starts_with(DDS_MAGIC) {
+        let (mut texture, offset) = read_dds_header(&data)?;
+        texture.read_surfaces(&data[offset..], None)?;
+        Ok(texture)
+    } else if data.starts_with(KTX2_MAGIC) {
+        let (mut texture, offsets) = read_ktx2_header(&data)?;
+        texture.read_surfaces(&data, Some(&offsets))?;
+        Ok(texture)
+    } else {
+        Err(invalid_data("Not a dds or ktx2 file"))
+    }
+}
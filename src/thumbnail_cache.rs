@@ -0,0 +1,161 @@
+/// thumbnail_cache.rs contains the persistent thumbnail cache on disk.
+/// It follow the freedesktop thumbnail specification layout, so the thumbnails are shared with
+/// the file managers:
+/// - $XDG_CACHE_HOME/thumbnails/normal for thumbnails up to 128 pixels, large up to 256 pixels
+/// - the file name is the md5 of the file URI with the png extension
+/// - the png stores the Thumb::URI, Thumb::MTime and Thumb::Size text chunks, a thumbnail is valid
+///   only if the modification time and the size of the original file still match.
+use image::{RgbaImage, DynamicImage, ImageOutputFormat};
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::image::{TwImage, TwPixels};
+use crate::decoder::{decode_file, probe_dimensions};
+
+
+const PNG_SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+
+/// escape a path as an URI, every byte outside the unreserved characters is percent encoded.
+pub fn file_uri(path: &Path) -> String {
+    let absolute = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut uri = String::from("file://");
+    for byte in absolute.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' | b'!' | b'*'
+            | b'\'' | b'(' | b')' => uri.push(byte as char),
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+
+/// path of the thumbnail of a file inside the thumbnail cache directory.
+pub fn thumbnail_path(file: &Path, size: u32) -> Option<PathBuf> {
+    let flavor = if size <= 128 { "normal" } else { "large" };
+    dirs::cache_dir().map(|cache| {
+        cache.join("thumbnails").join(flavor).join(format!("{:x}.png", md5::compute(file_uri(file))))
+    })
+}
+
+
+/// modification time in seconds and size in bytes of a file.
+fn file_stamp(file: &Path) -> Option<(u64, u64)> {
+    let metadata = fs::metadata(file).ok()?;
+    let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    Some((mtime, metadata.len()))
+}
+
+
+/// get the tEXt chunks of a png as keyword, text pairs.
+fn read_text_chunks(data: &[u8]) -> Vec<(String, String)> {
+    let mut chunks = Vec::new();
+    if data.len() < 8 || data[..8] != PNG_SIGNATURE { return chunks }
+    let mut i = 8;
+    while i + 8 <= data.len() {
+        let length = u32::from_be_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]) as usize;
+        let kind = &data[i + 4..i + 8];
+        if i + 12 + length > data.len() { break }
+        if kind == b"tEXt" {
+            let content = &data[i + 8..i + 8 + length];
+            if let Some(separator) = content.iter().position(|b| *b == 0) {
+                chunks.push((String::from_utf8_lossy(&content[..separator]).into_owned(),
+                             String::from_utf8_lossy(&content[separator + 1..]).into_owned()));
+            }
+        }
+        if kind == b"IDAT" || kind == b"IEND" { break }
+        i += 12 + length;
+    }
+    chunks
+}
+
+
+/// insert tEXt chunks right after the IHDR chunk of an encoded png.
+fn insert_text_chunks(data: Vec<u8>, texts: &[(&str, String)]) -> Vec<u8> {
+    // signature + IHDR chunk, IHDR data is always 13 bytes
+    let header_end = 8 + 12 + 13;
+    let mut output = data[..header_end].to_vec();
+    for (keyword, text) in texts {
+        let mut content = b"tEXt".to_vec();
+        content.extend_from_slice(keyword.as_bytes());
+        content.push(0);
+        content.extend_from_slice(text.as_bytes());
+        output.extend_from_slice(&((content.len() - 4) as u32).to_be_bytes());
+        output.extend_from_slice(&content);
+        output.extend_from_slice(&crc32fast::hash(&content).to_be_bytes());
+    }
+    output.extend_from_slice(&data[header_end..]);
+    output
+}
+
+
+/// read the thumbnail of a file from the cache, None if missing or outdated.
+/// Returns the thumbnail with the original image dimensions.
+pub fn read_thumbnail(file: &Path, size: u32) -> Option<(RgbaImage, (u32, u32))> {
+    let (mtime, file_size) = file_stamp(file)?;
+    let data = fs::read(thumbnail_path(file, size)?).ok()?;
+    let texts = read_text_chunks(&data);
+    let text = |key: &str| texts.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone());
+    if text("Thumb::MTime")? != mtime.to_string() { return None }
+    if let Some(thumb_size) = text("Thumb::Size") {
+        if thumb_size != file_size.to_string() { return None }
+    }
+    // dimensions are optional in the specification, the thumbnails of other softwares may miss them
+    let dimensions = match (text("Thumb::Image::Width").and_then(|w| w.parse().ok()),
+                            text("Thumb::Image::Height").and_then(|h| h.parse().ok())) {
+        (Some(width), Some(height)) => (width, height),
+        _ => probe_dimensions(file).ok()?,
+    };
+    image::load_from_memory(&data).ok().map(|image| (image.to_rgba(), dimensions))
+}
+
+
+/// write the thumbnail of a file in the cache, written in a temporary file then renamed as asked
+/// by the specification.
+pub fn write_thumbnail(file: &Path, size: u32, thumbnail: &RgbaImage, dimensions: (u32, u32)) -> io::Result<()> {
+    let (mtime, file_size) = file_stamp(file).ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No file metadata"))?;
+    let path = thumbnail_path(file, size).ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No cache directory"))?;
+    let dir = path.parent().unwrap();
+    fs::create_dir_all(dir)?;
+    let mut encoded = Vec::new();
+    DynamicImage::ImageRgba8(thumbnail.clone()).write_to(&mut encoded, ImageOutputFormat::PNG)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    let encoded = insert_text_chunks(encoded, &[("Thumb::URI", file_uri(file)),
+                                                 ("Thumb::MTime", mtime.to_string()),
+                                                 ("Thumb::Size", file_size.to_string()),
+                                                 ("Thumb::Image::Width", dimensions.0.to_string()),
+                                                 ("Thumb::Image::Height", dimensions.1.to_string()),
+                                                 ("Software", "Tower View".to_owned())]);
+    // the md5 cache key keeps the loading threads of the same process from sharing a temporary file
+    let key = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("thumbnail");
+    let temporary = dir.join(format!("tower-{}-{}.png.tmp", key, std::process::id()));
+    fs::write(&temporary, encoded)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&temporary, fs::Permissions::from_mode(0o600))?;
+    }
+    fs::rename(&temporary, &path)
+}
+
+
+/// get the thumbnail of a file, from the disk cache if valid, otherwise the thumbnail is generated
+/// and saved in the disk cache. Same output as load_thumbnail, the TwImage keeps the original size.
+pub fn load_cached_thumbnail(name: &str, size: u32) -> Result<(TwImage, TwPixels), image::ImageError> {
+    let path = Path::new(name);
+    if let Some((thumbnail, (width, height))) = read_thumbnail(path, size) {
+        debug!("Thumbnail read from disk cache {:?}", name);
+        return Ok((TwImage::new(width, height, name), TwPixels::from_rgba(thumbnail)))
+    }
+    let buffer = decode_file(path)?;
+    let (width, height) = (buffer.width, buffer.height);
+    let thumbnail = image::imageops::thumbnail(&buffer.to_rgba(), size, size);
+    if let Err(e) = write_thumbnail(path, size, &thumbnail, (width, height)) {
+        warn!("Failed to write thumbnail of {:?} in disk cache: {:?}", name, e);
+    }
+    Ok((TwImage::new(width, height, name), TwPixels::from_rgba(thumbnail)))
+}
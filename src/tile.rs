@@ -0,0 +1,109 @@
+/// tile.rs contains the tiled representation of the huge images and its non system functions.
+/// An image larger than MAX_TEXTURE_SIZE is displayed with the texture of its first mip level that
+/// fit, then the tiles of the finer levels are streamed over it when the camera comes closer.
+/// Tiles are children of the TwImage entity, so they follow its moves, rotation and scale.
+use amethyst::ecs::prelude::{Component, DenseVecStorage, Entity};
+use amethyst::renderer::sprite::Sprite;
+
+use std::sync::Arc;
+
+use crate::image::{TwImage, TwPixels};
+
+
+// TODO: texture and tile sizes as settings
+pub const MAX_TEXTURE_SIZE: u32 = 8192;
+pub const TILESIZE: u32 = 2048;
+
+
+/// tile component, a part of a mip level of the image entity.
+#[derive(PartialEq, Debug, Clone)]
+pub struct TwTile {
+    pub image: Entity,
+    pub level: usize,
+    pub column: u32,
+    pub row: u32,
+    // flip flags of the image when the tile was placed
+    pub flip: (bool, bool),
+}
+
+impl Component for TwTile {
+    type Storage = DenseVecStorage<Self>;
+}
+
+
+/// level of detail needed to display an image at the camera distance, the log2 of the image
+/// pixels covered by one screen pixel. At real_size_z one image pixel is one screen pixel.
+pub fn required_lod(camera_z: f32, real_size_z: f32, scale: f32) -> f32 {
+    let ratio = camera_z / real_size_z / scale;
+    if ratio <= 1.0 { 0.0 } else { ratio.log2() }
+}
+
+
+/// mip level needed to display an image at the camera distance.
+pub fn required_level(camera_z: f32, real_size_z: f32, scale: f32) -> usize {
+    required_lod(camera_z, real_size_z, scale).floor() as usize
+}
+
+
+/// rectangle of a tile in full resolution pixel coord, x, y, width, height.
+pub fn tile_rect(tw_pixels: &TwPixels, level: usize, column: u32, row: u32) -> (f32, f32, f32, f32) {
+    let (width, height, _) = tw_pixels.level(level);
+    let factor = (1 << level) as f32;
+    let tile_width = TILESIZE.min(width - column * TILESIZE);
+    let tile_height = TILESIZE.min(height - row * TILESIZE);
+    ((column * TILESIZE) as f32 * factor, (row * TILESIZE) as f32 * factor,
+     tile_width as f32 * factor, tile_height as f32 * factor)
+}
+
+
+/// tiles of a level intersecting a rectangle in full resolution pixel coord,
+/// given as min and max corners.
+pub fn visible_tiles(tw_pixels: &TwPixels, level: usize, min: (f32, f32), max: (f32, f32)) -> Vec<(u32, u32)> {
+    let (width, height, _) = tw_pixels.level(level);
+    let tile_size = (TILESIZE << level as u32) as f32;
+    let columns = (width + TILESIZE - 1) / TILESIZE;
+    let rows = (height + TILESIZE - 1) / TILESIZE;
+    let first_column = (min.0 / tile_size).floor().max(0.0) as u32;
+    let first_row = (min.1 / tile_size).floor().max(0.0) as u32;
+    let last_column = ((max.0 / tile_size).floor().max(0.0) as u32).min(columns - 1);
+    let last_row = ((max.1 / tile_size).floor().max(0.0) as u32).min(rows - 1);
+    let mut tiles = Vec::new();
+    for row in first_row..=last_row {
+        for column in first_column..=last_column {
+            tiles.push((column, row));
+        }
+    }
+    tiles
+}
+
+
+/// copy the pixels of a tile out of its mip level.
+pub fn tile_pixels(tw_pixels: &TwPixels, level: usize, column: u32, row: u32) -> TwPixels {
+    let (width, height, data) = tw_pixels.level(level);
+    let channels = data.len() / (width as usize * height as usize).max(1);
+    let tile_width = TILESIZE.min(width - column * TILESIZE) as usize;
+    let tile_height = TILESIZE.min(height - row * TILESIZE) as usize;
+    let (x, y) = ((column * TILESIZE) as usize, (row * TILESIZE) as usize);
+    let mut pixels = Vec::with_capacity(tile_width * tile_height * channels);
+    for line in y..y + tile_height {
+        let start = (line * width as usize + x) * channels;
+        pixels.extend_from_slice(&data[start..start + tile_width * channels]);
+    }
+    TwPixels {
+        width: tile_width as u32,
+        height: tile_height as u32,
+        data: Arc::new(pixels),
+        format: tw_pixels.format,
+        swizzle: tw_pixels.swizzle,
+        mips: Vec::new(),
+        blocks: None,
+    }
+}
+
+
+/// sprite of a tile sized in full resolution pixels, the whole tile texture is used.
+pub fn build_tile_sprite(tw_image: &TwImage, width: f32, height: f32) -> Sprite {
+    let (left, right) = if tw_image.flip_horizontal { (1.0, 0.0) } else { (0.0, 1.0) };
+    let (bottom, top) = if tw_image.flip_vertical { (0.0, 1.0) } else { (1.0, 0.0) };
+    Sprite::from(((width, height), [left, right, bottom, top]))
+}
@@ -0,0 +1,143 @@
+/// tile_system.rs contains the streaming of the tiles of the huge images.
+use amethyst::{
+    assets::{AssetStorage, Loader},
+    core::{SystemDesc, Transform, Parent, math::Point3},
+    derive::SystemDesc,
+    ecs::{Join, Read, System, SystemData, World, WriteStorage},
+    ecs::prelude::*,
+    renderer::{Camera, SpriteRender, SpriteSheet, Texture, Transparent, resources::Tint},
+    window::ScreenDimensions,
+};
+
+use std::collections::HashSet;
+
+use crate::image::TwImage;
+use crate::tile::{TwTile, required_level, tile_rect, visible_tiles, tile_pixels, build_tile_sprite};
+use crate::tower::TowerData;
+use crate::raycasting_system::screen_to_world;
+
+
+// TODO: tiles uploaded per frame as settings
+const TILES_PER_FRAME: usize = 2;
+
+
+#[derive(SystemDesc, Default)]
+pub struct TwTileStreamingSystem;
+/// stream the tiles of the huge images, only the tiles under the camera view are created at the
+/// mip level needed by the camera Z, the others are deleted.
+/// The tiles are created from the cached TwPixels, a few per frame to keep the board smooth.
+/// Cropped images keep their single texture.
+impl<'s> System<'s> for TwTileStreamingSystem {
+    type SystemData = (ReadStorage<'s, TwImage>,
+                       ReadStorage<'s, TwTile>,
+                       ReadStorage<'s, Transform>,
+                       WriteStorage<'s, Tint>,
+                       ReadStorage<'s, Camera>,
+                       ReadExpect<'s, ScreenDimensions>,
+                       Read<'s, TowerData>,
+                       Read<'s, AssetStorage<Texture>>,
+                       Read<'s, AssetStorage<SpriteSheet>>,
+                       ReadExpect<'s, Loader>,
+                       Write<'s, LazyUpdate>,
+                       Entities<'s>);
+    fn run(&mut self, (
+        tw_images,
+        tw_tiles,
+        transforms,
+        mut tints,
+        cameras,
+        screen_dimensions,
+        td,
+        texture_storage,
+        sprite_storage,
+        loader,
+        world,
+        entities,
+    ): Self::SystemData) {
+        // tiles of deleted or replaced images
+        for (tile, entity) in (&tw_tiles, &*entities).join() {
+            if !entities.is_alive(tile.image) {
+                entities.delete(entity).expect("Failed to delete tile entity.");
+            }
+        }
+        let cache = match td.cache.try_lock() {
+            Ok(cache) => cache,
+            Err(_e) => return
+        };
+        let (camera, cam_transform) = (&cameras, &transforms).join().next().unwrap();
+        let (width, height) = (screen_dimensions.width(), screen_dimensions.height());
+        let corners = [(0.0, 0.0), (width, 0.0), (0.0, height), (width, height)].iter()
+            .map(|corner| screen_to_world(*corner, camera, cam_transform, &screen_dimensions))
+            .collect::<Vec<_>>();
+        let mut wanted = HashSet::new();
+        let mut to_create = Vec::new();
+        for (tw_image, transform, entity) in (&tw_images, &transforms, &*entities).join() {
+            if tw_image.crop.is_some() { continue }
+            let tw_pixels = match cache.get(&tw_image.file_name) {
+                Some((_, tw_pixels)) => tw_pixels,
+                None => continue
+            };
+            let first = tw_pixels.first_texture_level();
+            let level = required_level(cam_transform.translation().z, td.real_size_z, tw_image.scale);
+            if level >= first { continue }
+            // camera view in full resolution pixel coord of the image
+            let inverse = match transform.matrix().try_inverse() {
+                Some(inverse) => inverse,
+                None => continue
+            };
+            let pixels = corners.iter().map(|corner| {
+                let local = inverse.transform_point(&Point3::new(corner.x, corner.y, 0.0));
+                tw_image.local_to_pixel((local.x, local.y))
+            }).collect::<Vec<_>>();
+            let min = pixels.iter().fold((std::f32::MAX, std::f32::MAX), |a, p| (a.0.min(p.0), a.1.min(p.1)));
+            let max = pixels.iter().fold((std::f32::MIN, std::f32::MIN), |a, p| (a.0.max(p.0), a.1.max(p.1)));
+            if max.0 < 0.0 || max.1 < 0.0 || min.0 > tw_image.width as f32 || min.1 > tw_image.height as f32 { continue }
+            for (column, row) in visible_tiles(tw_pixels, level, min, max) {
+                wanted.insert((entity, level, column, row));
+                to_create.push((entity, level, column, row));
+            }
+        }
+        // drop the tiles out of view, at an other level or placed with other flips
+        let mut existing = HashSet::new();
+        for (tile, entity) in (&tw_tiles, &*entities).join() {
+            let key = (tile.image, tile.level, tile.column, tile.row);
+            let flip = tw_images.get(tile.image).map(|tw_image| (tw_image.flip_horizontal, tw_image.flip_vertical));
+            if !wanted.contains(&key) || flip != Some(tile.flip) {
+                entities.delete(entity).expect("Failed to delete tile entity.");
+            } else {
+                existing.insert(key);
+                // tiles follow the image channels
+                if let Some(tint) = tints.get(tile.image).cloned() {
+                    tints.insert(entity, tint).expect("Failed to insert tile tint.");
+                }
+            }
+        }
+        for (image, level, column, row) in to_create.into_iter()
+            .filter(|key| !existing.contains(key))
+            .take(TILES_PER_FRAME) {
+            let tw_image = tw_images.get(image).unwrap();
+            let tw_pixels = &cache.get(&tw_image.file_name).unwrap().1;
+            let pixels = tile_pixels(tw_pixels, level, column, row);
+            let texture = loader.load_from_data(pixels.texture_data(tw_image.filter.unwrap_or(td.filter)), (), &texture_storage);
+            let (x, y, tile_width, tile_height) = tile_rect(tw_pixels, level, column, row);
+            let sprite_sheet = SpriteSheet {
+                texture,
+                sprites: vec![build_tile_sprite(tw_image, tile_width, tile_height)],
+            };
+            let sprite_sheet = loader.load_from_data(sprite_sheet, (), &sprite_storage);
+            let center = tw_image.pixel_to_local((x + tile_width * 0.5, y + tile_height * 0.5));
+            let mut transform = Transform::default();
+            // tiles are drawn just above their image and under the next one
+            transform.set_translation_xyz(center.0, center.1, 0.0002);
+            world.create_entity(&*entities)
+                .with(TwTile { image, level, column, row, flip: (tw_image.flip_horizontal, tw_image.flip_vertical) })
+                .with(Parent { entity: image })
+                .with(transform)
+                .with(SpriteRender { sprite_sheet, sprite_number: 0 })
+                .with(tints.get(image).cloned().unwrap_or_default())
+                .with(Transparent)
+                .build();
+            debug!("Tile {:?} {:?} of level {:?} is streamed for {:?}", column, row, level, &tw_image.file_name);
+        }
+    }
+}
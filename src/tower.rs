@@ -3,8 +3,7 @@ use amethyst::{
     ecs::prelude::*,
     prelude::*,
     core::math::{Point2, Point3},
-    renderer::types::TextureData,
-    renderer::{debug_drawing::{DebugLines, DebugLinesComponent, DebugLinesParams}},
+    renderer::{debug_drawing::{DebugLines, DebugLinesComponent, DebugLinesParams}, rendy::factory::Factory, types::DefaultBackend},
     window::ScreenDimensions,
 };
 use geo::{Rect};
@@ -12,21 +11,28 @@ use geo::{Rect};
 
 use crate::camera;
 
-use crate::image::TwImage;
+use crate::image::{TwImage, TwPixels, TwFilter};
 use crate::args_cli::Opt;
 use crate::inputshandler::{get_drop_file, get_moved_mouse, TwInputsHandler, alt_mouse_pressed,
                            mouse_released, alt_mouse_released, key_pressed, key_released,
-                           ctrl_mouse_pressed, ctrl_mouse_released, mouse_pressed};
+                           ctrl_mouse_pressed, ctrl_mouse_released, mouse_pressed,
+                           shift_mouse_pressed, shift_mouse_released};
 
-use crate::utils::{list_valid_files};
+use crate::utils::{list_working_set, TwListOptions};
+use crate::archive::{resolve_input, is_archive_file, TwExtractions};
+use crate::download::{is_url, TwDownload};
+use crate::instance::start_instance_server;
+use crate::remote::{start_remote_server, TwRemoteCall};
+use crate::texture::check_block_formats;
 
 
 
 
 use std::sync::{Arc, Mutex};
 use std::ffi::{OsStr, OsString};
-use std::collections::HashMap;
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::fs;
 
 use std::time::Duration;
 
@@ -35,6 +41,7 @@ pub const BACKGROUNDCOLOR: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
 pub const BACKGROUNDCOLOR2: [f32; 4] = [0.1, 0.1, 0.1, 1.0];
 pub const WINDOWWIDTH: f32 = 1280.0;
 pub const WINDOWHEIGHT: f32 = 720.0;
+pub const THUMBNAILSIZE: u32 = 128;
 
 
 pub struct TowerData {
@@ -42,14 +49,25 @@ pub struct TowerData {
     pub scene_rect: Rect<f32>,
     pub active_rect: Rect<f32>,
     pub scene_middle_point: Point2<f32>,
-    pub cache: Arc<Mutex<HashMap<String, (TwImage, TextureData)>>>,
+    pub cache: Arc<Mutex<HashMap<String, (TwImage, TwPixels)>>>,
+    // paths that failed to load in cache, their TwPlaceHolder are deleted
+    pub failed: Arc<Mutex<HashSet<String>>>,
+    pub thumbnails: Arc<Mutex<HashMap<String, (TwImage, TwPixels)>>>,
     pub working_dir: OsString,
+    pub working_dirs: Vec<OsString>,
     pub file_to_cache: Vec<OsString>,
     pub files_order: Vec<OsString>,
     pub inputs_path: Vec<String>,
     pub debug_line_start: Point3<f32>,
     pub debug_line_end: Point3<f32>,
     pub real_size_z: f32,
+    pub scene_path: Option<PathBuf>,
+    pub filter: TwFilter,
+    pub list_options: TwListOptions,
+    pub downloads: Arc<Mutex<HashMap<String, TwDownload>>>,
+    pub extractions: Arc<Mutex<TwExtractions>>,
+    pub remote_inputs: Arc<Mutex<Vec<String>>>,
+    pub remote_calls: Arc<Mutex<Vec<TwRemoteCall>>>,
 }
 
 impl Default for TowerData {
@@ -59,37 +77,104 @@ impl Default for TowerData {
             scene_rect: Rect::new((0.0, 0.0), (0.0, 0.0)),
             active_rect: Rect::new((0.0, 0.0), (0.0, 0.0)),
             cache: Arc::new(Mutex::new(HashMap::new())),
+            failed: Arc::new(Mutex::new(HashSet::new())),
+            thumbnails: Arc::new(Mutex::new(HashMap::new())),
             working_dir: OsStr::new(".").to_owned(),
+            working_dirs: Vec::new(),
             file_to_cache: Vec::new(),
             files_order: Vec::new(),
             scene_middle_point: Point2::new(0.0, 0.0),
             inputs_path: Vec::new(),
             debug_line_start: Point3::new(0.0, 0.0, 0.0),
             debug_line_end: Point3::new(0.0, 0.0, 0.0),
-            real_size_z: 0.0
+            real_size_z: 0.0,
+            scene_path: None,
+            filter: TwFilter::default(),
+            list_options: TwListOptions::default(),
+            downloads: Arc::new(Mutex::new(HashMap::new())),
+            extractions: Arc::new(Mutex::new(TwExtractions::default())),
+            remote_inputs: Arc::new(Mutex::new(Vec::new())),
+            remote_calls: Arc::new(Mutex::new(Vec::new())),
         }
     }
 }
 
+impl TowerData {
+    /// add a directory to the working set, the navigation order is listed again with the files
+    /// of every directory of the set and the cache is kept.
+    /// the directory becomes the current working dir.
+    pub fn add_working_dir(&mut self, dir: &Path) {
+        self.set_working_dirs(&[dir.to_path_buf()]);
+    }
+
+    /// add the directories to the working set then list the navigation order once, the last
+    /// directory becomes the current working dir.
+    pub fn set_working_dirs(&mut self, dirs: &[PathBuf]) {
+        // the same directory can be reached from different paths, ./shots and shots/
+        let canonical = |dir: &OsStr| fs::canonicalize(dir).unwrap_or_else(|_| PathBuf::from(dir));
+        let mut canonicals = self.working_dirs.iter().map(|dir| canonical(dir)).collect::<Vec<_>>();
+        for dir in dirs {
+            let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir.as_path() };
+            let dir = dir.as_os_str().to_owned();
+            let canonical_dir = canonical(&dir);
+            if !canonicals.contains(&canonical_dir) {
+                canonicals.push(canonical_dir);
+                self.working_dirs.push(dir.clone());
+                info!("Directory {:?} added to the working set", &dir);
+            }
+            self.working_dir = dir;
+        }
+        self.files_order = list_working_set(&self.working_dirs, &self.list_options);
+        self.file_to_cache = self.files_order.clone();
+    }
+}
+
 #[derive(Default)]
 pub struct Tower;
 
 impl<'a> SimpleState for Tower {
     fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
         let world = data.world;
+        // the blocks of the texture files are uploaded only in the formats the device samples
+        if let Some(factory) = world.try_fetch::<Factory<DefaultBackend>>() {
+            check_block_formats(&*factory);
+        }
         // init tower data
         let mut tower_data = TowerData::default();
         // init camera
         camera::initialise_camera(world, &mut tower_data);
         // command line arguments
         let opt = Opt::from_args();
+        tower_data.filter = opt.filter;
+        tower_data.list_options.sort_by = opt.sort;
+        tower_data.list_options.reverse = opt.reverse;
+        tower_data.list_options.filter = opt.glob.or(opt.regex);
+        tower_data.list_options.depth = opt.depth.unwrap_or(if opt.recursive { std::usize::MAX } else { 0 });
+        if opt.single_instance {
+            if let Err(e) = start_instance_server(Arc::clone(&tower_data.remote_inputs)) {
+                warn!("Single instance mode disabled: {:?}", e);
+            }
+        }
+        if opt.remote {
+            if let Err(e) = start_remote_server(Arc::clone(&tower_data.remote_calls)) {
+                warn!("Remote control disabled: {:?}", e);
+            }
+        }
         // get file to cache
-        tower_data.inputs_path = opt.inputs.iter().map(|input| input.to_owned()).collect::<Vec<_>>();
-        if let Some(last_input_path) = tower_data.inputs_path.last() {
-            tower_data.working_dir = Path::new(last_input_path).parent().unwrap().as_os_str().to_owned();
+        tower_data.inputs_path = opt.inputs.iter().map(|input| resolve_input(input)).collect::<Vec<_>>();
+        // the directories are working dirs themselves, the files bring their parent
+        let input_dirs = tower_data.inputs_path.iter()
+            .filter(|input| !is_url(input) && !is_archive_file(Path::new(input)))
+            .map(|input| {
+                let path = Path::new(input);
+                if path.is_dir() { path.to_path_buf() } else { path.parent().unwrap_or(Path::new("")).to_path_buf() }
+            })
+            .collect::<Vec<_>>();
+        if input_dirs.is_empty() {
+            tower_data.add_working_dir(Path::new("."));
+        } else {
+            tower_data.set_working_dirs(&input_dirs);
         }
-        tower_data.file_to_cache = list_valid_files(&tower_data.working_dir);
-        tower_data.files_order = tower_data.file_to_cache.clone();
         world.insert(tower_data);
         // init twinputshandler
         let mut tw_inputs_handler = TwInputsHandler::default();
@@ -166,6 +251,23 @@ impl<'a> SimpleState for Tower {
                     debug!("Ctrl+Click released");
                 }
             }
+            // shift mouse pressed event
+            if let Some(button) = shift_mouse_pressed(&event) {
+                {
+                    let mut tw_in = data.world.fetch_mut::<TwInputsHandler>();
+                    tw_in.shift_mouse_button_pressed = Some(button);
+                    tw_in.mouse_world_clicked_position = tw_in.mouse_world_position;
+                    debug!("Shift+Click pressed");
+                }
+            }
+            // shift mouse release
+            if let Some(_button) = shift_mouse_released(&event) {
+                {
+                    let mut tw_in = data.world.fetch_mut::<TwInputsHandler>();
+                    tw_in.shift_mouse_button_pressed = None;
+                    debug!("Shift+Click released");
+                }
+            }
             // mouse pressed event
             if let Some(button) = mouse_pressed(&event) {
                 {
@@ -188,6 +290,7 @@ impl<'a> SimpleState for Tower {
                     tw_in.mouse_button_pressed = None;
                     tw_in.alt_mouse_button_pressed = None;
                     tw_in.ctrl_mouse_button_pressed = None;
+                    tw_in.shift_mouse_button_pressed = None;
                     tw_in.mouse_world_clicked_position = None;
                     tw_in.mouse_double_clicked = None;
                     debug!("Mouse click released");
@@ -197,8 +300,11 @@ impl<'a> SimpleState for Tower {
             if let Some(key_code) = key_pressed(&event) {
                 {
                     let mut tw_in = data.world.fetch_mut::<TwInputsHandler>();
-                    tw_in.keys_pressed.push(key_code);
-                    debug!("Key pressed {:?}", key_code);
+                    // keys typed in a note, the export path or the files filter are not shortcuts
+                    if !tw_in.ui_wants_keyboard {
+                        tw_in.keys_pressed.push(key_code);
+                        debug!("Key pressed {:?}", key_code);
+                    }
                 }
             }
             // keyboard released
@@ -1,19 +1,54 @@
 /// contains all UI related system, use ImGui as GUI lib
 use amethyst::ecs::{Join, WriteStorage};
 use amethyst::ecs::prelude::*;
+use amethyst::core::Transform;
+use amethyst::assets::{AssetStorage, Loader};
+use amethyst::renderer::{Texture, sprite::{SpriteRender, SpriteSheet}};
 use amethyst_imgui::{
 	imgui,
-	imgui::{im_str, Condition},
+	imgui::{im_str, Condition, ImString, TextureId},
+	ImguiState,
 };
 
 use amethyst::input::{VirtualKeyCode};
-use crate::image::{TwActiveUiComponent, TwImage};
+use crate::image::{TwActiveUiComponent, TwImage, TwFilter, TwPixels};
 use crate::inputshandler::TwInputsHandler;
+use crate::placeholder::TwPlaceHolder;
+use crate::tower::{TowerData, THUMBNAILSIZE};
+use crate::utils::{list_working_set, TwSortBy, TwFileFilter};
+use crate::texture::{TwTextureFile, CUBE_FACES, read_texture_file, read_texture_header};
+use crate::decoder::{TwSubImage, compose_sub_images, list_sub_images, sub_image_path};
+use crate::web_formats::{decode_webp, webp_frame_timestamps};
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 
 
 pub const UI_WIDTH: f32 = 300.0;
 
 
+#[derive(Default, Clone, Copy)]
+pub struct UiKeyboardCaptureSystem;
+/// read each frame if an imgui text field has the keyboard. While it lasts, the keys pressed are
+/// not pushed in TwInputsHandler.keys_pressed, see tower.rs, and the keys already pressed are
+/// dropped when the capture starts, so typing doesn't trigger the board shortcuts.
+impl<'s> amethyst::ecs::System<'s> for UiKeyboardCaptureSystem {
+	type SystemData = WriteExpect<'s, TwInputsHandler>;
+	fn run(&mut self, mut tw_in: Self::SystemData) {
+		let capture = amethyst_imgui::with(|ui| ui.io().want_capture_keyboard).unwrap_or(false);
+		if capture && !tw_in.ui_wants_keyboard {
+			tw_in.keys_pressed.clear();
+			debug!("Keyboard captured by the UI");
+		}
+		tw_in.ui_wants_keyboard = capture;
+	}
+}
+
+
 #[derive(Default, Clone, Copy)]
 pub struct SliderChannelsSystem {
 	pub open: bool,
@@ -57,3 +92,587 @@ impl<'s> amethyst::ecs::System<'s> for SliderChannelsSystem {
 		if twactives.is_empty() { self.open = false }
     }
 }
+
+
+#[derive(Default, Clone, Copy)]
+pub struct MetadataSystem {
+	pub open: bool,
+}
+/// generate a window with the metadata of the active image, read by the loader thread.
+/// File, dimensions, color type and bit depth, ICC profile, then EXIF and EXR attributes if any.
+/// Window is opened with I key and keep open while escape key is not pushed
+impl<'s> amethyst::ecs::System<'s> for MetadataSystem {
+	type SystemData = (WriteExpect<'s, TwInputsHandler>,
+					   ReadStorage<'s, TwActiveUiComponent>,
+					   ReadStorage<'s, TwImage>);
+	fn run(&mut self, (
+			mut tw_in,
+			twactives,
+			twimages,
+	) : Self::SystemData) {
+		if tw_in.keys_pressed.contains(&VirtualKeyCode::I) && tw_in.keys_pressed.len() == 1 {
+			if Duration::from_millis(500) <= tw_in.stopwatch.elapsed() {
+				self.open = true;
+				tw_in.stopwatch.restart();
+			}
+		}
+		if tw_in.keys_pressed.contains(&VirtualKeyCode::Escape) { self.open = false }
+		if !self.open { return }
+		let metadata = match (&twactives, &twimages).join().next().and_then(|(_, twimage)| twimage.metadata.clone()) {
+			Some(metadata) => metadata,
+			None => return
+		};
+		amethyst_imgui::with(|ui| {
+			imgui::Window::new(im_str!("Metadata"))
+				.size([UI_WIDTH * 1.5, 0.0], Condition::Always)
+				.position([ui.io().mouse_pos[0] - UI_WIDTH * 0.75, ui.io().mouse_pos[1]], Condition::Appearing)
+				.build(ui, || {
+					ui.text_wrapped(&ImString::new(metadata.path.clone()));
+					ui.text(format!("File size: {:.2} MB", metadata.file_size as f32 / 1_000_000.0));
+					ui.text(format!("Dimensions: {} x {}", metadata.dimensions.0, metadata.dimensions.1));
+					ui.text(format!("Color: {}, {} bit", metadata.color_type, metadata.bit_depth));
+					ui.text(format!("ICC profile: {}", metadata.icc_profile.as_ref().map_or("none", |name| name.as_str())));
+					if !metadata.exif.is_empty() && imgui::CollapsingHeader::new(ui, im_str!("EXIF")).default_open(true).build() {
+						for (name, value) in &metadata.exif {
+							ui.text(format!("{}: {}", name, value));
+						}
+					}
+					if !metadata.exr_attributes.is_empty() && imgui::CollapsingHeader::new(ui, im_str!("EXR header")).default_open(true).build() {
+						for (name, value) in &metadata.exr_attributes {
+							ui.text(format!("{}: {}", name, value));
+						}
+					}
+				});
+		});
+	}
+}
+
+
+#[derive(Default)]
+pub struct TextureSurfaceSystem {
+	pub open: bool,
+	// header of the files, None when it's not a texture file
+	headers: HashMap<String, Option<TwTextureFile>>,
+	// face and mip level shown by each image
+	surfaces: HashMap<Entity, (u32, u32)>,
+	loaded: Arc<Mutex<Vec<(Entity, (u32, u32), TwPixels)>>>,
+}
+/// generate a window to browse the mip levels and the cube faces of the active image when it's a
+/// dds or ktx2 texture. The chosen surface is read from the file in a new thread and replaces the
+/// texture of the image, the sprite keeps the full resolution size.
+/// Window is opened with D key and keep open while escape key is not pushed
+impl<'s> amethyst::ecs::System<'s> for TextureSurfaceSystem {
+	type SystemData = (WriteExpect<'s, TwInputsHandler>,
+					   Read<'s, TowerData>,
+					   ReadStorage<'s, TwActiveUiComponent>,
+					   ReadStorage<'s, TwImage>,
+					   ReadStorage<'s, SpriteRender>,
+					   Write<'s, AssetStorage<SpriteSheet>>,
+					   Read<'s, AssetStorage<Texture>>,
+					   ReadExpect<'s, Loader>,
+					   Entities<'s>);
+	fn run(&mut self, (
+			mut tw_in,
+			tw_data,
+			twactives,
+			twimages,
+			sprites,
+			mut sprite_sheets,
+			texture_storage,
+			loader,
+			entities,
+	) : Self::SystemData) {
+		// surfaces read by the threads
+		if let Ok(mut loaded) = self.loaded.try_lock() {
+			for (entity, surface, tw_pixels) in loaded.drain(..) {
+				if !entities.is_alive(entity) { continue }
+				// an older surface read slower than the one chosen after it
+				if self.surfaces.get(&entity) != Some(&surface) { continue }
+				if let (Some(twimage), Some(sprite)) = (twimages.get(entity), sprites.get(entity)) {
+					let texture_data = tw_pixels.texture_data(twimage.filter.unwrap_or(tw_data.filter));
+					let texture = loader.load_from_data(texture_data, (), &texture_storage);
+					if let Some(sprite_sheet) = sprite_sheets.get_mut(&sprite.sprite_sheet) {
+						sprite_sheet.texture = texture;
+					}
+				}
+			}
+		}
+		if tw_in.keys_pressed.contains(&VirtualKeyCode::D) && tw_in.keys_pressed.len() == 1 {
+			if Duration::from_millis(500) <= tw_in.stopwatch.elapsed() {
+				self.open = true;
+				tw_in.stopwatch.restart();
+			}
+		}
+		if tw_in.keys_pressed.contains(&VirtualKeyCode::Escape) { self.open = false }
+		if !self.open { return }
+		let (entity, file_name) = match (&twactives, &twimages, &*entities).join().next() {
+			Some((_, twimage, entity)) => (entity, twimage.file_name.clone()),
+			None => return
+		};
+		let header = self.headers.entry(file_name.clone())
+			.or_insert_with(|| read_texture_header(Path::new(&file_name)).ok());
+		let header = match header {
+			Some(header) => header,
+			None => return
+		};
+		let (mut face, mut level) = self.surfaces.get(&entity).cloned().unwrap_or((0, 0));
+		let mut changed = false;
+		amethyst_imgui::with(|ui| {
+			imgui::Window::new(im_str!("Texture"))
+				.size([UI_WIDTH, 0.0], Condition::Always)
+				.position([ui.io().mouse_pos[0] - UI_WIDTH * 0.5, ui.io().mouse_pos[1]], Condition::Appearing)
+				.build(ui, || {
+					ui.text(format!("{:?}{}", header.format, if header.srgb { " sRGB" } else { "" }));
+					let (width, height) = header.level_size(level);
+					ui.text(format!("{} x {}", width, height));
+					if header.levels > 1 {
+						changed |= imgui::Slider::new(im_str!("Mip level"), 0..=header.levels - 1)
+							.build(ui, &mut level);
+					}
+					if header.faces > 1 {
+						changed |= imgui::Slider::new(im_str!("Face"), 0..=header.faces - 1)
+							.build(ui, &mut face);
+						if header.faces == 6 {
+							ui.same_line(0.0);
+							ui.text(CUBE_FACES[face as usize]);
+						}
+					}
+				});
+		});
+		if !changed { return }
+		self.surfaces.insert(entity, (face, level));
+		let loaded = Arc::clone(&self.loaded);
+		thread::spawn(move || {
+			match read_texture_file(Path::new(&file_name)).and_then(|texture| texture.surface_pixels(face, level)) {
+				Ok(tw_pixels) => {
+					debug!("Face {:?} mip level {:?} of {:?} is loaded", face, level, &file_name);
+					loaded.lock().unwrap().push((entity, (face, level), tw_pixels));
+				}
+				Err(e) => error!("Failed to read face {:?} mip level {:?} of {:?}: {:?}", face, level, &file_name, e)
+			}
+		});
+	}
+}
+
+
+#[derive(Default)]
+pub struct FramesSystem {
+	pub open: bool,
+	// end timestamps of the frames of the files, None when it's not an animation
+	timestamps: HashMap<String, Option<Vec<u32>>>,
+	// frame shown by each image
+	frames: HashMap<Entity, usize>,
+	loaded: Arc<Mutex<Vec<(Entity, usize, TwPixels)>>>,
+}
+/// generate a window to browse the frames of the active image when it's an animated webp. The
+/// frames are listed from the file headers, the chosen frame is decoded in a new thread and
+/// replaces the texture of the image.
+/// Window is opened with G key and keep open while escape key is not pushed
+impl<'s> amethyst::ecs::System<'s> for FramesSystem {
+	type SystemData = (WriteExpect<'s, TwInputsHandler>,
+					   Read<'s, TowerData>,
+					   ReadStorage<'s, TwActiveUiComponent>,
+					   ReadStorage<'s, TwImage>,
+					   ReadStorage<'s, SpriteRender>,
+					   Write<'s, AssetStorage<SpriteSheet>>,
+					   Read<'s, AssetStorage<Texture>>,
+					   ReadExpect<'s, Loader>,
+					   Entities<'s>);
+	fn run(&mut self, (
+			mut tw_in,
+			tw_data,
+			twactives,
+			twimages,
+			sprites,
+			mut sprite_sheets,
+			texture_storage,
+			loader,
+			entities,
+	) : Self::SystemData) {
+		// frames decoded by the threads
+		if let Ok(mut loaded) = self.loaded.try_lock() {
+			for (entity, frame, tw_pixels) in loaded.drain(..) {
+				if !entities.is_alive(entity) { continue }
+				// an older frame decoded slower than the one chosen after it
+				if self.frames.get(&entity) != Some(&frame) { continue }
+				if let (Some(twimage), Some(sprite)) = (twimages.get(entity), sprites.get(entity)) {
+					let texture_data = tw_pixels.texture_data(twimage.filter.unwrap_or(tw_data.filter));
+					let texture = loader.load_from_data(texture_data, (), &texture_storage);
+					if let Some(sprite_sheet) = sprite_sheets.get_mut(&sprite.sprite_sheet) {
+						sprite_sheet.texture = texture;
+					}
+				}
+			}
+		}
+		self.frames.retain(|entity, _| entities.is_alive(*entity));
+		if tw_in.keys_pressed.contains(&VirtualKeyCode::G) && tw_in.keys_pressed.len() == 1 {
+			if Duration::from_millis(500) <= tw_in.stopwatch.elapsed() {
+				self.open = true;
+				tw_in.stopwatch.restart();
+			}
+		}
+		if tw_in.keys_pressed.contains(&VirtualKeyCode::Escape) { self.open = false }
+		if !self.open { return }
+		let (entity, file_name) = match (&twactives, &twimages, &*entities).join().next() {
+			Some((_, twimage, entity)) => (entity, twimage.file_name.clone()),
+			None => return
+		};
+		let timestamps = self.timestamps.entry(file_name.clone()).or_insert_with(|| {
+			match webp_frame_timestamps(Path::new(&file_name)) {
+				Ok(timestamps) if timestamps.len() > 1 => Some(timestamps),
+				_ => None
+			}
+		});
+		let timestamps = match timestamps {
+			Some(timestamps) => timestamps,
+			None => return
+		};
+		let mut frame = self.frames.get(&entity).cloned().unwrap_or(0);
+		let mut changed = false;
+		amethyst_imgui::with(|ui| {
+			imgui::Window::new(im_str!("Frames"))
+				.size([UI_WIDTH, 0.0], Condition::Always)
+				.position([ui.io().mouse_pos[0] - UI_WIDTH * 0.5, ui.io().mouse_pos[1]], Condition::Appearing)
+				.build(ui, || {
+					let start = if frame == 0 { 0 } else { timestamps[frame - 1] };
+					ui.text(format!("{} frames, {} ms", timestamps.len(), timestamps[timestamps.len() - 1]));
+					ui.text(format!("{} ms to {} ms", start, timestamps[frame]));
+					let mut shown = frame as u32 + 1;
+					changed |= imgui::Slider::new(im_str!("Frame"), 1..=timestamps.len() as u32)
+						.build(ui, &mut shown);
+					frame = shown as usize - 1;
+				});
+		});
+		if !changed { return }
+		self.frames.insert(entity, frame);
+		let loaded = Arc::clone(&self.loaded);
+		thread::spawn(move || {
+			match decode_webp(Path::new(&file_name), frame) {
+				Ok(buffer) => {
+					debug!("Frame {:?} of {:?} is decoded", frame, &file_name);
+					loaded.lock().unwrap().push((entity, frame, TwPixels::from_buffer(buffer).with_mipmaps()));
+				}
+				Err(e) => error!("Failed to decode the frame {:?} of {:?}: {:?}", frame, &file_name, e)
+			}
+		});
+	}
+}
+
+
+#[derive(Default)]
+pub struct LayersSystem {
+	pub open: bool,
+	// layers of the files, None when the format has no layer
+	layers: HashMap<String, Option<Vec<TwSubImage>>>,
+	// visibility of the layers of each image
+	visibility: HashMap<Entity, Vec<bool>>,
+	loaded: Arc<Mutex<Vec<(Entity, TwPixels)>>>,
+}
+/// generate a window listing the layers of the active image, like the layers of a psd.
+/// A checkbox show or hide a layer, the visible layers are composed in a new thread and replace
+/// the texture of the image. The Pull out button add the layer alone on the board, next to the image.
+/// Window is opened with shift + L and keep open while escape key is not pushed
+impl<'s> amethyst::ecs::System<'s> for LayersSystem {
+	type SystemData = (WriteExpect<'s, TwInputsHandler>,
+					   Read<'s, TowerData>,
+					   ReadStorage<'s, TwActiveUiComponent>,
+					   ReadStorage<'s, TwImage>,
+					   ReadStorage<'s, Transform>,
+					   ReadStorage<'s, SpriteRender>,
+					   Write<'s, AssetStorage<SpriteSheet>>,
+					   Read<'s, AssetStorage<Texture>>,
+					   ReadExpect<'s, Loader>,
+					   Write<'s, LazyUpdate>,
+					   Entities<'s>);
+	fn run(&mut self, (
+			mut tw_in,
+			tw_data,
+			twactives,
+			twimages,
+			transforms,
+			sprites,
+			mut sprite_sheets,
+			texture_storage,
+			loader,
+			world,
+			entities,
+	) : Self::SystemData) {
+		// compositions done by the threads
+		if let Ok(mut loaded) = self.loaded.try_lock() {
+			for (entity, tw_pixels) in loaded.drain(..) {
+				if !entities.is_alive(entity) { continue }
+				if let (Some(twimage), Some(sprite)) = (twimages.get(entity), sprites.get(entity)) {
+					let texture_data = tw_pixels.texture_data(twimage.filter.unwrap_or(tw_data.filter));
+					let texture = loader.load_from_data(texture_data, (), &texture_storage);
+					if let Some(sprite_sheet) = sprite_sheets.get_mut(&sprite.sprite_sheet) {
+						sprite_sheet.texture = texture;
+					}
+				}
+			}
+		}
+		if tw_in.keys_pressed.contains(&VirtualKeyCode::L) && tw_in.keys_pressed.contains(&VirtualKeyCode::LShift) && tw_in.keys_pressed.len() == 2 {
+			if Duration::from_millis(500) <= tw_in.stopwatch.elapsed() {
+				self.open = true;
+				tw_in.stopwatch.restart();
+			}
+		}
+		if tw_in.keys_pressed.contains(&VirtualKeyCode::Escape) { self.open = false }
+		if !self.open { return }
+		let (entity, twimage) = match (&twactives, &twimages, &*entities).join().next() {
+			Some((_, twimage, entity)) => (entity, twimage.clone()),
+			None => return
+		};
+		let file_name = twimage.file_name.clone();
+		let layers = self.layers.entry(file_name.clone()).or_insert_with(|| {
+			match list_sub_images(Path::new(&file_name)) {
+				Ok(layers) if !layers.is_empty() => Some(layers),
+				_ => None
+			}
+		});
+		let layers = match layers {
+			Some(layers) => layers,
+			None => return
+		};
+		let visibility = self.visibility.entry(entity)
+			.or_insert_with(|| layers.iter().map(|layer| layer.visible).collect());
+		let mut changed = false;
+		let mut pulled = None;
+		amethyst_imgui::with(|ui| {
+			imgui::Window::new(im_str!("Layers"))
+				.size([UI_WIDTH, 0.0], Condition::Always)
+				.position([ui.io().mouse_pos[0] - UI_WIDTH * 0.5, ui.io().mouse_pos[1]], Condition::Appearing)
+				.build(ui, || {
+					// the top layer first, like in the painting softwares
+					for (index, layer) in layers.iter().enumerate().rev() {
+						let id = ui.push_id(index as i32);
+						changed |= ui.checkbox(&ImString::new(layer.name.clone()), &mut visibility[index]);
+						ui.same_line(UI_WIDTH - 80.0);
+						if ui.small_button(im_str!("Pull out")) { pulled = Some(index) }
+						id.pop(ui);
+					}
+				});
+		});
+		if let Some(index) = pulled {
+			if let Some(transform) = transforms.get(entity) {
+				// TODO: offset as settings
+				let mut position = Transform::default();
+				position.set_translation_xyz(transform.translation().x + twimage.width as f32 * twimage.scale + 10.0,
+											 transform.translation().y, 0.0);
+				let path = sub_image_path(Path::new(&file_name), index);
+				world.create_entity(&*entities)
+					.with(position)
+					.with(TwPlaceHolder { from_next: false, to_cache: true, twimage_path: path.clone(), inherit: None })
+					.build();
+				debug!("TwPlaceHolder is created for the layer {:?}", &path);
+			}
+		}
+		if !changed { return }
+		let indices = visibility.iter().enumerate().filter(|(_, visible)| **visible).map(|(index, _)| index).collect::<Vec<_>>();
+		let loaded = Arc::clone(&self.loaded);
+		thread::spawn(move || {
+			match compose_sub_images(Path::new(&file_name), &indices) {
+				Ok(buffer) => {
+					debug!("Layers {:?} of {:?} are composed", &indices, &file_name);
+					loaded.lock().unwrap().push((entity, TwPixels::from_buffer(buffer).with_mipmaps()));
+				}
+				Err(e) => error!("Failed to compose the layers of {:?}: {:?}", &file_name, e)
+			}
+		});
+	}
+}
+
+
+#[derive(Default)]
+pub struct FilmstripSystem {
+	pub open: bool,
+	textures: HashMap<String, TextureId>,
+	dragging: Option<String>,
+	filter_text: ImString,
+	filter_regex: bool,
+}
+/// generate a strip at the bottom of the window with the thumbnails of every file of
+/// TowerData.files_order, the file of the ui active image is highlighted.
+/// Click on a thumbnail swap the ui active image with this file, drag a thumbnail onto the board
+/// add it as a new TwPlaceHolder under the mouse.
+/// The order and the filter of the files are changed from the strip header, O key cycle the sort
+/// and shift + O reverse it.
+/// Strip is shown or hidden with tab key.
+impl<'s> amethyst::ecs::System<'s> for FilmstripSystem {
+	type SystemData = (WriteExpect<'s, TwInputsHandler>,
+					   Write<'s, TowerData>,
+					   ReadStorage<'s, TwActiveUiComponent>,
+					   ReadStorage<'s, TwImage>,
+					   ReadExpect<'s, Loader>,
+					   Read<'s, AssetStorage<Texture>>,
+					   WriteExpect<'s, ImguiState>,
+					   Write<'s, LazyUpdate>,
+					   Entities<'s>);
+	fn run(&mut self, (
+			mut tw_in,
+			mut tw_data,
+			twactives,
+			twimages,
+			loader,
+			texture_storage,
+			mut imgui_state,
+			world,
+			entities,
+	) : Self::SystemData) {
+		if tw_in.keys_pressed.contains(&VirtualKeyCode::Tab) && tw_in.keys_pressed.len() == 1 {
+			if Duration::from_millis(500) <= tw_in.stopwatch.elapsed() {
+				self.open = !self.open;
+				tw_in.stopwatch.restart();
+			}
+		}
+		let mut list_options = tw_data.list_options.clone();
+		let mut order_changed = false;
+		if tw_in.keys_pressed.contains(&VirtualKeyCode::O) && Duration::from_millis(500) <= tw_in.stopwatch.elapsed() {
+			if tw_in.keys_pressed.contains(&VirtualKeyCode::LShift) && tw_in.keys_pressed.len() == 2 {
+				list_options.reverse = !list_options.reverse;
+				order_changed = true;
+				tw_in.stopwatch.restart();
+			} else if tw_in.keys_pressed.len() == 1 {
+				list_options.sort_by = list_options.sort_by.next();
+				order_changed = true;
+				tw_in.stopwatch.restart();
+			}
+		}
+		if self.open {
+			let filter_text = &mut self.filter_text;
+			let filter_regex = &mut self.filter_regex;
+			let mut filter_changed = false;
+			let changed = &mut order_changed;
+			amethyst_imgui::with(|ui| {
+				let display_size = ui.io().display_size;
+				let strip_height = THUMBNAILSIZE as f32 + 40.0;
+				imgui::Window::new(im_str!("Files order"))
+					.title_bar(false)
+					.resizable(false)
+					.movable(false)
+					.always_auto_resize(true)
+					.position([0.0, display_size[1] - strip_height], Condition::Always)
+					.position_pivot([0.0, 1.0])
+					.build(ui, || {
+						*changed |= ui.radio_button(im_str!("Name"), &mut list_options.sort_by, TwSortBy::Name);
+						ui.same_line(0.0);
+						*changed |= ui.radio_button(im_str!("Modified"), &mut list_options.sort_by, TwSortBy::Modified);
+						ui.same_line(0.0);
+						*changed |= ui.radio_button(im_str!("Size"), &mut list_options.sort_by, TwSortBy::Size);
+						ui.same_line(0.0);
+						*changed |= ui.radio_button(im_str!("Dimensions"), &mut list_options.sort_by, TwSortBy::Dimensions);
+						ui.same_line(0.0);
+						*changed |= ui.checkbox(im_str!("Reverse"), &mut list_options.reverse);
+						ui.same_line(0.0);
+						if filter_text.capacity_with_nul() < 256 { filter_text.reserve(256) }
+						filter_changed |= ui.input_text(im_str!("Filter"), filter_text).build();
+						ui.same_line(0.0);
+						filter_changed |= ui.checkbox(im_str!("Regex"), filter_regex);
+					});
+			});
+			if filter_changed {
+				let pattern = self.filter_text.to_str();
+				let filter = if pattern.is_empty() {
+					Ok(None)
+				} else if self.filter_regex {
+					TwFileFilter::regex(pattern).map(Some)
+				} else {
+					TwFileFilter::glob(pattern).map(Some)
+				};
+				match filter {
+					Ok(filter) => {
+						list_options.filter = filter;
+						order_changed = true;
+					}
+					// keep the previous filter while the pattern is typed
+					Err(e) => debug!("Invalid files filter {:?}: {:?}", pattern, e)
+				}
+			}
+		}
+		if order_changed {
+			tw_data.files_order = list_working_set(&tw_data.working_dirs, &list_options);
+			tw_data.list_options = list_options;
+			info!("Files are sorted by {:?}, reverse {:?}, filter {:?}", tw_data.list_options.sort_by,
+				  tw_data.list_options.reverse, tw_data.list_options.filter);
+		}
+		if !self.open { return }
+		// upload the new thumbnails as imgui textures
+		if let Ok(thumbnails) = tw_data.thumbnails.try_lock() {
+			for (path, (_, pixels)) in thumbnails.iter() {
+				if !self.textures.contains_key(path) {
+					let handle = loader.load_from_data(pixels.texture_data(TwFilter::Nearest), (), &texture_storage);
+					imgui_state.textures.push(handle);
+					self.textures.insert(path.clone(), TextureId::from(imgui_state.textures.len() - 1));
+				}
+			}
+		}
+		let active = (&twactives, &twimages, &*entities).join().next().map(|(_, twimage, entity)| (entity, twimage.clone()));
+		let files = tw_data.files_order.iter().map(|f| f.to_str().unwrap().to_owned()).collect::<Vec<_>>();
+		let textures = &self.textures;
+		let dragging = &mut self.dragging;
+		let mut clicked = None;
+		let mut dropped = None;
+		amethyst_imgui::with(|ui| {
+			let display_size = ui.io().display_size;
+			let strip_height = THUMBNAILSIZE as f32 + 40.0;
+			imgui::Window::new(im_str!("Files"))
+				.title_bar(false)
+				.resizable(false)
+				.movable(false)
+				.horizontal_scrollbar(true)
+				.size([display_size[0], strip_height], Condition::Always)
+				.position([0.0, display_size[1] - strip_height], Condition::Always)
+				.build(ui, || {
+					for file in &files {
+						match textures.get(file) {
+							Some(texture_id) => {
+								let current = active.as_ref().map_or(false, |(_, twimage)| &twimage.file_name == file);
+								let button = imgui::ImageButton::new(*texture_id, [THUMBNAILSIZE as f32, THUMBNAILSIZE as f32])
+									.frame_padding(2)
+									.background_col(if current { [1.0, 0.8, 0.0, 1.0] } else { [0.0, 0.0, 0.0, 0.0] });
+								if button.build(ui) { clicked = Some(file.clone()) }
+							}
+							// thumbnail not generated yet
+							None => {
+								let name = ImString::new(Path::new(file).file_name().unwrap().to_str().unwrap());
+								if ui.button(&name, [THUMBNAILSIZE as f32, THUMBNAILSIZE as f32]) { clicked = Some(file.clone()) }
+							}
+						}
+						if ui.is_item_hovered() {
+							ui.tooltip_text(file);
+						}
+						if ui.is_item_active() && ui.is_mouse_dragging(imgui::MouseButton::Left) {
+							*dragging = Some(file.clone());
+						}
+						ui.same_line(0.0);
+					}
+				});
+			if dragging.is_some() && !ui.is_mouse_down(imgui::MouseButton::Left) {
+				// released outside the strip, on the board
+				if !ui.io().want_capture_mouse {
+					dropped = dragging.clone();
+				}
+				*dragging = None;
+				clicked = None;
+			}
+		});
+		if let Some(file) = clicked {
+			if let Some((entity, twimage)) = &active {
+				if &twimage.file_name != &file {
+					debug!("Files strip swap the ui active TwImage with {:?}", &file);
+					world.insert(*entity, TwPlaceHolder { from_next: true, to_cache: true, twimage_path: file.clone(), inherit: Some(twimage.clone()) });
+					tw_data.file_to_cache.push(OsString::from(&file));
+				}
+			}
+		}
+		if let (Some(file), Some(world_position)) = (dropped, tw_in.mouse_world_position) {
+			let mut position = Transform::default();
+			position.set_translation_xyz(world_position.0, world_position.1, 0.0);
+			world.create_entity(&*entities)
+				.with(position)
+				.with(TwPlaceHolder { from_next: false, to_cache: true, twimage_path: file.clone(), inherit: None })
+				.build();
+			tw_data.file_to_cache.push(OsString::from(&file));
+			debug!("TwPlaceHolder is created from files strip for path {:?}", &file);
+		}
+	}
+}
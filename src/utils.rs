@@ -1,35 +1,193 @@
 use std::path::Path;
 use std::ffi::{OsStr, OsString};
 use std::fs;
+use std::cmp::Ordering;
+use std::str::FromStr;
 
+use crate::decoder::{decoder_for_extension, probe_dimensions};
 
-//static IMAGE_FORMATS: &'static [&str; 15] = &["bmp", "dxt", "flat", "gif", "hdr", "ico", "imageops",
-//                                             "io", "jpeg", "jpg", "math", "png", "pnm", "tga",
-//                                             "tiff"];
 
-static IMAGE_FORMATS: &'static [&str; 8] = &["bmp", "gif", "jpeg", "jpg", "png", "tga", "tiff", "tif"];
+/// true if a decoder reads the file extension, the content is not checked to keep the listing of
+/// the directories fast.
+pub fn is_valid_file(file: &Path) -> bool {
+    decoder_for_extension(file).is_some()
+}
 
 
-pub fn is_valid_file(file: &Path) -> bool {
-   file.extension().map_or(false, |ext| {
-        IMAGE_FORMATS.iter().any(|f| *f == &ext.to_str().unwrap().to_lowercase())
-    })
+/// order of the files of the working directory.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum TwSortBy {
+    Name,
+    Modified,
+    Size,
+    Dimensions,
+}
+
+impl Default for TwSortBy {
+    fn default() -> Self {
+        TwSortBy::Name
+    }
 }
 
+impl FromStr for TwSortBy {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "name" => Ok(TwSortBy::Name),
+            "mtime" | "modified" => Ok(TwSortBy::Modified),
+            "size" => Ok(TwSortBy::Size),
+            "dimensions" => Ok(TwSortBy::Dimensions),
+            _ => Err(format!("Invalid sort {:?}, expected name, mtime, size or dimensions", s))
+        }
+    }
+}
 
-pub fn list_valid_files(dir: &OsStr) -> Vec<OsString> {
-    let mut valid_paths = Vec::new();
-    let paths = fs::read_dir(dir).unwrap();
-    for path in paths {
-        let p = path.unwrap();
-        if is_valid_file(p.path().as_path()) && p.path().as_path().is_file() {
-            valid_paths.push(p.path().as_os_str().to_owned())
+impl TwSortBy {
+    pub fn next(self) -> Self {
+        match self {
+            TwSortBy::Name => TwSortBy::Modified,
+            TwSortBy::Modified => TwSortBy::Size,
+            TwSortBy::Size => TwSortBy::Dimensions,
+            TwSortBy::Dimensions => TwSortBy::Name,
+        }
+    }
+}
+
+
+/// filter on the file names of the working directory.
+#[derive(Debug, Clone)]
+pub enum TwFileFilter {
+    Glob(glob::Pattern),
+    Regex(regex::Regex),
+}
+
+impl TwFileFilter {
+    pub fn glob(pattern: &str) -> Result<Self, String> {
+        glob::Pattern::new(pattern).map(TwFileFilter::Glob).map_err(|e| e.to_string())
+    }
+
+    pub fn regex(pattern: &str) -> Result<Self, String> {
+        regex::Regex::new(pattern).map(TwFileFilter::Regex).map_err(|e| e.to_string())
+    }
+
+    pub fn matches(&self, file_name: &str) -> bool {
+        match self {
+            TwFileFilter::Glob(pattern) => pattern.matches(file_name),
+            TwFileFilter::Regex(regex) => regex.is_match(file_name),
+        }
+    }
+}
+
+
+/// how the working directories are listed, from the CLI flags then changed with the files strip.
+/// depth is the count of sub directory levels listed, 0 list only the directory itself.
+#[derive(Debug, Clone, Default)]
+pub struct TwListOptions {
+    pub sort_by: TwSortBy,
+    pub reverse: bool,
+    pub filter: Option<TwFileFilter>,
+    pub depth: usize,
+}
+
+
+/// compare two strings as human do, digits are compared as numbers so img2 is before img10.
+/// Letters are compared without case.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        match (a.peek().cloned(), b.peek().cloned()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(ca), Some(cb)) if ca.is_ascii_digit() && cb.is_ascii_digit() => {
+                let mut digits_a = String::new();
+                while let Some(c) = a.peek().cloned().filter(|c| c.is_ascii_digit()) { digits_a.push(c); a.next(); }
+                let mut digits_b = String::new();
+                while let Some(c) = b.peek().cloned().filter(|c| c.is_ascii_digit()) { digits_b.push(c); b.next(); }
+                let (trimmed_a, trimmed_b) = (digits_a.trim_start_matches('0'), digits_b.trim_start_matches('0'));
+                let ordering = trimmed_a.len().cmp(&trimmed_b.len())
+                    .then_with(|| trimmed_a.cmp(trimmed_b))
+                    .then_with(|| digits_a.len().cmp(&digits_b.len()));
+                if ordering != Ordering::Equal { return ordering }
+            }
+            (Some(ca), Some(cb)) => {
+                let ordering = ca.to_lowercase().cmp(cb.to_lowercase());
+                if ordering != Ordering::Equal { return ordering }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+
+/// sort files with the list options, the natural order of the names break the ties.
+pub fn sort_files(files: &mut Vec<OsString>, options: &TwListOptions) {
+    // the whole path is compared, the files of a sub directory stay together
+    files.sort_by(|a, b| natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()));
+    // sort_by_cached_key is stable, the natural order stay inside the equal keys
+    match options.sort_by {
+        TwSortBy::Name => {}
+        TwSortBy::Modified => files.sort_by_cached_key(|f| fs::metadata(f).and_then(|m| m.modified()).ok()),
+        TwSortBy::Size => files.sort_by_cached_key(|f| fs::metadata(f).map(|m| m.len()).ok()),
+        TwSortBy::Dimensions => files.sort_by_cached_key(|f| {
+            probe_dimensions(Path::new(f)).ok().map(|(width, height)| width as u64 * height as u64)
+        }),
+    }
+    if options.reverse { files.reverse() }
+}
+
+
+/// push the valid image files of a directory, then of its sub directories until the depth.
+/// The symbolic links to directories are not followed, a link to a parent would loop until the
+/// depth, the links to files are listed.
+fn collect_valid_files(dir: &Path, depth: usize, options: &TwListOptions, valid_paths: &mut Vec<OsString>) {
+    let paths = match fs::read_dir(dir) {
+        Ok(paths) => paths,
+        Err(e) => {
+            warn!("Failed to list the directory {:?}: {:?}", dir, e);
+            return
         }
+    };
+    for entry in paths {
+        let (path, file_type) = match entry.and_then(|entry| Ok((entry.path(), entry.file_type()?))) {
+            Ok(entry) => entry,
+            Err(e) => {
+                warn!("Failed to read an entry of the directory {:?}: {:?}", dir, e);
+                continue
+            }
+        };
+        if file_type.is_dir() {
+            if depth > 0 { collect_valid_files(&path, depth - 1, options, valid_paths) }
+        } else if is_valid_file(&path) && (file_type.is_file() || path.is_file()) {
+            let file_name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+            if options.filter.as_ref().map_or(true, |filter| filter.matches(&file_name)) {
+                valid_paths.push(path.into_os_string())
+            }
+        }
+    }
+}
+
+
+/// list the valid image files of several directories, filtered and sorted with the list options.
+pub fn list_working_set(dirs: &[OsString], options: &TwListOptions) -> Vec<OsString> {
+    let mut valid_paths = Vec::new();
+    for dir in dirs {
+        collect_valid_files(Path::new(dir), options.depth, options, &mut valid_paths);
     }
+    sort_files(&mut valid_paths, options);
+    // nested directories of the working set list the same files, they are side by side once sorted
+    valid_paths.dedup();
     valid_paths
 }
 
 
+/// list the valid image files of a directory, filtered and sorted with the list options.
+pub fn list_valid_files(dir: &OsStr, options: &TwListOptions) -> Vec<OsString> {
+    list_working_set(&[dir.to_owned()], options)
+}
+
+
 pub fn premultiply_by_alpha(pixels: &Vec<u8>) -> Vec<u8> {
     let mut pixels_mult = Vec::new();
     for (mut i, _pixel) in pixels.iter().enumerate() {
@@ -63,3 +221,116 @@ pub fn add_alpha_channel(pixels: &Vec<u8>) -> Vec<u8> {
     pixels_alpha
 }
 
+
+
+/// parse an hexadecimal color, RRGGBB or RRGGBBAA with an optional #, as rgba values between 0 and 1.
+pub fn parse_hex_color(hex: &str) -> Result<[f32; 4], String> {
+    let hex = hex.trim_start_matches('#');
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("Invalid color {:?}, expected RRGGBB or RRGGBBAA", hex))
+    }
+    let mut color = [1.0; 4];
+    for (i, c) in color.iter_mut().enumerate().take(hex.len() / 2) {
+        *c = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap() as f32 / 255.0;
+    }
+    Ok(color)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // img1.png img2.png img10.png notes.txt sub/img3.png sub/deeper/img4.png
+    fn fixture() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("sub").join("deeper")).unwrap();
+        for file in &["img1.png", "img2.png", "img10.png", "notes.txt", "sub/img3.png", "sub/deeper/img4.png"] {
+            fs::write(dir.path().join(file), b"").unwrap();
+        }
+        dir
+    }
+
+    fn names(files: &[OsString]) -> Vec<String> {
+        files.iter()
+            .map(|f| PathBuf::from(f).file_name().unwrap().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn natural_order() {
+        assert_eq!(natural_cmp("img2", "img10"), Ordering::Less);
+        assert_eq!(natural_cmp("img10", "img9"), Ordering::Greater);
+        assert_eq!(natural_cmp("IMG2", "img3"), Ordering::Less);
+        assert_eq!(natural_cmp("img01", "img1"), Ordering::Greater);
+        assert_eq!(natural_cmp("img", "img1"), Ordering::Less);
+        assert_eq!(natural_cmp("shot_2_b", "shot_2_a"), Ordering::Greater);
+    }
+
+    #[test]
+    fn list_in_natural_order() {
+        let dir = fixture();
+        let files = list_valid_files(dir.path().as_os_str(), &TwListOptions::default());
+        assert_eq!(names(&files), vec!["img1.png", "img2.png", "img10.png"]);
+    }
+
+    #[test]
+    fn list_reversed() {
+        let dir = fixture();
+        let options = TwListOptions { reverse: true, ..TwListOptions::default() };
+        let files = list_valid_files(dir.path().as_os_str(), &options);
+        assert_eq!(names(&files), vec!["img10.png", "img2.png", "img1.png"]);
+    }
+
+    #[test]
+    fn list_with_glob_filter() {
+        let dir = fixture();
+        let options = TwListOptions { filter: Some(TwFileFilter::glob("img1*").unwrap()), ..TwListOptions::default() };
+        let files = list_valid_files(dir.path().as_os_str(), &options);
+        assert_eq!(names(&files), vec!["img1.png", "img10.png"]);
+        assert!(TwFileFilter::glob("[").is_err());
+    }
+
+    #[test]
+    fn list_with_regex_filter() {
+        let dir = fixture();
+        let options = TwListOptions { filter: Some(TwFileFilter::regex(r"^img\d\.").unwrap()), ..TwListOptions::default() };
+        let files = list_valid_files(dir.path().as_os_str(), &options);
+        assert_eq!(names(&files), vec!["img1.png", "img2.png"]);
+        assert!(TwFileFilter::regex("(").is_err());
+    }
+
+    #[test]
+    fn list_with_depth() {
+        let dir = fixture();
+        let depth = |depth| {
+            let options = TwListOptions { depth, ..TwListOptions::default() };
+            names(&list_valid_files(dir.path().as_os_str(), &options))
+        };
+        assert_eq!(depth(0), vec!["img1.png", "img2.png", "img10.png"]);
+        // the whole path is sorted, the sub directory comes after the files named img
+        assert_eq!(depth(1), vec!["img1.png", "img2.png", "img10.png", "img3.png"]);
+        assert_eq!(depth(2), vec!["img1.png", "img2.png", "img10.png", "img4.png", "img3.png"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn list_without_following_directory_links() {
+        let dir = fixture();
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("sub").join("loop")).unwrap();
+        std::os::unix::fs::symlink(dir.path().join("img1.png"), dir.path().join("sub").join("link.png")).unwrap();
+        let options = TwListOptions { depth: std::usize::MAX, ..TwListOptions::default() };
+        let files = list_valid_files(dir.path().as_os_str(), &options);
+        assert_eq!(names(&files), vec!["img1.png", "img2.png", "img10.png", "img4.png", "img3.png", "link.png"]);
+    }
+
+    #[test]
+    fn working_set_without_duplicates() {
+        let dir = fixture();
+        let dirs = vec![dir.path().as_os_str().to_owned(), dir.path().join("sub").into_os_string()];
+        let options = TwListOptions { depth: 1, ..TwListOptions::default() };
+        let files = list_working_set(&dirs, &options);
+        assert_eq!(names(&files), vec!["img1.png", "img2.png", "img10.png", "img4.png", "img3.png"]);
+    }
+}
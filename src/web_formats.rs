@@ -0,0 +1,161 @@
+/// web_formats.rs contains the readers of the formats of the web, WebP still and animated, and
+/// AVIF up to 12bit. The frames of an animated WebP are browsed one at a time, see FramesSystem.
+use avif_decode::Image as AvifImage;
+
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::decoder::{TwPixelBuffer, TwSamples, invalid_data};
+
+
+/// canvas size from the first chunk of a webp file, VP8X, VP8 lossy or VP8L lossless.
+pub fn webp_size(path: &Path) -> io::Result<(u32, u32)> {
+    let mut header = [0; 30];
+    File::open(path)?.read_exact(&mut header)?;
+    if &header[0..4] != b"RIFF" || &header[8..12] != b"WEBP" {
+        return Err(invalid_data("Not a webp file"))
+    }
+    let u24 = |i: usize| header[i] as u32 | (header[i + 1] as u32) << 8 | (header[i + 2] as u32) << 16;
+    match &header[12..16] {
+        b"VP8X" => Ok((u24(24) + 1, u24(27) + 1)),
+        b"VP8 " => Ok(((u16::from_le_bytes([header[26], header[27]]) & 0x3fff) as u32,
+                       (u16::from_le_bytes([header[28], header[29]]) & 0x3fff) as u32)),
+        b"VP8L" => {
+            let bits = u32::from_le_bytes([header[21], header[22], header[23], header[24]]);
+            Ok(((bits & 0x3fff) + 1, ((bits >> 14) & 0x3fff) + 1))
+        }
+        _ => Err(invalid_data("Unknown webp chunk"))
+    }
+}
+
+
+/// end timestamps in milliseconds of the frames of an animated webp, read from the ANMF chunk
+/// headers without decoding the frames. A still image has none.
+pub fn webp_frame_timestamps(path: &Path) -> io::Result<Vec<u32>> {
+    let mut file = File::open(path)?;
+    let mut header = [0; 12];
+    file.read_exact(&mut header)?;
+    if &header[0..4] != b"RIFF" || &header[8..12] != b"WEBP" {
+        return Err(invalid_data("Not a webp file"))
+    }
+    let mut timestamps = Vec::new();
+    let mut end = 0;
+    let mut chunk = [0; 8];
+    // each chunk is its fourcc and its size, the data are padded to an even size
+    while file.read_exact(&mut chunk).is_ok() {
+        let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as i64;
+        let mut skipped = size + size % 2;
+        if &chunk[0..4] == b"ANMF" {
+            // the frame position and size come before its duration
+            let mut frame = [0; 16];
+            file.read_exact(&mut frame)?;
+            end += frame[12] as u32 | (frame[13] as u32) << 8 | (frame[14] as u32) << 16;
+            timestamps.push(end);
+            skipped -= 16;
+        }
+        file.seek(SeekFrom::Current(skipped))?;
+    }
+    Ok(timestamps)
+}
+
+
+/// decode a frame of a webp file, the first one for a still image. The frames are whole canvas
+/// with straight alpha, the previous frames are decoded to build it but they are not kept.
+pub fn decode_webp(path: &Path, frame: usize) -> io::Result<TwPixelBuffer> {
+    let data = fs::read(path)?;
+    let decoder = webp_animation::Decoder::new(&data).map_err(|e| invalid_data(format!("{:?}", e)))?;
+    let (width, height) = decoder.dimensions();
+    let rgba = decoder.into_iter().nth(frame).map(|frame| frame.data().to_vec())
+        .ok_or_else(|| invalid_data(format!("No frame {} in {:?}", frame, path)))?;
+    Ok(TwPixelBuffer { width, height, channels: 4, samples: TwSamples::U8(rgba) })
+}
+
+
+/// dimensions from the ispe property of an avif file, the image spatial extents.
+pub fn avif_size(path: &Path) -> io::Result<(u32, u32)> {
+    let mut header = vec![0; 4096];
+    let read = File::open(path)?.read(&mut header)?;
+    header.truncate(read);
+    let position = header.windows(4).position(|w| w == b"ispe")
+        .ok_or_else(|| invalid_data("No ispe property in avif file"))?;
+    let u32_at = |i: usize| header.get(i..i + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]));
+    // the box type is followed by the version and flags
+    match (u32_at(position + 8), u32_at(position + 12)) {
+        (Some(width), Some(height)) => Ok((width, height)),
+        _ => Err(invalid_data("Truncated avif header"))
+    }
+}
+
+
+/// decode an avif file, the high bit depth images keep 16bit samples.
+pub fn decode_avif(path: &Path) -> io::Result<TwPixelBuffer> {
+    let data = fs::read(path)?;
+    let decoder = avif_decode::Decoder::from_avif(&data).map_err(|e| invalid_data(format!("{:?}", e)))?;
+    let image = decoder.to_image().map_err(|e| invalid_data(format!("{:?}", e)))?;
+    let buffer = match image {
+        AvifImage::Rgb8(img) => TwPixelBuffer { width: img.width() as u32, height: img.height() as u32, channels: 3,
+            samples: TwSamples::U8(img.pixels().flat_map(|p| vec![p.r, p.g, p.b]).collect()) },
+        AvifImage::Rgba8(img) => TwPixelBuffer { width: img.width() as u32, height: img.height() as u32, channels: 4,
+            samples: TwSamples::U8(img.pixels().flat_map(|p| vec![p.r, p.g, p.b, p.a]).collect()) },
+        AvifImage::Gray8(img) => TwPixelBuffer { width: img.width() as u32, height: img.height() as u32, channels: 1,
+            samples: TwSamples::U8(img.pixels().map(|p| p.0).collect()) },
+        AvifImage::Rgb16(img) => TwPixelBuffer { width: img.width() as u32, height: img.height() as u32, channels: 3,
+            samples: TwSamples::U16(img.pixels().flat_map(|p| vec![p.r, p.g, p.b]).collect()) },
+        AvifImage::Rgba16(img) => TwPixelBuffer { width: img.width() as u32, height: img.height() as u32, channels: 4,
+            samples: TwSamples::U16(img.pixels().flat_map(|p| vec![p.r, p.g, p.b, p.a]).collect()) },
+        AvifImage::Gray16(img) => TwPixelBuffer { width: img.width() as u32, height: img.height() as u32, channels: 1,
+            samples: TwSamples::U16(img.pixels().map(|p| p.0).collect()) },
+    };
+    Ok(buffer)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the bytes of a webp file made of the given chunks, only the chunk headers are meaningful.
+    fn webp_data(chunks: &[(&[u8], Vec<u8>)]) -> Vec<u8> {
+        let mut body = b"WEBP".to_vec();
+        for (fourcc, data) in chunks {
+            body.extend_from_slice(fourcc);
+            body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            body.extend_from_slice(data);
+            if data.len() % 2 == 1 { body.push(0) }
+        }
+        let mut file = b"RIFF".to_vec();
+        file.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        file.extend(body);
+        file
+    }
+
+    /// an ANMF chunk, the frame is not a valid bitstream.
+    fn anmf(duration: u32, frame_bytes: usize) -> Vec<u8> {
+        let mut data = vec![0; 12];
+        data.extend_from_slice(&duration.to_le_bytes()[0..3]);
+        data.push(0);
+        data.extend(vec![0xff; frame_bytes]);
+        data
+    }
+
+    #[test]
+    fn frame_timestamps_of_an_animation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("anim.webp");
+        fs::write(&path, webp_data(&[(b"VP8X", vec![0x02; 10]), (b"ANIM", vec![0; 6]),
+                                     (b"ANMF", anmf(100, 33)), (b"ANMF", anmf(250, 8)), (b"ANMF", anmf(70_000, 0))])).unwrap();
+        assert_eq!(webp_frame_timestamps(&path).unwrap(), vec![100, 350, 70_350]);
+    }
+
+    #[test]
+    fn no_frame_timestamp_for_a_still_image() {
+        let dir = tempfile::tempdir().unwrap();
+        let still = dir.path().join("still.webp");
+        fs::write(&still, webp_data(&[(b"VP8L", vec![0x2f; 21])])).unwrap();
+        assert!(webp_frame_timestamps(&still).unwrap().is_empty());
+        let wave = dir.path().join("sound.webp");
+        fs::write(&wave, b"RIFF\0\0\0\0WAVE").unwrap();
+        assert!(webp_frame_timestamps(&wave).is_err());
+    }
+}